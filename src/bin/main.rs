@@ -5,101 +5,534 @@ mod ui;
 
 use crate::egui::Sense;
 use crate::ui::config_ui::ConfigView;
-use crate::ui::library::LibraryView;
+use crate::ui::library::{EnqueuePosition, LibraryAction, LibraryView};
+use crate::ui::play_queue::show_play_queue;
 use crate::ui::playback_controls::{PlaybackCommand, PlaybackControls};
 use crate::ui::playlist::{PlaylistAction, PlaylistView};
+use crate::ui::playlist_files::{PlaylistFileAction, PlaylistFilesView};
+use crate::ui::playlist_tabs::{PlaylistTabs, PlaylistTabsAction};
 use crate::ui::time_label;
 use anyhow::Result;
-use eframe::egui::{Ui, Visuals, Widget};
+use eframe::egui::{Key, Modifiers, Ui, Visuals, Widget};
 use eframe::{egui, App, Storage};
 use log::LevelFilter;
 use log::{info, warn};
 use simple_music_lib::config::Config;
 use simple_music_lib::image_cache::ImageCache;
-use simple_music_lib::library;
-use simple_music_lib::library::{Library, ListEntryId, Playlist, SongId};
+use simple_music_lib::library::background_scan::BackgroundScan;
+use simple_music_lib::library::cache;
+use simple_music_lib::library::play_queue::PlayQueue;
+use simple_music_lib::library::playlist_file::{self, PathStyle};
+use simple_music_lib::library::playlist_position::PlaylistPosition;
+use simple_music_lib::library::playlists::{PlaylistId, Playlists};
+use simple_music_lib::library::shuffle::ShuffleOrder;
+use simple_music_lib::library::watcher::{LibraryWatcher, DEBOUNCE_DURATION};
+use simple_music_lib::library::{
+    Library, ListEntryId, Playlist, RepeatMode, RescanSummary, SongId,
+};
 use simple_music_lib::playback::Playback;
 use simplelog::{ColorChoice, ConfigBuilder, TermLogger, TerminalMode};
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
+/// Key of the playlists in the app storage, next to the config.
+const PLAYLISTS_KEY: &str = "playlists";
+
 struct MusicApp {
     library: Library,
-    playlist: Playlist,
-    playlist_selected_song: Option<(ListEntryId, SongId)>,
+    playlists: Playlists,
+    /// The playlist whose tab is open.
+    shown_playlist: PlaylistId,
+    /// The playlist the current song is from. Next and previous go through this playlist,
+    /// also while another playlist is shown.
+    playing_playlist: PlaylistId,
+    /// The current song, in the playing playlist.
+    playlist_position: PlaylistPosition,
+    /// Order of the playing playlist when shuffling.
+    shuffle_order: ShuffleOrder,
+    /// Songs to play before continuing with the playlist.
+    play_queue: PlayQueue,
+    /// The song from the play queue that is playing, if any.
+    /// The playlist continues after [playlist_position](Self::playlist_position)
+    /// once the queue is empty.
+    queued_song: Option<SongId>,
+    playlist_tabs: PlaylistTabs,
     playlist_view: PlaylistView,
     library_view: LibraryView,
+    playlist_files_view: PlaylistFilesView,
     playback_controls: PlaybackControls,
     config: Config,
     config_view: ConfigView,
     playback: Playback,
     image_cache: ImageCache,
+    /// Where the library is cached between runs. `None` if there is no place to store it.
+    library_cache_path: Option<PathBuf>,
+    /// Whether the library changed in a way that is not yet in the cache, like play counts.
+    library_cache_dirty: bool,
+    /// Only present while the library roots are being scanned.
+    library_scan: Option<BackgroundScan>,
+    /// Only present when the library directory is being watched for changes.
+    /// Not while the library is being scanned, it is started once the scan is done.
+    library_watcher: Option<LibraryWatcher>,
 }
 
 impl MusicApp {
     fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        let config = if let Some(storage) = cc.storage {
+        let mut config: Config = if let Some(storage) = cc.storage {
             eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default()
         } else {
             Default::default()
         };
+        config.migrate();
+
+        let playlists: Playlists = if let Some(storage) = cc.storage {
+            eframe::get_value(storage, PLAYLISTS_KEY).unwrap_or_default()
+        } else {
+            Default::default()
+        };
 
         let visuals = Visuals::dark();
         cc.egui_ctx.set_visuals(visuals);
 
         let mut app = Self {
             library: Library::new(),
-            playlist: Playlist::new(),
-            playlist_selected_song: None,
+            shown_playlist: playlists.first_id(),
+            playing_playlist: playlists.first_id(),
+            playlists,
+            playlist_position: PlaylistPosition::Stopped,
+            shuffle_order: ShuffleOrder::new(),
+            play_queue: PlayQueue::new(),
+            queued_song: None,
+            playlist_tabs: PlaylistTabs::new(),
             playlist_view: PlaylistView::new(),
             library_view: LibraryView::new(),
+            playlist_files_view: PlaylistFilesView::new(),
             playback_controls: PlaybackControls::new(),
             config,
             config_view: ConfigView::new(),
             playback: Playback::new(),
             image_cache: ImageCache::new(),
+            library_cache_path: cache::default_cache_path(),
+            library_cache_dirty: false,
+            library_scan: None,
+            library_watcher: None,
         };
 
+        app.load_library_cache();
+        app.library_view.update_items(&app.library);
+        let song_ids: Vec<SongId> = app.library.songs().map(|(&id, _)| id).collect();
+        app.load_images(&cc.egui_ctx, &song_ids);
+
         app.scan_library_dir(&cc.egui_ctx);
 
         app
     }
 
-    /// Scans the library directory for songs.
-    /// TODO: remove any songs that are no longer in the directory, add any that are new,
-    ///       and update those that are already in the library.
+    /// Loads the library as it was at the end of the previous run.
+    /// This allows [scan_library_dir](Self::scan_library_dir) to skip reading unchanged files.
+    fn load_library_cache(&mut self) {
+        if let Some(cache_path) = &self.library_cache_path {
+            match cache::load_library_cache(cache_path) {
+                Ok(Some(library)) => self.library = library,
+                Ok(None) => {}
+                Err(e) => warn!(
+                    "Could not load library cache '{}': {}",
+                    cache_path.display(),
+                    e
+                ),
+            }
+        }
+    }
+
+    fn save_library_cache(&mut self) {
+        self.library_cache_dirty = false;
+
+        if let Some(cache_path) = &self.library_cache_path {
+            if let Err(e) = cache::save_library_cache(&self.library, cache_path) {
+                warn!(
+                    "Could not save library cache '{}': {}",
+                    cache_path.display(),
+                    e
+                );
+            }
+        }
+    }
+
+    /// Starts scanning the library roots for songs in the background.
+    /// Songs that are already in the library keep their id, so the playlist stays intact.
+    /// A scan that is still running is stopped first.
     fn scan_library_dir(&mut self, ctx: &egui::Context) {
-        if self.config.library_directory.is_dir() {
-            self.library.clear();
-            self.playlist.clear();
-
-            match library::scan_directory_for_songs(&self.config.library_directory) {
-                Ok(songs) => {
-                    self.library.add_songs(songs);
-                    self.library_view.update_items(&self.library);
-
-                    for (&id, song) in self.library.songs() {
-                        self.image_cache
-                            .load_image_from_song_path(ctx, &song.path, id);
-                    }
+        // The watcher would change the library while it is being scanned.
+        // It is started again once the scan is done.
+        self.library_watcher = None;
+
+        if let Some(scan) = self.library_scan.take() {
+            // Its changes would not show up in the summary of the new scan.
+            if scan.summary().has_changes() {
+                self.save_library_cache();
+            }
+        }
+
+        let ctx = ctx.clone();
+        self.library_scan = Some(BackgroundScan::start(
+            &self.library,
+            self.config.library_roots.clone(),
+            self.config.scan_options(),
+            move || ctx.request_repaint(),
+        ));
+    }
+
+    /// Applies the songs that were scanned since the last frame,
+    /// so they show up in the library while the scan is still going.
+    fn apply_library_scan_events(&mut self, ctx: &egui::Context) {
+        let (changes, finished) = match &mut self.library_scan {
+            Some(scan) => (scan.apply_events(&mut self.library), scan.is_finished()),
+            None => return,
+        };
+
+        if let Some(changes) = changes {
+            if changes.has_changes() {
+                self.library_view.update_items(&self.library);
+                self.load_changed_images(ctx, &changes);
+            }
+        }
+
+        if finished {
+            if let Some(scan) = self.library_scan.take() {
+                self.handle_library_changes(scan.summary());
+            }
+            self.watch_library_dir(ctx);
+        }
+    }
+
+    fn load_images(&self, ctx: &egui::Context, song_ids: &[SongId]) {
+        for id in song_ids {
+            if let Some(song) = self.library.get_song(id) {
+                self.image_cache
+                    .load_image_in_background(ctx, &song.path, *id);
+            }
+        }
+    }
+
+    /// Loads the images of the songs that were added or changed.
+    fn load_changed_images(&self, ctx: &egui::Context, summary: &RescanSummary) {
+        self.load_images(ctx, &summary.added);
+        self.load_images(ctx, &summary.updated);
+        let moved: Vec<SongId> = summary.moved.iter().map(|&(_, new_id)| new_id).collect();
+        self.load_images(ctx, &moved);
+    }
+
+    /// (Re)starts watching the library roots for changes, if that is enabled.
+    fn watch_library_dir(&mut self, ctx: &egui::Context) {
+        self.library_watcher = None;
+
+        if self.config.watch_library_directory {
+            let ctx = ctx.clone();
+
+            match LibraryWatcher::new(
+                self.config.library_roots.clone(),
+                self.config.scan_options(),
+                move || ctx.request_repaint(),
+            ) {
+                Ok(watcher) => self.library_watcher = Some(watcher),
+                Err(e) => warn!("Could not watch the library directories: {}", e),
+            }
+        }
+    }
+
+    /// Applies the changes the library watcher has seen, once the directory has settled down.
+    fn apply_library_dir_changes(&mut self, ctx: &egui::Context) {
+        let summary = match &mut self.library_watcher {
+            Some(watcher) => {
+                let summary = watcher.apply_changes(&mut self.library);
+                if watcher.has_pending_changes() {
+                    // Make sure we come back to apply the changes, even if nothing else happens.
+                    ctx.request_repaint_after(DEBOUNCE_DURATION);
                 }
-                Err(e) => warn!("Something went wrong while scanning for songs: '{}'", e),
+                summary
+            }
+            None => None,
+        };
+
+        if let Some(summary) = summary {
+            if summary.has_changes() || !summary.warnings.is_empty() {
+                self.handle_library_changes(&summary);
+                self.load_changed_images(ctx, &summary);
             }
         }
     }
 
+    fn handle_library_changes(&mut self, summary: &RescanSummary) {
+        for warning in &summary.warnings {
+            warn!(
+                "Something went wrong while scanning for songs: '{}'",
+                warning
+            );
+        }
+
+        info!(
+            "Library updated: {} added, {} removed, {} updated, {} moved, {} unchanged.",
+            summary.added.len(),
+            summary.removed.len(),
+            summary.updated.len(),
+            summary.moved.len(),
+            summary.unchanged
+        );
+
+        if !summary.moved.is_empty() {
+            self.follow_moved_songs(summary);
+        }
+
+        let missing_entries: usize = self
+            .playlists
+            .iter()
+            .map(|named| {
+                named
+                    .playlist
+                    .entries_missing_from_library(&self.library)
+                    .len()
+            })
+            .sum();
+        if missing_entries > 0 {
+            warn!(
+                "{} playlist entries refer to songs that are no longer in the library.",
+                missing_entries
+            );
+        }
+
+        self.library_view.update_items(&self.library);
+
+        if summary.has_changes() {
+            self.save_library_cache();
+        }
+    }
+
+    /// Moved songs get a new id, so everything that refers to them is changed to the new id.
+    fn follow_moved_songs(&mut self, summary: &RescanSummary) {
+        let new_id = |id| summary.current_id(id);
+
+        for named in self.playlists.iter_mut() {
+            named.playlist.replace_song_ids(new_id);
+        }
+        self.play_queue.replace_song_ids(new_id);
+        self.queued_song = self.queued_song.map(new_id);
+        self.playlist_position.replace_song_ids(new_id);
+    }
+
     fn show_library(&mut self, ui: &mut Ui) {
-        let add_songs = self.library_view.show_library(ui, &self.image_cache);
+        let action = self.library_view.show_library(
+            ui,
+            &self.library,
+            &self.image_cache,
+            &mut self.config.library_columns,
+        );
+
+        self.handle_library_action(action);
+    }
+
+    fn handle_library_action(&mut self, action: LibraryAction) {
+        match action {
+            LibraryAction::AddSongs { songs, position } => self.enqueue_songs(songs, position),
+            LibraryAction::None => {}
+        }
+    }
+
+    fn playing_playlist(&self) -> &Playlist {
+        &self.playlists.get_or_first(self.playing_playlist).playlist
+    }
+
+    fn playing_playlist_mut(&mut self) -> &mut Playlist {
+        &mut self
+            .playlists
+            .get_or_first_mut(self.playing_playlist)
+            .playlist
+    }
+
+    fn shown_playlist_mut(&mut self) -> &mut Playlist {
+        &mut self
+            .playlists
+            .get_or_first_mut(self.shown_playlist)
+            .playlist
+    }
+
+    /// Edits the shown playlist. When it is the playing playlist,
+    /// playback continues where the current entry was if the edit removes it.
+    fn edit_shown_playlist<R>(&mut self, edit: impl FnOnce(&mut Playlist) -> R) -> R {
+        let playlist = &mut self
+            .playlists
+            .get_or_first_mut(self.shown_playlist)
+            .playlist;
+        if self.playing_playlist == self.shown_playlist {
+            self.playlist_position.edit_playlist(playlist, edit)
+        } else {
+            edit(playlist)
+        }
+    }
+
+    fn is_playing_shown_playlist(&self) -> bool {
+        !self.playlist_position.is_stopped() && self.playing_playlist == self.shown_playlist
+    }
+
+    /// Whether there is a current song, from the playlist or from the play queue.
+    fn has_current_song(&self) -> bool {
+        !self.playlist_position.is_stopped() || self.queued_song.is_some()
+    }
+
+    /// Without a current song, playback starts in the shown playlist.
+    fn play_shown_playlist_if_stopped(&mut self) {
+        if self.playlist_position.is_stopped() {
+            self.set_playing_playlist(self.shown_playlist);
+        }
+    }
+
+    fn set_playing_playlist(&mut self, id: PlaylistId) {
+        if self.playing_playlist != id {
+            self.playing_playlist = id;
+            self.shuffle_order.clear();
+        }
+    }
 
-        self.playlist.add_songs(add_songs);
+    /// Adds songs to the shown playlist, except for songs that should be played next.
+    /// Those are added to the play queue, or inserted in the playlist that is playing.
+    fn enqueue_songs(&mut self, songs: Vec<SongId>, position: EnqueuePosition) {
+        match position {
+            EnqueuePosition::Append => self.shown_playlist_mut().add_songs(songs),
+            EnqueuePosition::Queue => self.play_queue.add_songs(songs),
+            EnqueuePosition::Next => {
+                // Without a current song, the first song in the playlist is played next.
+                self.play_shown_playlist_if_stopped();
+                let index = self.playlist_position.next_index(self.playing_playlist());
+                self.playing_playlist_mut().insert_songs(index, songs);
+            }
+            EnqueuePosition::Replace => {
+                let was_playing = self.is_playing_shown_playlist();
+
+                self.shown_playlist_mut().replace_songs(songs);
+
+                // The current song is no longer in the playlist, so continue with the new songs.
+                if was_playing {
+                    self.playlist_position = PlaylistPosition::Stopped;
+                    self.skip_to_next_song();
+                }
+            }
+        }
+    }
+
+    fn handle_playlist_file_action(&mut self, action: PlaylistFileAction) {
+        match action {
+            PlaylistFileAction::Load(path) => self.load_playlist_file(&path),
+            PlaylistFileAction::Save(path) => self.save_playlist_file(&path),
+            PlaylistFileAction::None => {}
+        }
+    }
+
+    /// Opens the songs of the file that are in the library as a new playlist.
+    fn load_playlist_file(&mut self, path: &Path) {
+        match playlist_file::load_playlist(path, &self.library) {
+            Ok(loaded) => {
+                info!(
+                    "Loaded playlist '{}': {} songs, {} not in the library.",
+                    path.display(),
+                    loaded.songs.len(),
+                    loaded.missing.len()
+                );
+                for entry in &loaded.missing {
+                    warn!(
+                        "Playlist entry '{}' is not in the library.",
+                        entry.path.display()
+                    );
+                }
+
+                let name = path
+                    .file_stem()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| Playlists::DEFAULT_NAME.to_string());
+                let id = self.playlists.create(&name);
+                self.playlists
+                    .get_or_first_mut(id)
+                    .playlist
+                    .add_songs(loaded.songs);
+                self.shown_playlist = id;
+
+                self.playlist_files_view
+                    .report_missing_songs(path.to_path_buf(), loaded.missing);
+            }
+            Err(e) => warn!("Could not load playlist '{}': {}", path.display(), e),
+        }
     }
 
-    fn play_next_song(&mut self) {
-        let next_entry = if let Some(cur_entry) = self.playlist_selected_song {
-            self.playlist.get_next_entry(cur_entry.0)
+    /// Ctrl+Z undoes an edit of the shown playlist, Ctrl+Shift+Z redoes it.
+    fn handle_undo_shortcuts(&mut self, ctx: &egui::Context) {
+        // Text fields have their own undo.
+        if ctx.wants_keyboard_input() || !ctx.input().key_pressed(Key::Z) {
+            return;
+        }
+
+        let modifiers = ctx.input().modifiers;
+        if modifiers.matches(Modifiers::COMMAND) {
+            self.edit_shown_playlist(Playlist::undo);
+        } else if modifiers.matches(Modifiers::COMMAND | Modifiers::SHIFT) {
+            self.edit_shown_playlist(Playlist::redo);
+        }
+    }
+
+    fn handle_playlist_tabs_action(&mut self, action: PlaylistTabsAction) {
+        match action {
+            PlaylistTabsAction::Remove(id) => {
+                if !self.playlist_position.is_stopped() && self.playing_playlist == id {
+                    self.stop_playing();
+                }
+                if self.playlists.remove(id) && self.shown_playlist == id {
+                    self.shown_playlist = self.playlists.first_id();
+                }
+            }
+            PlaylistTabsAction::None => {}
+        }
+    }
+
+    fn save_playlist_file(&self, path: &Path) {
+        let path_style = if self.config.relative_playlist_paths {
+            PathStyle::Relative
         } else {
-            self.playlist.get_first_entry()
+            PathStyle::Absolute
         };
 
+        if let Err(e) = playlist_file::save_playlist(
+            &self.playlists.get_or_first(self.shown_playlist).playlist,
+            &self.library,
+            path,
+            path_style,
+        ) {
+            warn!("Could not save playlist '{}': {}", path.display(), e);
+        }
+    }
+
+    /// Plays the song after the current one, when the user asks for it.
+    fn skip_to_next_song(&mut self) {
+        self.play_next_song(self.config.repeat_mode.when_skipping());
+    }
+
+    fn play_next_song(&mut self, repeat: RepeatMode) {
+        if let (RepeatMode::One, Some(song_id)) = (repeat, self.queued_song) {
+            if self.play_queued_song(song_id) {
+                return;
+            }
+        }
+
+        // Songs in the play queue go before the rest of the playlist.
+        while let Some(song_id) = self.play_queue.pop_next() {
+            if self.play_queued_song(song_id) {
+                return;
+            }
+        }
+
+        self.play_shown_playlist_if_stopped();
+        let playlist = &self.playlists.get_or_first(self.playing_playlist).playlist;
+        let shuffle = self.config.shuffle.then_some(&mut self.shuffle_order);
+        let next_entry =
+            self.playlist_position
+                .next_entry(playlist, &self.library, shuffle, repeat);
+
         if let Some(entry) = next_entry {
             self.play_playlist_entry(entry);
         } else {
@@ -107,10 +540,12 @@ impl MusicApp {
             if self.config.infinite_playlist {
                 // TODO: Remember which songs have already played, and don't select those?
                 if let Some(&random_song_id) = self.library.get_random_song_id() {
-                    self.playlist.add_song(random_song_id);
-                    // This recursive call should be fine, because we just added another song to play.
-                    // But it would likely be better if there wasn't recursion here.
-                    self.play_next_song();
+                    self.playing_playlist_mut()
+                        .add_songs_without_history(vec![random_song_id]);
+                    // The song that was just added is the last entry, so it is the one to play.
+                    if let Some(entry) = self.playing_playlist().get_last_entry() {
+                        self.play_playlist_entry(entry);
+                    }
                 } else {
                     self.stop_playing();
                 }
@@ -122,77 +557,165 @@ impl MusicApp {
 
     /// Stops playing, and sets the current song to `None`.
     fn stop_playing(&mut self) {
-        self.playlist_selected_song = None;
+        self.playlist_position = PlaylistPosition::Stopped;
+        self.queued_song = None;
         self.playback.stop();
     }
 
     fn play_previous_song(&mut self) {
-        let prev_entry = if let Some(cur_entry) = self.playlist_selected_song {
-            self.playlist.get_previous_entry(cur_entry.0)
-        } else {
-            self.playlist.get_last_entry()
-        };
+        // The song before a queued song is the one in the playlist that was playing before it.
+        if let (Some(_), Some((entry_id, song_id))) =
+            (self.queued_song, self.playlist_position.entry())
+        {
+            if let Some(index) = self.playing_playlist().get_song_index(entry_id) {
+                self.play_playlist_entry((entry_id, song_id, index));
+                return;
+            }
+        }
+
+        self.play_shown_playlist_if_stopped();
+        let repeat = self.config.repeat_mode.when_skipping();
+        let playlist = &self.playlists.get_or_first(self.playing_playlist).playlist;
+        let shuffle = self.config.shuffle.then_some(&mut self.shuffle_order);
+        let prev_entry =
+            self.playlist_position
+                .previous_entry(playlist, &self.library, shuffle, repeat);
 
         if let Some(entry) = prev_entry {
             self.play_playlist_entry(entry);
         }
     }
 
-    fn play_playlist_entry(
+    /// Plays an entry of the playing playlist.
+    fn play_playlist_entry(&mut self, entry: (ListEntryId, SongId, usize)) {
+        self.play_entry_of_playlist(self.playing_playlist, entry);
+    }
+
+    /// Plays an entry of the playlist with the id.
+    /// Once the song plays, that playlist is the playing playlist.
+    fn play_entry_of_playlist(
         &mut self,
+        playlist_id: PlaylistId,
         (entry_id, song_id, entry_index): (ListEntryId, SongId, usize),
     ) {
-        if let Some(song) = self.library.get_song(&song_id) {
-            match self.playback.play_file(&song.path) {
-                Ok(()) => self.playlist_selected_song = Some((entry_id, song_id)),
-                Err(e) => warn!("Could not play song `{}`: {}", song.path.display(), e),
+        let song = match self.library.get_song(&song_id) {
+            Some(song) => song,
+            None => {
+                // The song was removed from the library, so the entry is passed.
+                self.set_playing_playlist(playlist_id);
+                self.playlist_position = PlaylistPosition::Entry(entry_id, song_id);
+                self.queued_song = None;
+                self.skip_to_next_song();
+                return;
+            }
+        };
+
+        match self.playback.play_file(&song.path) {
+            Ok(()) => {
+                self.set_playing_playlist(playlist_id);
+                self.playlist_position = PlaylistPosition::Entry(entry_id, song_id);
+                self.queued_song = None;
+                self.library.record_play(&song_id);
+                self.library_cache_dirty = true;
+            }
+            Err(e) => {
+                // Nothing changes, also not the playing playlist.
+                warn!("Could not play song `{}`: {}", song.path.display(), e);
+                return;
             }
         }
         self.playback.unpause();
 
         if self.config.infinite_playlist {
+            // When shuffling, the songs that were played are not the ones in front of this one.
+            let played: Vec<ListEntryId> = if self.config.shuffle {
+                self.shuffle_order
+                    .played()
+                    .iter()
+                    .copied()
+                    .filter(|&played_id| played_id != entry_id)
+                    .collect()
+            } else {
+                self.playing_playlist()
+                    .song_ids()
+                    .take(entry_index)
+                    .map(|&(played_id, _)| played_id)
+                    .collect()
+            };
+
             // Fill the playlist with random songs until we have the desired amount of buffer.
-            let songs_in_buffer = self.playlist.length() - (entry_index + 1);
+            let songs_in_buffer = self
+                .playing_playlist()
+                .length()
+                .saturating_sub(played.len() + 1);
             let desired_buffer = self.config.infinite_playlist_song_buffer as usize;
 
+            // These are not edits of the user, so they can't be undone.
             if songs_in_buffer < desired_buffer {
-                for _ in songs_in_buffer..desired_buffer {
-                    if let Some(&random_song) = self.library.get_random_song_id() {
-                        self.playlist.add_song(random_song);
-                    }
-                }
+                let random_songs = (songs_in_buffer..desired_buffer)
+                    .filter_map(|_| self.library.get_random_song_id().copied())
+                    .collect();
+                self.playing_playlist_mut()
+                    .add_songs_without_history(random_songs);
             }
 
-            // Remove songs from the back until we are left with the desired amount of rear buffer.
-            let songs_in_rear_buffer = entry_index;
+            // Remove the songs that were played longest ago,
+            // until we are left with the desired amount of rear buffer.
             let desired_rear_buffer = self.config.infinite_playlist_song_rear_buffer as usize;
-            if songs_in_rear_buffer > desired_rear_buffer {
-                for _ in desired_rear_buffer..songs_in_rear_buffer {
-                    self.playlist.remove_song_by_index(0);
+            if played.len() > desired_rear_buffer {
+                for &played_id in &played[..played.len() - desired_rear_buffer] {
+                    self.playing_playlist_mut()
+                        .remove_song_without_history(played_id);
                 }
             }
         }
     }
 
+    /// Plays a song from the play queue. Returns whether it could be played.
+    fn play_queued_song(&mut self, song_id: SongId) -> bool {
+        let song = match self.library.get_song(&song_id) {
+            Some(song) => song,
+            None => return false,
+        };
+
+        match self.playback.play_file(&song.path) {
+            Ok(()) => {
+                self.queued_song = Some(song_id);
+                self.library.record_play(&song_id);
+                self.library_cache_dirty = true;
+                self.playback.unpause();
+                true
+            }
+            Err(e) => {
+                warn!("Could not play song `{}`: {}", song.path.display(), e);
+                false
+            }
+        }
+    }
+
     fn show_playback_controls(&mut self, ui: &mut Ui) {
         ui.horizontal(|ui| {
             let paused = self.playback.is_paused();
             let volume = self.playback.volume();
 
-            if let Some(command) =
-                self.playback_controls
-                    .show(ui, paused, volume, &mut self.config.infinite_playlist)
-            {
+            if let Some(command) = self.playback_controls.show(
+                ui,
+                paused,
+                volume,
+                &mut self.config.infinite_playlist,
+                &mut self.config.shuffle,
+                &mut self.config.repeat_mode,
+            ) {
                 match command {
                     PlaybackCommand::Pause => self.playback.pause(),
                     PlaybackCommand::Unpause => {
-                        if self.playlist_selected_song.is_some() {
+                        if self.has_current_song() {
                             self.playback.unpause();
                         } else {
-                            self.play_next_song();
+                            self.skip_to_next_song();
                         }
                     }
-                    PlaybackCommand::NextSong => self.play_next_song(),
+                    PlaybackCommand::NextSong => self.skip_to_next_song(),
                     PlaybackCommand::PreviousSong => self.play_previous_song(),
                     PlaybackCommand::SetVolume(new_volume) => self.playback.set_volume(new_volume),
                 }
@@ -219,7 +742,7 @@ impl MusicApp {
 
                     let seconds_selected = (total_length as f32 * fraction).floor() as u64;
 
-                    if self.playlist_selected_song.is_some() {
+                    if self.has_current_song() {
                         self.playback.seek_seconds_into_song(seconds_selected);
                     }
                 }
@@ -227,7 +750,7 @@ impl MusicApp {
 
             if seconds_played == total_length && seconds_played != 0 {
                 // Song has ended. Play next song.
-                self.play_next_song();
+                self.play_next_song(self.config.repeat_mode);
             }
 
             if !paused {
@@ -242,22 +765,44 @@ impl MusicApp {
 
 impl App for MusicApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        let prev_library_directory = self.config.library_directory.clone();
+        let prev_watch_library_directory = self.config.watch_library_directory;
 
-        self.config_view.show(ctx, &mut self.config);
+        let library_settings_changed = self.config_view.show(ctx, &mut self.config);
 
-        if prev_library_directory != self.config.library_directory {
+        if library_settings_changed {
             self.scan_library_dir(ctx);
+        } else if prev_watch_library_directory != self.config.watch_library_directory
+            && self.library_scan.is_none()
+        {
+            self.watch_library_dir(ctx);
         }
 
+        self.playlist_files_view.show_missing_songs(ctx);
+        self.handle_undo_shortcuts(ctx);
+
+        self.apply_library_scan_events(ctx);
+        self.apply_library_dir_changes(ctx);
+        self.image_cache.receive_loaded_images();
+
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             ui.horizontal(|ui| {
                 if ui.button("Config").clicked() {
                     self.config_view.open_window();
                 }
 
-                let add_songs = self.library_view.show_library_search_widget(ui);
-                self.playlist.add_songs(add_songs);
+                let shown_name = &self.playlists.get_or_first(self.shown_playlist).name;
+                let action = self.playlist_files_view.show_buttons(ui, shown_name);
+                self.handle_playlist_file_action(action);
+                ui.separator();
+
+                let action = self
+                    .library_view
+                    .show_library_search_widget(ui, &self.library);
+                self.handle_library_action(action);
+
+                if let Some(scan) = &self.library_scan {
+                    show_library_scan_progress(ui, scan);
+                }
             });
         });
 
@@ -265,6 +810,12 @@ impl App for MusicApp {
             self.show_playback_controls(ui);
         });
 
+        if !self.play_queue.is_empty() {
+            egui::TopBottomPanel::bottom("play_queue_panel").show(ctx, |ui| {
+                show_play_queue(ui, &mut self.play_queue, &self.library);
+            });
+        }
+
         if self.library_view.should_show_library() {
             egui::SidePanel::right("right_panel")
                 .resizable(true)
@@ -275,30 +826,56 @@ impl App for MusicApp {
         }
 
         egui::CentralPanel::default().show(ctx, |ui| {
+            let playing = (!self.playlist_position.is_stopped()).then_some(self.playing_playlist);
+            let action =
+                self.playlist_tabs
+                    .show(ui, &mut self.playlists, &mut self.shown_playlist, playing);
+            self.handle_playlist_tabs_action(action);
+            ui.separator();
+
+            // The current song is only highlighted in the playlist it is from,
+            // and not while a song from the play queue is playing.
+            let selected_song = if self.is_playing_shown_playlist() && self.queued_song.is_none() {
+                self.playlist_position.entry()
+            } else {
+                None
+            };
             let action = self.playlist_view.show(
                 ui,
-                &mut self.playlist,
+                &mut self
+                    .playlists
+                    .get_or_first_mut(self.shown_playlist)
+                    .playlist,
                 &self.library,
                 &self.image_cache,
-                self.playlist_selected_song,
+                selected_song,
+                &mut self.config.playlist_columns,
             );
 
             match action {
                 PlaylistAction::PlaySong((list_entry, song_id)) => {
-                    if let Some(index) = self.playlist.get_song_index(list_entry) {
-                        self.play_playlist_entry((list_entry, song_id, index));
+                    let shown = self.shown_playlist;
+                    let index = self
+                        .playlists
+                        .get_or_first(shown)
+                        .playlist
+                        .get_song_index(list_entry);
+                    if let Some(index) = index {
+                        self.play_entry_of_playlist(shown, (list_entry, song_id, index));
                     }
                 }
                 PlaylistAction::RemoveSong(remove_id) => {
-                    if let Some((selected_id, _)) = self.playlist_selected_song {
-                        if selected_id == remove_id {
-                            // TODO: Make it not play a song if you remove the last song from the playlist
-                            //   (because the "next song" will be the current song, because it hasn't been removed yet).
-                            self.play_next_song();
-                        }
+                    // Also while a queued song is playing, the playlist continues where the entry was.
+                    let removes_current = self.is_playing_shown_playlist()
+                        && self.playlist_position.entry().map(|(entry_id, _)| entry_id)
+                            == Some(remove_id);
+                    self.edit_shown_playlist(|playlist| playlist.remove_song(remove_id));
+
+                    if removes_current && self.queued_song.is_none() {
+                        self.skip_to_next_song();
                     }
-                    self.playlist.remove_song(remove_id)
                 }
+                PlaylistAction::QueueSong(song_id) => self.play_queue.add_songs(vec![song_id]),
                 PlaylistAction::None => {}
             }
         });
@@ -306,6 +883,44 @@ impl App for MusicApp {
 
     fn save(&mut self, storage: &mut dyn Storage) {
         eframe::set_value(storage, eframe::APP_KEY, &self.config);
+        eframe::set_value(storage, PLAYLISTS_KEY, &self.playlists);
+
+        if self.library_cache_dirty {
+            self.save_library_cache();
+        }
+    }
+}
+
+fn show_library_scan_progress(ui: &mut Ui, scan: &BackgroundScan) {
+    ui.separator();
+
+    if scan.is_cancelled() {
+        ui.spinner();
+        ui.label("Cancelling scan...");
+        return;
+    }
+
+    let progress = scan.progress();
+    if progress.all_files_found {
+        egui::ProgressBar::new(progress.fraction_processed())
+            .text(format!(
+                "Scanning library: {} / {} songs",
+                progress.files_processed, progress.files_found
+            ))
+            .desired_width(250.0)
+            .ui(ui);
+    } else {
+        ui.spinner();
+        ui.label(format!("Looking for songs: {} found", progress.files_found));
+    }
+
+    if progress.errors > 0 {
+        ui.label(format!("{} errors", progress.errors))
+            .on_hover_text("The errors are listed in the log once the scan is done.");
+    }
+
+    if ui.button("Cancel").clicked() {
+        scan.cancel();
     }
 }
 
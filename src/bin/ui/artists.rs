@@ -0,0 +1,117 @@
+use crate::ui::library::{show_enqueue_buttons, LibraryAction};
+use eframe::egui;
+use eframe::egui::collapsing_header::CollapsingState;
+use eframe::egui::{CursorIcon, Id, Label, Sense, Ui};
+use simple_music_lib::library::grouping::{AlbumGroup, ArtistGroup};
+use simple_music_lib::library::{Library, SongId};
+
+/// Shows songs grouped by artist, then by album, then the tracks of the album.
+pub struct ArtistView {
+    /// Built when the view is shown, and thrown away when the songs change.
+    artists: Option<Vec<ArtistGroup>>,
+}
+
+impl ArtistView {
+    pub fn new() -> Self {
+        Self { artists: None }
+    }
+
+    /// Groups the songs again the next time the view is shown.
+    pub fn clear(&mut self) {
+        self.artists = None;
+    }
+
+    pub fn show(&mut self, ui: &mut Ui, library: &Library, song_ids: &[SongId]) -> LibraryAction {
+        let artists = self
+            .artists
+            .get_or_insert_with(|| library.group_by_artist(song_ids.iter().copied()));
+
+        let mut action = LibraryAction::None;
+
+        egui::ScrollArea::both()
+            .auto_shrink([false, false])
+            .show(ui, |ui| {
+                for artist in artists.iter() {
+                    show_artist(ui, artist, library, &mut action);
+                }
+            });
+
+        action
+    }
+}
+
+fn show_artist(ui: &mut Ui, artist: &ArtistGroup, library: &Library, action: &mut LibraryAction) {
+    let id = Id::new("library_artist").with(&artist.name);
+
+    CollapsingState::load_with_default_open(ui.ctx(), id, false)
+        .show_header(ui, |ui| {
+            let text = format!("{} ({})", artist.name, artist.song_count());
+            show_group_label(ui, text, || artist.all_songs(), action);
+        })
+        .body(|ui| {
+            for album in &artist.albums {
+                show_album(ui, id, album, library, action);
+            }
+        });
+}
+
+fn show_album(
+    ui: &mut Ui,
+    artist_id: Id,
+    album: &AlbumGroup,
+    library: &Library,
+    action: &mut LibraryAction,
+) {
+    let id = artist_id.with(&album.title);
+
+    CollapsingState::load_with_default_open(ui.ctx(), id, false)
+        .show_header(ui, |ui| {
+            let text = match album.year {
+                Some(year) => format!("{} ({}, {})", album.title, year, album.songs.len()),
+                None => format!("{} ({})", album.title, album.songs.len()),
+            };
+            show_group_label(ui, text, || album.songs.clone(), action);
+        })
+        .body(|ui| {
+            for &song_id in &album.songs {
+                if let Some(song) = library.get_song(&song_id) {
+                    ui.horizontal(|ui| {
+                        if ui.button("+").on_hover_text("Add to playlist").clicked() {
+                            *action = LibraryAction::append(vec![song_id]);
+                        }
+
+                        match song.track_number {
+                            Some(track) => ui.label(format!("{}. {}", track, song.title)),
+                            None => ui.label(&song.title),
+                        };
+                    });
+                }
+            }
+        });
+}
+
+/// Double-clicking the label adds the songs to the playlist,
+/// right-clicking it shows the other ways to add them.
+fn show_group_label<F>(ui: &mut Ui, text: String, songs: F, action: &mut LibraryAction)
+where
+    F: Fn() -> Vec<SongId>,
+{
+    let response = ui
+        .add(Label::new(text).sense(Sense::click()))
+        .on_hover_cursor(CursorIcon::PointingHand)
+        .on_hover_text("Double-click to add to the playlist, right-click for more");
+
+    if response.double_clicked() {
+        *action = LibraryAction::append(songs());
+    }
+
+    response.context_menu(|ui| {
+        if let Some(position) = show_enqueue_buttons(ui, true) {
+            *action = LibraryAction::AddSongs {
+                songs: songs(),
+                position,
+            };
+            ui.close_menu();
+        }
+    });
+}
@@ -0,0 +1,160 @@
+use eframe::egui::{
+    vec2, Align, CursorIcon, Label, Layout, RichText, Sense, Shape, TextureHandle, Ui,
+};
+use simple_music_lib::library::columns::{Column, ColumnLayout, SortOrder};
+
+/// Width of a cell with a small button, like the "+" to add a song to the playlist.
+pub const BUTTON_WIDTH: f32 = 24.0;
+const MIN_COLUMN_WIDTH: f32 = 30.0;
+/// The part at the right edge of a column header that can be dragged to resize the column.
+const RESIZE_HANDLE_WIDTH: f32 = 6.0;
+const CELL_PADDING: f32 = 3.0;
+
+/// Shows the headers of the columns, aligned with the cells of [show_cells].
+/// `leading_width` is the width of the cells in front of the columns, like buttons.
+///
+/// Clicking a header sorts by that column, dragging its right edge resizes it,
+/// and right-clicking shows which columns can be shown.
+/// Returns whether the sort order changed.
+pub fn show_column_headers(
+    ui: &mut Ui,
+    layout: &mut ColumnLayout,
+    leading_width: f32,
+    row_height: f32,
+) -> bool {
+    let mut sort_by = None;
+    let mut toggle_column = None;
+
+    ui.horizontal(|ui| {
+        ui.spacing_mut().item_spacing.x = 0.0;
+        ui.add_space(leading_width);
+
+        let sort = layout.sort;
+        let shown: Vec<Column> = layout.columns.iter().map(|shown| shown.column).collect();
+
+        for column_width in &mut layout.columns {
+            let column = column_width.column;
+            let text = match sort {
+                Some(SortOrder {
+                    column: sorted,
+                    descending,
+                }) if sorted == column => {
+                    format!("{} {}", column.name(), if descending { "⏷" } else { "⏶" })
+                }
+                _ => column.name().to_string(),
+            };
+
+            let header_width = (column_width.width - RESIZE_HANDLE_WIDTH).max(0.0);
+            cell(ui, header_width, row_height, |ui| {
+                let response = ui
+                    .add(Label::new(RichText::new(text).strong()).sense(Sense::click()))
+                    .on_hover_text("Click to sort, right-click to choose the columns");
+                if response.clicked() {
+                    sort_by = Some(column);
+                }
+                response.context_menu(|ui| {
+                    for column in Column::ALL {
+                        let mut is_shown = shown.contains(&column);
+                        if ui.checkbox(&mut is_shown, column.name()).clicked() {
+                            toggle_column = Some(column);
+                        }
+                    }
+                });
+            });
+
+            let (rect, response) = ui.allocate_exact_size(
+                vec2(RESIZE_HANDLE_WIDTH, row_height),
+                Sense::click_and_drag(),
+            );
+            let response = response.on_hover_cursor(CursorIcon::ResizeHorizontal);
+            if response.dragged() {
+                column_width.width =
+                    (column_width.width + response.drag_delta().x).max(MIN_COLUMN_WIDTH);
+            }
+            ui.painter().vline(
+                rect.center().x,
+                rect.y_range(),
+                ui.visuals().widgets.noninteractive.bg_stroke,
+            );
+        }
+    });
+
+    if let Some(column) = toggle_column {
+        layout.toggle_column(column);
+    }
+
+    match sort_by {
+        Some(column) => {
+            layout.cycle_sort(column);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Lays out a row of a list, with a background on every other row.
+pub fn show_row<R>(ui: &mut Ui, row_index: usize, add_contents: impl FnOnce(&mut Ui) -> R) -> R {
+    let background = ui.painter().add(Shape::Noop);
+
+    let response = ui.horizontal(|ui| {
+        ui.spacing_mut().item_spacing.x = 0.0;
+        add_contents(ui)
+    });
+
+    if row_index % 2 == 1 {
+        let rect = response.response.rect.expand2(vec2(0.0, 1.0));
+        ui.painter().set(
+            background,
+            Shape::rect_filled(rect, 0.0, ui.visuals().faint_bg_color),
+        );
+    }
+
+    response.inner
+}
+
+/// Shows a cell for every column, aligned with the [headers](show_column_headers).
+pub fn show_cells(
+    ui: &mut Ui,
+    layout: &ColumnLayout,
+    row_height: f32,
+    mut add_cell: impl FnMut(&mut Ui, Column),
+) {
+    for column_width in &layout.columns {
+        cell(ui, column_width.width, row_height, |ui| {
+            add_cell(ui, column_width.column)
+        });
+    }
+}
+
+/// A cell with the thumbnail of a song, or an empty one if there is no thumbnail.
+pub fn image_cell(ui: &mut Ui, texture: Option<&TextureHandle>, row_height: f32) {
+    // TODO: get image width scaled relative to it's normal height, and not the target height.
+    let size = vec2(row_height / 9.0 * 16.0, row_height);
+
+    match texture {
+        Some(texture) => {
+            ui.image(texture, size);
+        }
+        None => {
+            ui.allocate_exact_size(size, Sense::hover());
+        }
+    }
+}
+
+/// Adds the contents in exactly the given width. Whatever doesn't fit is cut off.
+pub fn cell<R>(
+    ui: &mut Ui,
+    width: f32,
+    row_height: f32,
+    add_contents: impl FnOnce(&mut Ui) -> R,
+) -> R {
+    let (rect, _) = ui.allocate_exact_size(vec2(width, row_height), Sense::hover());
+
+    let mut cell_ui = ui.child_ui(
+        rect.shrink2(vec2(CELL_PADDING, 0.0)),
+        Layout::left_to_right(Align::Center),
+    );
+    cell_ui.set_clip_rect(rect.intersect(ui.clip_rect()));
+
+    add_contents(&mut cell_ui)
+}
@@ -1,6 +1,7 @@
-use eframe::egui::{Context, DragValue, Grid, Widget, Window};
+use eframe::egui::{Context, DragValue, Grid, TextEdit, Ui, Widget, Window};
 use rfd::FileDialog;
 use simple_music_lib::config::Config;
+use simple_music_lib::library::LibraryRoot;
 
 #[derive(Default)]
 pub struct ConfigView {
@@ -12,27 +13,41 @@ impl ConfigView {
         Default::default()
     }
 
-    pub fn show(&mut self, ctx: &Context, config: &mut Config) {
+    /// Returns whether the library roots or scan settings were changed,
+    /// and the library should be rescanned.
+    pub fn show(&mut self, ctx: &Context, config: &mut Config) -> bool {
+        let mut library_roots_changed = false;
+
         Window::new("Config")
             .collapsible(false)
             .open(&mut self.window_open)
             .show(ctx, |ui| {
+                ui.heading("Library");
+                library_roots_changed = show_library_roots(ui, &mut config.library_roots);
+
+                ui.separator();
+
                 Grid::new("config_grid")
                     .striped(true)
                     .num_columns(2)
                     .show(ui, |ui| {
-                        ui.label("Library:");
-                        ui.label(format!("{}", config.library_directory.display()));
+                        ui.label("Watch library for changes:");
+                        ui.checkbox(&mut config.watch_library_directory, "");
+                        ui.end_row();
 
+                        ui.label("Recognize moved songs (slow):");
+                        ui.checkbox(&mut config.recognize_moved_songs, "");
                         ui.end_row();
 
-                        ui.label("");
-                        if ui.button("Select library directory").clicked() {
-                            if let Some(dir) = FileDialog::new().pick_folder() {
-                                // TODO: let the user know when an error occured, with a pop-up or something like that.
-                                config.library_directory = dir;
-                            }
-                        }
+                        ui.label("Scan hidden directories:");
+                        library_roots_changed |= ui
+                            .checkbox(&mut config.scan_hidden_directories, "")
+                            .changed();
+                        ui.end_row();
+
+                        ui.label("Follow symbolic links:");
+                        library_roots_changed |=
+                            ui.checkbox(&mut config.follow_symlinks, "").changed();
                         ui.end_row();
 
                         ui.label("Infinite playlist:");
@@ -46,11 +61,90 @@ impl ConfigView {
                         ui.label("Infinite playlist rear buffer:");
                         DragValue::new(&mut config.infinite_playlist_song_rear_buffer).ui(ui);
                         ui.end_row();
+
+                        ui.label("Save playlists with relative paths:");
+                        ui.checkbox(&mut config.relative_playlist_paths, "");
+                        ui.end_row();
                     });
             });
+
+        library_roots_changed
     }
 
     pub fn open_window(&mut self) {
         self.window_open = true;
     }
 }
+
+/// Returns whether the roots were changed.
+fn show_library_roots(ui: &mut Ui, roots: &mut Vec<LibraryRoot>) -> bool {
+    let mut changed = false;
+    let mut remove_root = None;
+
+    for (index, root) in roots.iter_mut().enumerate() {
+        ui.push_id(index, |ui| {
+            ui.horizontal(|ui| {
+                changed |= ui.checkbox(&mut root.enabled, "").changed();
+                ui.label(format!("{}", root.path.display()));
+
+                if ui.button("Remove").clicked() {
+                    remove_root = Some(index);
+                }
+            });
+
+            ui.collapsing("Include / exclude patterns", |ui| {
+                ui.label("Include (if empty, everything is included):");
+                changed |= show_patterns(ui, &mut root.include);
+                ui.label("Exclude:");
+                changed |= show_patterns(ui, &mut root.exclude);
+            });
+        });
+    }
+
+    if let Some(index) = remove_root {
+        roots.remove(index);
+        changed = true;
+    }
+
+    if ui.button("Add library directory").clicked() {
+        if let Some(dir) = FileDialog::new().pick_folder() {
+            // TODO: let the user know when an error occured, with a pop-up or something like that.
+            roots.push(LibraryRoot::new(dir));
+            changed = true;
+        }
+    }
+
+    changed
+}
+
+/// Shows an editable list of glob patterns.
+/// Only reports a change once editing a pattern is done, so we don't rescan on every key press.
+fn show_patterns(ui: &mut Ui, patterns: &mut Vec<String>) -> bool {
+    let mut changed = false;
+    let mut remove_pattern = None;
+
+    for (index, pattern) in patterns.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            let response = TextEdit::singleline(pattern)
+                .hint_text("**/*.flac")
+                .desired_width(200.0)
+                .ui(ui);
+            changed |= response.lost_focus();
+
+            if ui.button("x").clicked() {
+                remove_pattern = Some(index);
+            }
+        });
+    }
+
+    if let Some(index) = remove_pattern {
+        patterns.remove(index);
+        changed = true;
+    }
+
+    if ui.button("+").clicked() {
+        patterns.push(String::new());
+    }
+
+    changed
+}
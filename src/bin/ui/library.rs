@@ -1,49 +1,129 @@
 use crate::egui::Image;
+use crate::ui::artists::ArtistView;
+use crate::ui::columns::{self, BUTTON_WIDTH};
 use eframe::egui;
-use eframe::egui::{Key, Modifiers, Ui};
+use eframe::egui::collapsing_header::CollapsingState;
+use eframe::egui::{Button, Color32, Id, Key, Label, Modifiers, Sense, Ui};
 use simple_music_lib::image_cache::ImageCache;
+use simple_music_lib::library::columns::{ColumnLayout, SortOrder};
+use simple_music_lib::library::folder_tree::{Folder, FolderTree};
+use simple_music_lib::library::query::{Query, QueryError};
 use simple_music_lib::library::{Library, Song, SongId};
+use std::collections::HashSet;
+
+/// Where songs from the library end up.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum EnqueuePosition {
+    /// At the end of the playlist.
+    Append,
+    /// In the play queue, to be played after the current song without changing the playlist.
+    Queue,
+    /// Right after the song that is currently playing.
+    Next,
+    /// Instead of everything that is in the playlist now.
+    Replace,
+}
+
+pub enum LibraryAction {
+    None,
+    AddSongs {
+        songs: Vec<SongId>,
+        position: EnqueuePosition,
+    },
+}
+
+impl LibraryAction {
+    pub fn append(songs: Vec<SongId>) -> Self {
+        LibraryAction::AddSongs {
+            songs,
+            position: EnqueuePosition::Append,
+        }
+    }
+}
+
+/// How the songs in the library are shown.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LibraryMode {
+    /// Every song on its own row, best search results first.
+    List,
+    /// Grouped by the folders the songs are in.
+    Folders,
+    /// Grouped by artist and album.
+    Artists,
+}
 
 pub struct LibraryView {
+    mode: LibraryMode,
+    /// Keeps the library open while not searching, so it can be browsed.
+    browsing: bool,
     filter_string: String,
-    /// Always sorted alphabetically.
-    unfiltered_items: Vec<(SongId, Song)>,
-    filtered_items: Vec<(SongId, Song)>,
+    /// The parsed filter string.
+    query: Query,
+    /// Why the filter string could not be used, if it couldn't.
+    query_error: Option<QueryError>,
+    song_count: usize,
+    /// Best matches first, unless the list is sorted by a column.
+    filtered_items: Vec<SongId>,
+    /// What `filtered_items` is sorted by, `None` for best matches first.
+    sort: Option<SortOrder>,
+    /// Can contain songs that are not in `filtered_items`, those are not used.
+    selected_items: HashSet<SongId>,
+    /// Where shift-clicking selects from.
+    selection_anchor: Option<SongId>,
+    /// The folders of `filtered_items`. Only built when the folders are shown,
+    /// and thrown away when the search results change.
+    folder_tree: Option<FolderTree>,
+    artist_view: ArtistView,
 }
 
 impl LibraryView {
     pub fn new() -> Self {
         Self {
+            mode: LibraryMode::List,
+            browsing: false,
             filter_string: String::new(),
-            unfiltered_items: vec![],
+            query: Query::All,
+            query_error: None,
+            song_count: 0,
             filtered_items: vec![],
+            sort: None,
+            selected_items: HashSet::new(),
+            selection_anchor: None,
+            folder_tree: None,
+            artist_view: ArtistView::new(),
         }
     }
 
+    /// Searches again, because the songs in the library changed.
     pub fn update_items(&mut self, library: &Library) {
-        self.unfiltered_items.clear();
+        self.song_count = library.song_count();
 
-        //  TODO (Wybe 2022-04-29): Copying the songs every time is not the most efficient way.
-        //                          but pre-mature optimization is also a thing.
-        for (&id, song) in library.songs() {
-            self.unfiltered_items.push((id, song.clone()));
+        if self.query_error.is_none() {
+            self.search(library);
+            self.folder_tree = None;
+            self.artist_view.clear();
         }
+    }
 
-        self.unfiltered_items
-            .sort_by(|(_, first), (_, second)| first.title.partial_cmp(&second.title).unwrap());
+    fn search(&mut self, library: &Library) {
+        self.filtered_items = library.search(&self.query);
 
-        self.filtered_items = self.unfiltered_items.clone();
+        if let Some(sort) = self.sort {
+            sort.sort(library, &mut self.filtered_items, |&id| id);
+        }
     }
 
-    /// Returns a list of songs to add to the playlist.
-    pub fn show_library_search_widget(&mut self, ui: &mut Ui) -> Vec<SongId> {
+    pub fn show_library_search_widget(&mut self, ui: &mut Ui, library: &Library) -> LibraryAction {
         let prev_filter_string = self.filter_string.clone();
 
         let search_bar = egui::TextEdit::singleline(&mut self.filter_string)
-            .hint_text("Search")
+            .hint_text("Search, like: artist:radiohead year:>2000 -live")
             .desired_width(200.0);
         let search_bar_response = ui.add(search_bar);
 
+        ui.toggle_value(&mut self.browsing, "Browse")
+            .on_hover_text("Keep the library open, also when not searching");
+
         let search_bar_enter_pressed =
             search_bar_response.lost_focus() && ui.input().key_pressed(Key::Enter);
 
@@ -60,27 +140,29 @@ impl LibraryView {
         }
 
         if prev_filter_string != self.filter_string {
-            self.update_filter_string(self.filter_string.clone());
+            self.update_filter_string(self.filter_string.clone(), library);
         }
 
-        if self.filtered_items.len() == self.unfiltered_items.len() {
-            ui.label(format!("{} songs", self.unfiltered_items.len()));
+        if let Some(error) = &self.query_error {
+            ui.colored_label(Color32::RED, error.to_string());
+        } else if self.filtered_items.len() == self.song_count {
+            ui.label(format!("{} songs", self.song_count));
         } else {
             ui.label(format!(
                 "{} / {} songs",
                 self.filtered_items.len(),
-                self.unfiltered_items.len()
+                self.song_count
             ));
         }
 
-        let mut add_songs = Vec::new();
+        let mut action = LibraryAction::None;
 
         if search_bar_enter_pressed {
-            if let Some(&(first_id, _)) = self.filtered_items.first() {
-                add_songs.push(first_id);
+            if let Some(&first_id) = self.filtered_items.first() {
+                action = LibraryAction::append(vec![first_id]);
 
                 // Clear the search bar
-                self.update_filter_string(String::new());
+                self.update_filter_string(String::new(), library);
             }
 
             // Because by default egui loses focus on a TextEdit when the enter key is pressed
@@ -90,85 +172,347 @@ impl LibraryView {
             search_bar_response.request_focus();
         }
 
-        add_songs
+        action
     }
 
-    fn update_filter_string(&mut self, new_string: String) {
+    fn update_filter_string(&mut self, new_string: String, library: &Library) {
         self.filter_string = new_string;
 
-        let lowercase_filter = self.filter_string.to_lowercase();
-        self.filtered_items = self
-            .unfiltered_items
-            .iter()
-            .filter(|(_, song)| song.title.to_lowercase().contains(&lowercase_filter))
-            .cloned()
-            .collect();
+        match Query::parse(&self.filter_string) {
+            Ok(query) => {
+                self.query = query;
+                self.query_error = None;
+                // Best match first, so that is the one that is added when pressing enter.
+                // Unless the list is sorted, then it is the first one shown.
+                self.search(library);
+            }
+            Err(e) => {
+                self.query_error = Some(e);
+                self.filtered_items.clear();
+            }
+        }
+        self.folder_tree = None;
+        self.artist_view.clear();
     }
 
     /// Whether or not the library wants to be displayed or not.
     pub fn should_show_library(&self) -> bool {
-        !self.filter_string.is_empty()
+        self.browsing || !self.filter_string.is_empty()
+    }
+
+    /// The selected songs, in the order they are shown.
+    fn selected_songs(&self) -> Vec<SongId> {
+        self.filtered_items
+            .iter()
+            .filter(|id| self.selected_items.contains(id))
+            .copied()
+            .collect()
     }
 
-    /// Returns a list of songs to add to the playlist.
-    pub fn show_library(&mut self, ui: &mut Ui, image_cache: &ImageCache) -> Vec<SongId> {
-        let mut add_songs = Vec::new();
+    /// Click selects only the song, ctrl-click adds or removes it from the selection,
+    /// and shift-click selects everything from the previously clicked song up to this one.
+    fn select(&mut self, song_id: SongId, modifiers: Modifiers) {
+        let anchor_index = self
+            .selection_anchor
+            .and_then(|anchor| self.filtered_items.iter().position(|&id| id == anchor));
+        let clicked_index = self.filtered_items.iter().position(|&id| id == song_id);
+
+        if !modifiers.command {
+            self.selected_items.clear();
+        }
+
+        match (modifiers.shift, anchor_index, clicked_index) {
+            (true, Some(anchor_index), Some(clicked_index)) => {
+                let range = anchor_index.min(clicked_index)..=anchor_index.max(clicked_index);
+                self.selected_items
+                    .extend(self.filtered_items[range].iter().copied());
+                // The anchor stays, so the range can be changed with another shift-click.
+            }
+            _ => {
+                if !self.selected_items.remove(&song_id) {
+                    self.selected_items.insert(song_id);
+                }
+                self.selection_anchor = Some(song_id);
+            }
+        }
+    }
+
+    pub fn show_library(
+        &mut self,
+        ui: &mut Ui,
+        library: &Library,
+        image_cache: &ImageCache,
+        columns: &mut ColumnLayout,
+    ) -> LibraryAction {
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.mode, LibraryMode::List, "List");
+            ui.selectable_value(&mut self.mode, LibraryMode::Folders, "Folders");
+            ui.selectable_value(&mut self.mode, LibraryMode::Artists, "Artists");
+        });
+
+        match self.mode {
+            LibraryMode::List => self.show_list(ui, library, image_cache, columns),
+            LibraryMode::Folders => self.show_folders(ui, library),
+            LibraryMode::Artists => self.artist_view.show(ui, library, &self.filtered_items),
+        }
+    }
+
+    fn show_list(
+        &mut self,
+        ui: &mut Ui,
+        library: &Library,
+        image_cache: &ImageCache,
+        columns: &mut ColumnLayout,
+    ) -> LibraryAction {
+        let mut action = LibraryAction::None;
+
+        // The columns are shared, so the sort order can also change while the list isn't shown.
+        if columns.sort != self.sort {
+            self.sort = columns.sort;
+            self.update_items(library);
+        }
+
+        let selected_songs = self.selected_songs();
+        ui.horizontal(|ui| {
+            ui.label(format!("{} selected:", selected_songs.len()));
+            if let Some(position) = show_enqueue_buttons(ui, !selected_songs.is_empty()) {
+                action = LibraryAction::AddSongs {
+                    songs: selected_songs,
+                    position,
+                };
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label(format!("All {} results:", self.filtered_items.len()));
+            if let Some(position) = show_enqueue_buttons(ui, !self.filtered_items.is_empty()) {
+                action = LibraryAction::AddSongs {
+                    songs: self.filtered_items.clone(),
+                    position,
+                };
+            }
+        });
+        ui.separator();
+
+        let mut clicked_song = None;
 
         let text_style = egui::TextStyle::Body;
         let row_height = ui.text_style_height(&text_style);
+        let leading_width = BUTTON_WIDTH + row_height / 9.0 * 16.0;
 
-        egui::ScrollArea::both()
+        egui::ScrollArea::horizontal()
             .auto_shrink([false, false])
-            .show_rows(
-                ui,
-                row_height,
-                self.filtered_items.len(),
-                |ui, row_range| {
-                    egui::Grid::new("library_grid")
-                        .num_columns(3)
-                        .start_row(row_range.start)
-                        .min_col_width(1.0)
-                        .striped(true)
-                        .show(ui, |ui| {
-                            for (id, song) in self
+            .show(ui, |ui| {
+                if columns::show_column_headers(ui, columns, leading_width, row_height) {
+                    self.sort = columns.sort;
+                    self.update_items(library);
+                }
+
+                egui::ScrollArea::vertical()
+                    .auto_shrink([false, false])
+                    .show_rows(
+                        ui,
+                        row_height,
+                        self.filtered_items.len(),
+                        |ui, row_range| {
+                            for (row_index, &id) in self
                                 .filtered_items
                                 .iter()
+                                .enumerate()
                                 .skip(row_range.start)
-                                .take(row_range.end)
+                                .take(row_range.len())
                             {
-                                if self.show_song(ui, row_height, song, *id, image_cache) {
-                                    add_songs.push(*id);
+                                if let Some(song) = library.get_song(&id) {
+                                    let row_action = columns::show_row(ui, row_index, |ui| {
+                                        self.show_song(
+                                            ui,
+                                            row_height,
+                                            song,
+                                            id,
+                                            library,
+                                            image_cache,
+                                            columns,
+                                        )
+                                    });
+
+                                    match row_action {
+                                        SongRowAction::None => {}
+                                        SongRowAction::Add => {
+                                            action = LibraryAction::append(vec![id])
+                                        }
+                                        SongRowAction::Enqueue(position) => {
+                                            action = LibraryAction::AddSongs {
+                                                songs: vec![id],
+                                                position,
+                                            }
+                                        }
+                                        SongRowAction::Select => clicked_song = Some(id),
+                                    }
                                 }
                             }
-                        });
-                },
-            );
+                        },
+                    );
+            });
+
+        if let Some(song_id) = clicked_song {
+            let modifiers = ui.input().modifiers;
+            self.select(song_id, modifiers);
+        }
+
+        action
+    }
 
-        add_songs
+    fn show_folders(&mut self, ui: &mut Ui, library: &Library) -> LibraryAction {
+        let filtered_items = &self.filtered_items;
+        let folder_tree = self
+            .folder_tree
+            .get_or_insert_with(|| FolderTree::new(library, filtered_items.iter().copied()));
+
+        let mut action = LibraryAction::None;
+
+        egui::ScrollArea::both()
+            .auto_shrink([false, false])
+            .show(ui, |ui| {
+                for root in &folder_tree.roots {
+                    show_folder(ui, root, library, &mut action);
+                }
+            });
+
+        action
     }
 
-    /// Returns if the "add song to playlist" button is pressed or not.
+    #[allow(clippy::too_many_arguments)]
     fn show_song(
         &self,
         ui: &mut Ui,
         row_height: f32,
         song: &Song,
         song_id: SongId,
+        library: &Library,
         image_cache: &ImageCache,
-    ) -> bool {
-        let add_song = ui.button("+").clicked();
+        columns: &ColumnLayout,
+    ) -> SongRowAction {
+        let mut action = SongRowAction::None;
+
+        columns::cell(ui, BUTTON_WIDTH, row_height, |ui| {
+            if ui
+                .small_button("+")
+                .on_hover_text("Add to playlist")
+                .clicked()
+            {
+                action = SongRowAction::Add;
+            }
+        });
 
         // TODO: allow caching and retrieving pre-scaled versions of images.
-        if let Some(texture_handle) = image_cache.get_texture_handle(song_id) {
-            // TODO: get image width scaled relative to it's normal height, and not the target height.
-            ui.image(texture_handle, [row_height / 9.0 * 16.0, row_height]);
-        } else {
-            ui.label("");
-        }
+        columns::image_cell(ui, image_cache.get_texture_handle(song_id), row_height);
 
-        ui.label(&song.title).on_hover_text(&song.title);
+        let selected = self.selected_items.contains(&song_id);
+        let stats = library.song_stats(&song_id);
+        columns::show_cells(ui, columns, row_height, |ui, column| {
+            let text = column.text(song, stats);
+            let response = ui.selectable_label(selected, &text).on_hover_text(text);
+            if response.clicked() {
+                action = SongRowAction::Select;
+            }
+            response.context_menu(|ui| {
+                if let Some(position) = show_enqueue_buttons(ui, true) {
+                    action = SongRowAction::Enqueue(position);
+                    ui.close_menu();
+                }
+            });
+        });
 
-        ui.end_row();
-        add_song
+        action
     }
 }
+
+enum SongRowAction {
+    None,
+    Add,
+    Enqueue(EnqueuePosition),
+    Select,
+}
+
+/// Shows the folder as a collapsible header, with the folders and songs in it below.
+fn show_folder(ui: &mut Ui, folder: &Folder, library: &Library, action: &mut LibraryAction) {
+    let id = Id::new("library_folder").with(&folder.path);
+
+    CollapsingState::load_with_default_open(ui.ctx(), id, false)
+        .show_header(ui, |ui| {
+            if ui
+                .button("+")
+                .on_hover_text("Add folder to playlist")
+                .clicked()
+            {
+                *action = LibraryAction::append(folder.all_songs());
+            }
+
+            let label = Label::new(format!("{} ({})", folder.name, folder.song_count()))
+                .sense(Sense::click());
+            ui.add(label)
+                .on_hover_text("Right-click to add to the playlist in other ways")
+                .context_menu(|ui| {
+                    if let Some(position) = show_enqueue_buttons(ui, true) {
+                        *action = LibraryAction::AddSongs {
+                            songs: folder.all_songs(),
+                            position,
+                        };
+                        ui.close_menu();
+                    }
+                });
+        })
+        .body(|ui| {
+            for child in &folder.folders {
+                show_folder(ui, child, library, action);
+            }
+
+            for song in &folder.songs {
+                ui.horizontal(|ui| {
+                    if ui.button("+").on_hover_text("Add to playlist").clicked() {
+                        *action = LibraryAction::append(vec![song.id]);
+                    }
+
+                    let title = library
+                        .get_song(&song.id)
+                        .map_or(song.file_name.as_str(), |song| song.title.as_str());
+                    ui.label(title).on_hover_text(&song.file_name);
+                });
+            }
+        });
+}
+
+/// Returns where to add the songs, if one of the buttons is clicked.
+pub fn show_enqueue_buttons(ui: &mut Ui, enabled: bool) -> Option<EnqueuePosition> {
+    let mut position = None;
+
+    if ui
+        .add_enabled(enabled, Button::new("Add"))
+        .on_hover_text("Add to the end of the playlist")
+        .clicked()
+    {
+        position = Some(EnqueuePosition::Append);
+    }
+    if ui
+        .add_enabled(enabled, Button::new("Play next"))
+        .on_hover_text("Play after the song that is playing, without adding to the playlist")
+        .clicked()
+    {
+        position = Some(EnqueuePosition::Queue);
+    }
+    if ui
+        .add_enabled(enabled, Button::new("Insert next"))
+        .on_hover_text("Add to the playlist after the song that is playing")
+        .clicked()
+    {
+        position = Some(EnqueuePosition::Next);
+    }
+    if ui
+        .add_enabled(enabled, Button::new("Replace"))
+        .on_hover_text("Replace the playlist")
+        .clicked()
+    {
+        position = Some(EnqueuePosition::Replace);
+    }
+
+    position
+}
@@ -1,9 +1,14 @@
 use eframe::egui::Ui;
 
+pub mod artists;
+pub mod columns;
 pub mod config_ui;
 pub mod library;
+pub mod play_queue;
 pub mod playback_controls;
 pub mod playlist;
+pub mod playlist_files;
+pub mod playlist_tabs;
 
 pub fn time_label(ui: &mut Ui, seconds: u64) {
     let hours = seconds / 3600;
@@ -0,0 +1,66 @@
+use eframe::egui::{self, Button, Ui};
+use simple_music_lib::library::play_queue::PlayQueue;
+use simple_music_lib::library::Library;
+
+/// The songs that are played before the rest of the playlist.
+/// They can be moved up and down, or taken out of the queue.
+pub fn show_play_queue(ui: &mut Ui, queue: &mut PlayQueue, library: &Library) {
+    // The queue can only be changed once it is shown.
+    let mut move_song = None;
+    let mut remove_song = None;
+
+    ui.horizontal(|ui| {
+        ui.strong(format!("Play next ({})", queue.len()));
+        if ui.button("Clear").clicked() {
+            queue.clear();
+        }
+    });
+
+    egui::ScrollArea::vertical()
+        .auto_shrink([false, true])
+        .max_height(120.0)
+        .show(ui, |ui| {
+            let last_index = queue.len().saturating_sub(1);
+
+            for (index, song_id) in queue.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(index > 0, Button::new("⏶").small())
+                        .on_hover_text("Play earlier")
+                        .clicked()
+                    {
+                        move_song = Some((index, index - 1));
+                    }
+                    if ui
+                        .add_enabled(index < last_index, Button::new("⏷").small())
+                        .on_hover_text("Play later")
+                        .clicked()
+                    {
+                        move_song = Some((index, index + 1));
+                    }
+                    if ui
+                        .small_button("x")
+                        .on_hover_text("Remove from the queue")
+                        .clicked()
+                    {
+                        remove_song = Some(index);
+                    }
+
+                    match library.get_song(song_id) {
+                        Some(song) => match &song.artist {
+                            Some(artist) => ui.label(format!("{} - {}", artist, song.title)),
+                            None => ui.label(&song.title),
+                        },
+                        None => ui.weak("<song no longer in library>"),
+                    };
+                });
+            }
+        });
+
+    if let Some((from, target)) = move_song {
+        queue.move_song(from, target);
+    }
+    if let Some(index) = remove_song {
+        queue.remove(index);
+    }
+}
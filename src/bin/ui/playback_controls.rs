@@ -1,6 +1,7 @@
 use crate::egui::Response;
 use eframe::egui::{ImageButton, Slider, Ui, Widget};
 use egui_extras::RetainedImage;
+use simple_music_lib::library::RepeatMode;
 
 pub struct PlaybackControls {
     icons: PlaybackIcons,
@@ -19,6 +20,8 @@ impl PlaybackControls {
         paused: bool,
         volume: i64,
         infinite_playlist: &mut bool,
+        shuffle: &mut bool,
+        repeat_mode: &mut RepeatMode,
     ) -> Option<PlaybackCommand> {
         let mut command = None;
 
@@ -40,6 +43,16 @@ impl PlaybackControls {
         }
 
         ui.checkbox(infinite_playlist, "Infinite");
+        ui.checkbox(shuffle, "Shuffle")
+            .on_hover_text("Play the playlist in a random order, without changing it");
+
+        if ui
+            .button(repeat_mode_label(*repeat_mode))
+            .on_hover_text("Click to change what is played after a song ends")
+            .clicked()
+        {
+            *repeat_mode = repeat_mode.next();
+        }
 
         match volume {
             0..=33 => {
@@ -68,6 +81,14 @@ fn image_button(ui: &mut Ui, image: &RetainedImage) -> Response {
     ImageButton::new(image.texture_id(ui.ctx()), image.size_vec2()).ui(ui)
 }
 
+fn repeat_mode_label(repeat_mode: RepeatMode) -> &'static str {
+    match repeat_mode {
+        RepeatMode::Off => "Repeat: off",
+        RepeatMode::All => "Repeat: all",
+        RepeatMode::One => "Repeat: one",
+    }
+}
+
 #[derive(Copy, Clone)]
 pub enum PlaybackCommand {
     Pause,
@@ -1,13 +1,19 @@
 use crate::egui;
 use crate::egui::Color32;
-use egui::{CursorIcon, Grid, Id, RichText, Sense, Ui};
+use crate::ui::columns::{self, BUTTON_WIDTH};
+use egui::{CursorIcon, Id, Label, RichText, Sense, Ui};
 use simple_music_lib::image_cache::ImageCache;
+use simple_music_lib::library::columns::{Column, ColumnLayout};
 use simple_music_lib::library::{Library, ListEntryId, Playlist, SongId};
 
+const MISSING_SONG_TITLE: &str = "<song no longer in library>";
+
 pub enum PlaylistAction {
     None,
     PlaySong((ListEntryId, SongId)),
     RemoveSong(ListEntryId),
+    /// Play the song after the current one, through the play queue.
+    QueueSong(SongId),
 }
 
 pub struct PlaylistView {
@@ -26,10 +32,32 @@ impl PlaylistView {
         library: &Library,
         image_cache: &ImageCache,
         current_selected_entry: Option<(ListEntryId, SongId)>,
+        columns: &mut ColumnLayout,
     ) -> PlaylistAction {
         let mut action = PlaylistAction::None;
 
-        if !ui.memory().is_anything_being_dragged() {
+        if let Some(sort) = columns.sort {
+            // Sorting only changes how the playlist is shown, until it is applied.
+            ui.horizontal(|ui| {
+                ui.label(format!("Sorted by {}.", sort.column.name().to_lowercase()));
+                if ui
+                    .button("Apply order to playlist")
+                    .on_hover_text("Reorder the playlist itself, so songs play in this order")
+                    .clicked()
+                {
+                    playlist.sort(library, sort);
+                    columns.sort = None;
+                }
+                if ui.button("Show playlist order").clicked() {
+                    columns.sort = None;
+                }
+            });
+        }
+
+        // Entries can only be moved around when they are shown in the order of the playlist.
+        let can_drag = columns.sort.is_none();
+
+        if !ui.memory().is_anything_being_dragged() || !can_drag {
             self.dragged_item = None
         }
 
@@ -41,32 +69,48 @@ impl PlaylistView {
 
         let text_style = egui::TextStyle::Body;
         let row_height = ui.text_style_height(&text_style);
+        let leading_width = 2.0 * BUTTON_WIDTH + row_height / 9.0 * 16.0;
+
+        let mut entries: Vec<(usize, ListEntryId, SongId)> = playlist
+            .song_ids()
+            .enumerate()
+            .map(|(idx, &(list_id, song_id))| (idx, list_id, song_id))
+            .collect();
+        if let Some(sort) = columns.sort {
+            sort.sort(library, &mut entries, |&(_, _, song_id)| song_id);
+        }
 
-        egui::ScrollArea::both()
+        egui::ScrollArea::horizontal()
             .auto_shrink([false, false])
-            .show_rows(ui, row_height, playlist.song_count(), |ui, row_range| {
-                Grid::new("playlist_grid")
-                    .num_columns(4)
-                    .start_row(row_range.start)
-                    .min_col_width(1.0)
-                    .striped(true)
-                    .show(ui, |ui| {
-                        for (idx, &(list_id, song_id)) in playlist
-                            .song_ids()
+            .show(ui, |ui| {
+                columns::show_column_headers(ui, columns, leading_width, row_height);
+
+                egui::ScrollArea::vertical()
+                    .auto_shrink([false, false])
+                    .show_rows(ui, row_height, entries.len(), |ui, row_range| {
+                        for (row_index, &(idx, list_id, song_id)) in entries
+                            .iter()
                             .enumerate()
                             .skip(row_range.start)
-                            .take(row_range.end)
+                            .take(row_range.len())
                         {
-                            if let Some(song) = library.get_song(&song_id) {
+                            columns::show_row(ui, row_index, |ui| {
+                                // Songs can disappear from the library when it is rescanned.
+                                // Their entries are still shown, so it is clear what went missing.
+                                let song = library.get_song(&song_id);
+
                                 let id_source = "playlist_drag";
                                 let ui_id = Id::new(id_source).with(list_id);
 
-                                let rect = ui.label("::").rect;
+                                let rect = columns::cell(ui, BUTTON_WIDTH, row_height, |ui| {
+                                    ui.add_enabled(can_drag, Label::new("::")).rect
+                                });
                                 let response = ui.interact(rect, ui_id, Sense::drag());
 
-                                if response.drag_started() {
+                                if can_drag && response.drag_started() {
                                     self.dragged_item = Some((list_id, idx));
-                                } else if response.hovered()
+                                } else if can_drag
+                                    && response.hovered()
                                     && !ui.memory().is_anything_being_dragged()
                                 {
                                     ui.output().cursor_icon = CursorIcon::Grab;
@@ -84,44 +128,71 @@ impl PlaylistView {
                                     }
                                 }
 
-                                if ui.button("x").clicked() {
-                                    action = PlaylistAction::RemoveSong(list_id);
-                                }
+                                columns::cell(ui, BUTTON_WIDTH, row_height, |ui| {
+                                    if ui.small_button("x").clicked() {
+                                        action = PlaylistAction::RemoveSong(list_id);
+                                    }
+                                });
 
                                 // TODO: allow caching and retrieving pre-scaled versions of images.
-                                if let Some(texture_handle) =
-                                    image_cache.get_texture_handle(song_id)
-                                {
-                                    // TODO: get image width scaled relative to it's normal height, and not the target height.
-                                    ui.image(texture_handle, [row_height / 9.0 * 16.0, row_height]);
-                                } else {
-                                    ui.label("");
-                                }
+                                columns::image_cell(
+                                    ui,
+                                    image_cache.get_texture_handle(song_id),
+                                    row_height,
+                                );
+
+                                let is_dragged = matches!(
+                                    self.dragged_item,
+                                    Some((dragged_id, _)) if dragged_id == list_id
+                                );
+                                let is_current = Some((list_id, song_id)) == current_selected_entry;
+                                let stats = library.song_stats(&song_id);
+
+                                columns::show_cells(ui, columns, row_height, |ui, column| {
+                                    let text = match song {
+                                        Some(song) => column.text(song, stats),
+                                        None if column == Column::Title => {
+                                            MISSING_SONG_TITLE.to_string()
+                                        }
+                                        None => String::new(),
+                                    };
 
-                                let mut label_text = RichText::new(&song.title);
-                                if let Some((dragged_id, _)) = self.dragged_item {
-                                    if list_id == dragged_id {
+                                    let mut label_text = RichText::new(&text);
+                                    if is_dragged {
                                         label_text = label_text
                                             .color(ui.style().interact(&response).text_color());
                                     }
-                                }
 
-                                if Some((list_id, song_id)) == current_selected_entry {
-                                    label_text = label_text.color(Color32::LIGHT_BLUE);
-                                }
+                                    if song.is_none() {
+                                        label_text = label_text.color(Color32::RED);
+                                    } else if is_current {
+                                        label_text = label_text.color(Color32::LIGHT_BLUE);
+                                    }
 
-                                let label = egui::Label::new(label_text).sense(Sense::click());
-                                if ui
-                                    .add(label)
-                                    .on_hover_cursor(CursorIcon::PointingHand)
-                                    .on_hover_text(&song.title)
-                                    .clicked()
-                                    && Some((list_id, song_id)) != current_selected_entry
-                                {
-                                    action = PlaylistAction::PlaySong((list_id, song_id));
-                                }
-                                ui.end_row();
-                            }
+                                    let label = egui::Label::new(label_text).sense(Sense::click());
+                                    let response = ui
+                                        .add(label)
+                                        .on_hover_cursor(CursorIcon::PointingHand)
+                                        .on_hover_text(text);
+                                    if response.clicked() && song.is_some() && !is_current {
+                                        action = PlaylistAction::PlaySong((list_id, song_id));
+                                    }
+                                    if song.is_some() {
+                                        response.context_menu(|ui| {
+                                            if ui
+                                                .button("Play next")
+                                                .on_hover_text(
+                                                    "Play after the song that is playing",
+                                                )
+                                                .clicked()
+                                            {
+                                                action = PlaylistAction::QueueSong(song_id);
+                                                ui.close_menu();
+                                            }
+                                        });
+                                    }
+                                });
+                            });
                         }
                     });
             });
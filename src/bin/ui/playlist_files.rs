@@ -0,0 +1,102 @@
+use eframe::egui::{Context, Grid, ScrollArea, Ui, Window};
+use rfd::FileDialog;
+use simple_music_lib::library::playlist_file::{PlaylistFileEntry, PlaylistFormat};
+use std::path::PathBuf;
+
+pub enum PlaylistFileAction {
+    None,
+    Load(PathBuf),
+    Save(PathBuf),
+}
+
+/// Loading and saving playlists as files.
+#[derive(Default)]
+pub struct PlaylistFilesView {
+    /// The last loaded playlist file, and its entries that were not found in the library.
+    /// Shown in a window until it is closed.
+    missing_songs: Option<(PathBuf, Vec<PlaylistFileEntry>)>,
+}
+
+impl PlaylistFilesView {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The playlist is saved under its own name by default.
+    pub fn show_buttons(&mut self, ui: &mut Ui, playlist_name: &str) -> PlaylistFileAction {
+        let mut action = PlaylistFileAction::None;
+
+        if ui
+            .button("Open playlist")
+            .on_hover_text("Open a playlist file as a new playlist")
+            .clicked()
+        {
+            if let Some(path) = FileDialog::new()
+                .add_filter("Playlist", &PlaylistFormat::EXTENSIONS)
+                .pick_file()
+            {
+                action = PlaylistFileAction::Load(path);
+            }
+        }
+
+        if ui.button("Save playlist").clicked() {
+            if let Some(path) = FileDialog::new()
+                .add_filter("M3U playlist", &["m3u8", "m3u"])
+                .add_filter("XSPF playlist", &["xspf"])
+                .add_filter("PLS playlist", &["pls"])
+                .set_file_name(&format!("{}.m3u8", playlist_name))
+                .save_file()
+            {
+                action = PlaylistFileAction::Save(path);
+            }
+        }
+
+        action
+    }
+
+    /// Lets the user know which songs of a loaded playlist file were left out.
+    pub fn report_missing_songs(&mut self, path: PathBuf, missing: Vec<PlaylistFileEntry>) {
+        self.missing_songs = if missing.is_empty() {
+            None
+        } else {
+            Some((path, missing))
+        };
+    }
+
+    pub fn show_missing_songs(&mut self, ctx: &Context) {
+        let (path, missing) = match &self.missing_songs {
+            Some(missing_songs) => missing_songs,
+            None => return,
+        };
+
+        let mut open = true;
+
+        Window::new("Songs not in the library")
+            .collapsible(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "{} songs of '{}' are not in the library, they were left out:",
+                    missing.len(),
+                    path.display()
+                ));
+
+                ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                    Grid::new("missing_songs_grid")
+                        .striped(true)
+                        .num_columns(2)
+                        .show(ui, |ui| {
+                            for entry in missing {
+                                ui.label(entry.title.as_deref().unwrap_or_default());
+                                ui.label(entry.path.display().to_string());
+                                ui.end_row();
+                            }
+                        });
+                });
+            });
+
+        if !open {
+            self.missing_songs = None;
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use eframe::egui::{Button, Key, TextEdit, Ui};
+use simple_music_lib::library::playlists::{PlaylistId, Playlists};
+
+pub enum PlaylistTabsAction {
+    None,
+    /// The playlist should be removed. Left to the caller, since it might be playing.
+    Remove(PlaylistId),
+}
+
+/// A tab for every playlist, to choose which one is shown.
+#[derive(Default)]
+pub struct PlaylistTabs {
+    /// The playlist whose tab is being renamed.
+    renaming: Option<Renaming>,
+}
+
+struct Renaming {
+    id: PlaylistId,
+    /// The name typed so far.
+    name: String,
+    /// The text field only gets focus when renaming starts,
+    /// so clicking somewhere else can end the renaming.
+    focused: bool,
+}
+
+impl PlaylistTabs {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Double-clicking a tab renames it, right-clicking it shows what else can be done with it.
+    pub fn show(
+        &mut self,
+        ui: &mut Ui,
+        playlists: &mut Playlists,
+        shown: &mut PlaylistId,
+        playing: Option<PlaylistId>,
+    ) -> PlaylistTabsAction {
+        let mut action = PlaylistTabsAction::None;
+        // These change the playlists, so they are applied once the tabs are shown.
+        let mut rename = None;
+        let mut duplicate = None;
+        let can_remove = playlists.len() > 1;
+
+        ui.horizontal_wrapped(|ui| {
+            for named in playlists.iter() {
+                let id = named.id();
+
+                if let Some(renaming) = self.renaming.as_mut().filter(|r| r.id == id) {
+                    let response =
+                        ui.add(TextEdit::singleline(&mut renaming.name).desired_width(120.0));
+
+                    if !renaming.focused {
+                        response.request_focus();
+                        renaming.focused = true;
+                    } else if response.lost_focus() {
+                        // Escape cancels, enter or clicking somewhere else keeps the new name.
+                        if !ui.input().key_pressed(Key::Escape) {
+                            rename = Some((id, renaming.name.clone()));
+                        }
+                        self.renaming = None;
+                    }
+                    continue;
+                }
+
+                let text = if Some(id) == playing {
+                    format!("▶ {}", named.name)
+                } else {
+                    named.name.clone()
+                };
+
+                let response = ui
+                    .selectable_label(*shown == id, text)
+                    .on_hover_text("Double-click to rename, right-click for more");
+                if response.clicked() {
+                    *shown = id;
+                }
+                if response.double_clicked() {
+                    self.start_renaming(id, &named.name);
+                }
+
+                response.context_menu(|ui| {
+                    if ui.button("Rename").clicked() {
+                        self.start_renaming(id, &named.name);
+                        ui.close_menu();
+                    }
+                    if ui.button("Duplicate").clicked() {
+                        duplicate = Some(id);
+                        ui.close_menu();
+                    }
+                    if ui
+                        .add_enabled(can_remove, Button::new("Delete"))
+                        .on_disabled_hover_text("The last playlist can't be deleted")
+                        .clicked()
+                    {
+                        action = PlaylistTabsAction::Remove(id);
+                        ui.close_menu();
+                    }
+                });
+            }
+
+            if ui.button("+").on_hover_text("New playlist").clicked() {
+                *shown = playlists.create(Playlists::DEFAULT_NAME);
+            }
+        });
+
+        if let Some((id, name)) = rename {
+            playlists.rename(id, &name);
+        }
+        if let Some(id) = duplicate {
+            if let Some(copy) = playlists.duplicate(id) {
+                *shown = copy;
+            }
+        }
+
+        action
+    }
+
+    fn start_renaming(&mut self, id: PlaylistId, name: &str) {
+        self.renaming = Some(Renaming {
+            id,
+            name: name.to_string(),
+            focused: false,
+        });
+    }
+}
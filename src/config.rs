@@ -1,12 +1,35 @@
+use crate::library::columns::ColumnLayout;
+use crate::library::{LibraryRoot, RepeatMode, ScanOptions};
 use serde_derive::{Deserialize, Serialize};
 use std::path::PathBuf;
 
-#[derive(Deserialize, Serialize, Default)]
+#[derive(Deserialize, Serialize)]
 // Auto fill properties with their defaults if they are missing.
 // Allows properties to be added to future versions without breaking the configs.
 #[serde(default)]
 pub struct Config {
-    pub library_directory: PathBuf,
+    /// Directories that are scanned for songs. Their songs are merged into one library.
+    pub library_roots: Vec<LibraryRoot>,
+    /// Older versions only had a single library directory.
+    /// Only read, and turned into a library root by [migrate](Config::migrate).
+    #[serde(skip_serializing)]
+    library_directory: PathBuf,
+    /// File extensions (without the leading dot) that are recognized as songs.
+    /// These are matched case-insensitively.
+    #[serde(default = "default_song_extensions")]
+    pub song_extensions: Vec<String>,
+    /// Keep the library up to date with files that are added, changed or removed
+    /// in the library directory while the program is running.
+    #[serde(default = "default_true")]
+    pub watch_library_directory: bool,
+    /// Recognize songs that are moved to another path, by the contents of their file.
+    /// This makes scanning a lot slower, because every file has to be read completely.
+    pub recognize_moved_songs: bool,
+    /// Also scan directories whose name starts with a dot. These are skipped by default.
+    pub scan_hidden_directories: bool,
+    /// Follow symbolic links to files and directories while scanning.
+    /// Songs that can be reached through several links are only added once.
+    pub follow_symlinks: bool,
     /// An infinite playlist automatically adds and removes songs when it reaches near the end.
     pub infinite_playlist: bool,
     /// How many songs an infinite playlist should keep in buffer in front of the current song.
@@ -16,6 +39,75 @@ pub struct Config {
     /// before removing them from the playlist.
     #[serde(default = "default_infinite_buffer")]
     pub infinite_playlist_song_rear_buffer: u32,
+    /// Play the songs of the playlist in a shuffled order, without reordering the playlist.
+    pub shuffle: bool,
+    /// What to play after the last song of the playlist, or after every song.
+    pub repeat_mode: RepeatMode,
+    /// Write the paths of songs in saved playlist files relative to the playlist file,
+    /// instead of absolute.
+    #[serde(default = "default_true")]
+    pub relative_playlist_paths: bool,
+    /// Columns of the list of songs in the library.
+    pub library_columns: ColumnLayout,
+    /// Columns of the playlist. Sorting it only changes how it is shown,
+    /// until the order is applied to the playlist itself.
+    pub playlist_columns: ColumnLayout,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            library_roots: Vec::new(),
+            library_directory: PathBuf::new(),
+            song_extensions: default_song_extensions(),
+            watch_library_directory: default_true(),
+            recognize_moved_songs: false,
+            scan_hidden_directories: false,
+            follow_symlinks: false,
+            infinite_playlist: false,
+            infinite_playlist_song_buffer: default_infinite_buffer(),
+            infinite_playlist_song_rear_buffer: default_infinite_buffer(),
+            shuffle: false,
+            repeat_mode: RepeatMode::default(),
+            relative_playlist_paths: default_true(),
+            library_columns: ColumnLayout::default(),
+            playlist_columns: ColumnLayout::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Brings a config that was saved by an older version of the program up to date.
+    pub fn migrate(&mut self) {
+        if !self.library_directory.as_os_str().is_empty() {
+            if self.library_roots.is_empty() {
+                self.library_roots
+                    .push(LibraryRoot::new(self.library_directory.clone()));
+            }
+            self.library_directory = PathBuf::new();
+        }
+    }
+
+    pub fn scan_options(&self) -> ScanOptions {
+        ScanOptions {
+            extensions: self.song_extensions.clone(),
+            hash_contents: self.recognize_moved_songs,
+            scan_hidden_directories: self.scan_hidden_directories,
+            follow_symlinks: self.follow_symlinks,
+        }
+    }
+}
+
+fn default_song_extensions() -> Vec<String> {
+    // All of these can be played by mpv.
+    ["ogg", "opus", "mp3", "flac", "m4a", "wav"]
+        .iter()
+        .map(|&extension| extension.to_owned())
+        .collect()
+}
+
+fn default_true() -> bool {
+    true
 }
 
 fn default_infinite_buffer() -> u32 {
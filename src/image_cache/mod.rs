@@ -3,79 +3,59 @@ use eframe::egui::{ColorImage, Context, TextureFilter, TextureHandle};
 use log::{debug, warn};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
 
-#[derive(Default)]
 pub struct ImageCache {
     images: HashMap<SongId, TextureHandle>,
+    /// Images that were loaded in the background, and have not been added to the cache yet.
+    loaded_images: Receiver<(SongId, TextureHandle)>,
+    loaded_images_sender: Sender<(SongId, TextureHandle)>,
 }
 
 impl ImageCache {
     pub fn new() -> ImageCache {
-        Default::default()
+        let (loaded_images_sender, loaded_images) = channel();
+
+        Self {
+            images: HashMap::new(),
+            loaded_images,
+            loaded_images_sender,
+        }
     }
 
     /// Searches for an image in the same folder as the song, and with the same name.
     /// Loads it if it finds it, and adds it to the cache.
     /// The handle can later be retrieved with the [SongId] using [get_texture_handle](ImageCache::get_texture_handle)
-    /// TODO: Load in the background.
     /// TODO: Allow specifying resizing the image, and caching those smaller images on disk.
     /// TODO: If there is already an image in the cache for this song, how do we make egui drop the texture for it?
     pub fn load_image_from_song_path(&mut self, ctx: &Context, song_path: &Path, song_id: SongId) {
-        let image_extensions = ["jpg", "png", "webp"];
-        let file_name = match song_path.file_stem() {
-            Some(value) => value,
-            None => return, // Can't load an image if we don't have a name to look for. TODO: add logging.
-        };
-        let directory = match song_path.parent() {
-            Some(value) => value,
-            None => return, // Can't load an image if we don't have a directory to look in. TODO: add logging.
-        };
-
-        let image_base_path = PathBuf::from(directory).join(file_name);
-
-        let image_data = {
-            let mut data = None;
-
-            for extension in image_extensions {
-                let full_path = image_base_path.with_extension(extension);
-
-                if !full_path.exists() {
-                    // No image with this extensions, try the next extension.
-                    continue;
-                }
+        if let Some(texture) = load_song_image(ctx, song_path) {
+            self.images.insert(song_id, texture);
+        }
+    }
+
+    /// Does the same as [load_image_from_song_path](ImageCache::load_image_from_song_path),
+    /// but on another thread so it does not block the UI.
+    /// The image is added to the cache by the first call to
+    /// [receive_loaded_images](ImageCache::receive_loaded_images) after it is loaded.
+    pub fn load_image_in_background(&self, ctx: &Context, song_path: &Path, song_id: SongId) {
+        let ctx = ctx.clone();
+        let song_path = song_path.to_path_buf();
+        let sender = self.loaded_images_sender.clone();
 
-                match image::open(&full_path) {
-                    Ok(img) => {
-                        let img = img.to_rgba8();
-                        let pixels = img.as_flat_samples();
-                        let size = [img.width() as _, img.height() as _];
-
-                        data = Some(ColorImage::from_rgba_unmultiplied(size, pixels.as_slice()));
-
-                        // Found an image with this extension,
-                        // no need to look for the other extensions.
-                        break;
-                    }
-                    Err(e) => {
-                        warn!("Cannot open image `{}`: {}", full_path.display(), e)
-                    }
+        rayon::spawn(move || {
+            if let Some(texture) = load_song_image(&ctx, &song_path) {
+                if sender.send((song_id, texture)).is_ok() {
+                    ctx.request_repaint();
                 }
             }
+        });
+    }
 
-            data
-        };
-
-        if let Some(data) = image_data {
-            self.images.insert(
-                song_id,
-                ctx.load_texture(file_name.to_string_lossy(), data, TextureFilter::Linear),
-            );
-        } else {
-            debug!(
-                "No image found for song `{}` in directory `{}`",
-                file_name.to_string_lossy(),
-                directory.display()
-            );
+    /// Adds the images that were loaded in the background to the cache.
+    pub fn receive_loaded_images(&mut self) {
+        while let Ok((song_id, texture)) = self.loaded_images.try_recv() {
+            self.images.insert(song_id, texture);
         }
     }
 
@@ -83,3 +63,62 @@ impl ImageCache {
         self.images.get(&song_id)
     }
 }
+
+impl Default for ImageCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn load_song_image(ctx: &Context, song_path: &Path) -> Option<TextureHandle> {
+    let image_extensions = ["jpg", "png", "webp"];
+    // Can't load an image if we don't have a name to look for. TODO: add logging.
+    let file_name = song_path.file_stem()?;
+    // Can't load an image if we don't have a directory to look in. TODO: add logging.
+    let directory = song_path.parent()?;
+
+    let image_base_path = PathBuf::from(directory).join(file_name);
+
+    let image_data = {
+        let mut data = None;
+
+        for extension in image_extensions {
+            let full_path = image_base_path.with_extension(extension);
+
+            if !full_path.exists() {
+                // No image with this extensions, try the next extension.
+                continue;
+            }
+
+            match image::open(&full_path) {
+                Ok(img) => {
+                    let img = img.to_rgba8();
+                    let pixels = img.as_flat_samples();
+                    let size = [img.width() as _, img.height() as _];
+
+                    data = Some(ColorImage::from_rgba_unmultiplied(size, pixels.as_slice()));
+
+                    // Found an image with this extension,
+                    // no need to look for the other extensions.
+                    break;
+                }
+                Err(e) => {
+                    warn!("Cannot open image `{}`: {}", full_path.display(), e)
+                }
+            }
+        }
+
+        data
+    };
+
+    if let Some(data) = image_data {
+        Some(ctx.load_texture(file_name.to_string_lossy(), data, TextureFilter::Linear))
+    } else {
+        debug!(
+            "No image found for song `{}` in directory `{}`",
+            file_name.to_string_lossy(),
+            directory.display()
+        );
+        None
+    }
+}
@@ -1,43 +1,118 @@
 use anyhow::Result;
+use serde_derive::{Deserialize, Serialize};
 use std::collections::hash_map::Iter;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
 use std::path::{Path, PathBuf};
-
-use log::warn;
-
-const SONG_EXTENSION: &str = ".ogg";
-
-#[derive(Debug)]
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::time::{Duration, SystemTime};
+
+use columns::SortOrder;
+use log::{debug, warn};
+use query::Query;
+use scanner::{find_song_files, has_song_extension, RootFilter};
+use search_index::SearchIndex;
+
+pub mod background_scan;
+pub mod cache;
+pub mod collation;
+pub mod columns;
+pub mod folder_tree;
+pub mod grouping;
+pub mod play_queue;
+pub mod playlist_file;
+pub mod playlist_position;
+pub mod playlists;
+pub mod query;
+pub mod scanner;
+pub mod search;
+mod search_index;
+pub mod shuffle;
+pub mod tags;
+pub mod watcher;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(from = "StoredLibrary")]
 pub struct Library {
     songs: HashMap<SongId, Song>,
-    /// Next id to use when inserting a new entry.
-    next_id: SongId,
+    /// Only for songs that are in the library. Kept when a song is read again or moved.
+    stats: HashMap<SongId, SongStats>,
+    /// Kept up to date with the songs. It is not stored, but built again when loading the library.
+    #[serde(skip)]
+    search_index: SearchIndex,
+}
+
+/// What is stored of a [Library].
+#[derive(Deserialize)]
+struct StoredLibrary {
+    songs: HashMap<SongId, Song>,
+    stats: HashMap<SongId, SongStats>,
+}
+
+impl From<StoredLibrary> for Library {
+    fn from(stored: StoredLibrary) -> Self {
+        let mut library = Library::new();
+        for (id, song) in stored.songs {
+            library.insert_song(id, song);
+        }
+        library.stats = stored.stats;
+        library
+    }
 }
 
 impl Library {
     pub fn new() -> Self {
         Self {
             songs: HashMap::new(),
-            next_id: SongId(0),
+            stats: HashMap::new(),
+            search_index: SearchIndex::default(),
         }
     }
 
     pub fn clear(&mut self) {
         self.songs.clear();
+        self.stats.clear();
+        self.search_index.clear();
     }
 
     pub fn songs(&self) -> Iter<'_, SongId, Song> {
         self.songs.iter()
     }
 
+    /// The id is derived from the song's path relative to its root,
+    /// so the same file gets the same id every time it is added.
     pub fn add_song(&mut self, song: Song) -> SongId {
-        let id = self.next_id;
-        self.songs.insert(id, song);
-        self.next_id = self.next_id.next();
+        let relative_path = song.path.strip_prefix(&song.root).unwrap_or(&song.path);
+        let mut id = SongId::from_path(relative_path);
+
+        // Two different paths resulting in the same id is very unlikely, but not impossible.
+        while self.songs.contains_key(&id) {
+            id = id.next();
+        }
 
+        self.insert_song(id, song);
+        self.stats.insert(
+            id,
+            SongStats {
+                date_added: Some(SystemTime::now()),
+                play_count: 0,
+            },
+        );
         id
     }
 
+    /// Adds or replaces the song, and keeps the search index up to date.
+    fn insert_song(&mut self, id: SongId, song: Song) {
+        self.search_index.insert(id, &song);
+        self.songs.insert(id, song);
+    }
+
+    fn remove_song(&mut self, id: &SongId) -> Option<Song> {
+        self.search_index.remove(*id);
+        self.stats.remove(id);
+        self.songs.remove(id)
+    }
+
     pub fn add_songs(&mut self, songs: Vec<Song>) {
         for song in songs {
             self.add_song(song);
@@ -52,6 +127,289 @@ impl Library {
         self.songs.get(id)
     }
 
+    /// Empty stats if the song is not in the library.
+    pub fn song_stats(&self, id: &SongId) -> SongStats {
+        self.stats.get(id).copied().unwrap_or_default()
+    }
+
+    /// Counts that the song was played. Does nothing if the song is not in the library.
+    pub fn record_play(&mut self, id: &SongId) {
+        if self.songs.contains_key(id) {
+            self.stats.entry(*id).or_default().play_count += 1;
+        }
+    }
+
+    /// The songs matching the query, best matches first.
+    /// Equally good matches, and all songs for an empty query, are sorted by title,
+    /// with [collation::SortKey].
+    /// Uses an index, so only the songs that can match are looked at.
+    pub fn search(&self, query: &Query) -> Vec<SongId> {
+        self.search_index.search(query, &self.songs)
+    }
+
+    /// Groups the songs by artist, and then by album, using their tags.
+    /// The album artist is used when it is known, so compilations stay together.
+    /// Songs that are not in the library are left out.
+    pub fn group_by_artist(
+        &self,
+        song_ids: impl IntoIterator<Item = SongId>,
+    ) -> Vec<grouping::ArtistGroup> {
+        grouping::group_by_artist(self, song_ids)
+    }
+
+    /// Brings the library up to date with the song files in the enabled roots.
+    /// New files are added, and songs whose file is no longer found are removed.
+    /// Changed files are read again, and keep their id.
+    /// Songs whose file did not change are kept as is, without reading the file again.
+    ///
+    /// Songs of a root or directory that cannot be scanned (like an external drive that
+    /// is not connected) are kept, and a warning is added to the summary.
+    pub fn rescan(&mut self, roots: &[LibraryRoot], options: &ScanOptions) -> RescanSummary {
+        let mut summary = RescanSummary::default();
+        let mut ids_by_path = self.ids_by_path();
+        let mut not_found: HashSet<PathBuf> = ids_by_path.keys().cloned().collect();
+
+        for root in roots.iter().filter(|root| root.enabled) {
+            let filter = RootFilter::new(root);
+
+            match find_song_files(&root.path, &filter, options) {
+                Ok(found) => {
+                    // Songs in directories that can't be read right now are kept.
+                    not_found.retain(|path| {
+                        !found
+                            .unreadable_dirs
+                            .iter()
+                            .any(|dir| path.starts_with(dir))
+                    });
+                    summary.warnings.extend(found.warnings);
+
+                    for path in found.files {
+                        not_found.remove(&path);
+                        self.update_song_file(
+                            &path,
+                            &root.path,
+                            options,
+                            &mut ids_by_path,
+                            &mut summary,
+                        );
+                    }
+                }
+                Err(e) => {
+                    not_found.retain(|path| !path.starts_with(&root.path));
+                    summary.warnings.push(e.to_string());
+                }
+            }
+        }
+
+        mark_removed(not_found, &mut ids_by_path, &mut summary);
+        self.remove_or_move_songs(&mut summary);
+
+        summary
+    }
+
+    /// Brings only the given paths up to date, for example after the file system reported
+    /// changes to them. Paths can be song files or directories, and they may no longer exist.
+    /// Songs in or below a path that no longer exists are removed.
+    /// A changed ignore file causes its whole directory to be brought up to date.
+    pub fn update_paths(
+        &mut self,
+        paths: &[PathBuf],
+        roots: &[LibraryRoot],
+        options: &ScanOptions,
+    ) -> RescanSummary {
+        let mut summary = RescanSummary::default();
+        let mut ids_by_path = self.ids_by_path();
+
+        let paths = paths.iter().map(|path| {
+            let is_ignore_file = path.file_name().is_some_and(|name| {
+                name == scanner::IGNORE_FILE_NAME || name == scanner::NO_MEDIA_FILE_NAME
+            });
+            match (is_ignore_file, path.parent()) {
+                (true, Some(dir)) => dir,
+                _ => path.as_path(),
+            }
+        });
+
+        for path in paths {
+            // When roots are nested, the innermost one decides what is included.
+            let root = roots
+                .iter()
+                .filter(|root| root.enabled && path.starts_with(&root.path))
+                .max_by_key(|root| root.path.components().count());
+
+            if path.is_file() {
+                let is_song = match root {
+                    Some(root) => {
+                        has_song_extension(path, &options.extensions)
+                            && RootFilter::new(root).accepts(path)
+                            && !scanner::is_ignored(path, &root.path, options)
+                    }
+                    None => false,
+                };
+
+                if let (true, Some(root)) = (is_song, root) {
+                    self.update_song_file(
+                        path,
+                        &root.path,
+                        options,
+                        &mut ids_by_path,
+                        &mut summary,
+                    );
+                } else if let Some(id) = ids_by_path.remove(path) {
+                    summary.removed.push(id);
+                }
+                continue;
+            }
+
+            // The path is a directory, or it no longer exists.
+            let mut not_found: HashSet<PathBuf> = ids_by_path
+                .keys()
+                .filter(|song_path| song_path.starts_with(path))
+                .cloned()
+                .collect();
+
+            if let (true, Some(root)) = (path.is_dir(), root) {
+                match find_song_files(path, &RootFilter::new(root), options) {
+                    Ok(found) => {
+                        not_found.retain(|path| {
+                            !found
+                                .unreadable_dirs
+                                .iter()
+                                .any(|dir| path.starts_with(dir))
+                        });
+                        summary.warnings.extend(found.warnings);
+
+                        for file in found.files {
+                            not_found.remove(&file);
+                            self.update_song_file(
+                                &file,
+                                &root.path,
+                                options,
+                                &mut ids_by_path,
+                                &mut summary,
+                            );
+                        }
+                    }
+                    Err(e) => {
+                        summary.warnings.push(e.to_string());
+                        continue;
+                    }
+                }
+            }
+
+            mark_removed(not_found, &mut ids_by_path, &mut summary);
+        }
+
+        // Done at the very end, because the removed and added halves of a move
+        // can be reported as separate paths.
+        self.remove_or_move_songs(&mut summary);
+
+        summary
+    }
+
+    fn ids_by_path(&self) -> HashMap<PathBuf, SongId> {
+        self.songs
+            .iter()
+            .map(|(&id, song)| (song.path.clone(), id))
+            .collect()
+    }
+
+    /// Reads the song file if it is new, or if it changed since it was last read.
+    fn update_song_file(
+        &mut self,
+        path: &Path,
+        root: &Path,
+        options: &ScanOptions,
+        ids_by_path: &mut HashMap<PathBuf, SongId>,
+        summary: &mut RescanSummary,
+    ) {
+        if let Some(id) = ids_by_path.get(path) {
+            let unchanged = match FileStamp::read(path) {
+                Ok(stamp) => self.songs[id].file_stamp == stamp,
+                Err(_) => false,
+            };
+
+            if unchanged {
+                summary.unchanged += 1;
+                return;
+            }
+        }
+
+        let song = song_from_file_path(path, root, options);
+        self.apply_read_song(path, song, ids_by_path, summary);
+    }
+
+    /// Puts a song that was just read from the file at `path` into the library.
+    /// `None` means the file could not be read as a song,
+    /// so if it was in the library, it is marked as removed.
+    fn apply_read_song(
+        &mut self,
+        path: &Path,
+        song: Option<Song>,
+        ids_by_path: &mut HashMap<PathBuf, SongId>,
+        summary: &mut RescanSummary,
+    ) {
+        match (ids_by_path.get(path).copied(), song) {
+            (Some(id), Some(song)) => {
+                self.insert_song(id, song);
+                summary.updated.push(id);
+            }
+            (Some(id), None) => {
+                ids_by_path.remove(path);
+                summary.removed.push(id);
+            }
+            (None, Some(song)) => {
+                let id = self.add_song(song);
+                ids_by_path.insert(path.to_path_buf(), id);
+                summary.added.push(id);
+            }
+            (None, None) => {}
+        }
+    }
+
+    /// Removes the songs that are marked as removed in the summary.
+    /// If a removed song has the same contents as an added song, it was moved instead.
+    /// In that case the song gets the id of its new path, so a file that later shows up
+    /// at the old path gets the old id. The stats are kept, and the summary tells
+    /// which id replaced which, so everything that refers to the song can follow it.
+    fn remove_or_move_songs(&mut self, summary: &mut RescanSummary) {
+        let mut added_by_hash: HashMap<u64, SongId> = summary
+            .added
+            .iter()
+            .filter_map(|id| {
+                self.songs
+                    .get(id)
+                    .and_then(|song| song.content_hash)
+                    .map(|hash| (hash, *id))
+            })
+            .collect();
+
+        for removed_id in std::mem::take(&mut summary.removed) {
+            let moved_to = self
+                .songs
+                .get(&removed_id)
+                .and_then(|song| song.content_hash)
+                .and_then(|hash| added_by_hash.remove(&hash));
+
+            match moved_to {
+                Some(added_id) => {
+                    let stats = self.stats.get(&removed_id).copied();
+                    self.remove_song(&removed_id);
+                    if let Some(stats) = stats {
+                        self.stats.insert(added_id, stats);
+                    }
+
+                    summary.added.retain(|&id| id != added_id);
+                    summary.moved.push((removed_id, added_id));
+                }
+                None => {
+                    self.remove_song(&removed_id);
+                    summary.removed.push(removed_id);
+                }
+            }
+        }
+    }
+
     pub fn get_random_song_id(&self) -> Option<&SongId> {
         // TODO: This is not the fastest implementation,
         //  since it needs to iter over a lot of the library.
@@ -67,50 +425,228 @@ impl Default for Library {
     }
 }
 
-#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
-pub struct SongId(usize);
+/// What changed in the library during a [rescan](Library::rescan).
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+pub struct RescanSummary {
+    pub added: Vec<SongId>,
+    pub removed: Vec<SongId>,
+    /// Songs whose file changed, and which were read again.
+    pub updated: Vec<SongId>,
+    /// Songs whose file was moved to another path, as the old and the new id.
+    /// The new id is the one of the new path. The songs keep their stats.
+    /// Only detected when [ScanOptions::hash_contents] is enabled.
+    pub moved: Vec<(SongId, SongId)>,
+    /// Amount of songs whose file did not change.
+    pub unchanged: usize,
+    /// Problems that did not stop the scan, like a root that could not be read.
+    pub warnings: Vec<String>,
+}
+
+impl RescanSummary {
+    pub fn has_changes(&self) -> bool {
+        !self.added.is_empty()
+            || !self.removed.is_empty()
+            || !self.updated.is_empty()
+            || !self.moved.is_empty()
+    }
+
+    /// The id the song has after these changes.
+    /// Only differs from the given id for songs that were moved.
+    pub fn current_id(&self, id: SongId) -> SongId {
+        self.moved
+            .iter()
+            .fold(id, |id, &(old, new)| if id == old { new } else { id })
+    }
+
+    /// Adds the changes of a later summary to this one.
+    pub fn extend(&mut self, other: RescanSummary) {
+        self.added.extend(other.added);
+        self.removed.extend(other.removed);
+        self.updated.extend(other.updated);
+        self.moved.extend(other.moved);
+        self.unchanged += other.unchanged;
+        self.warnings.extend(other.warnings);
+    }
+}
+
+/// Marks the songs with the given paths as removed in the summary.
+fn mark_removed(
+    paths: HashSet<PathBuf>,
+    ids_by_path: &mut HashMap<PathBuf, SongId>,
+    summary: &mut RescanSummary,
+) {
+    for path in paths {
+        if let Some(id) = ids_by_path.remove(&path) {
+            summary.removed.push(id);
+        }
+    }
+}
+
+/// Identifies a song in the [Library].
+/// Derived from the path of the song, so it stays the same between runs of the program.
+#[derive(Eq, PartialEq, Ord, PartialOrd, Hash, Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct SongId(u64);
 
 impl SongId {
+    /// The path is expected to be relative to the library root.
+    /// That way the ids stay the same when the whole library is moved somewhere else.
+    pub fn from_path(path: &Path) -> Self {
+        // Use the same separator on every platform, so the id doesn't depend on it.
+        let components: Vec<String> = path
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().to_string())
+            .collect();
+
+        SongId(hash_to_u64(components.join("/").as_bytes()))
+    }
+
     fn next(&self) -> Self {
-        SongId(self.0 + 1)
+        SongId(self.0.wrapping_add(1))
     }
 }
 
+/// A hash that is stable between runs and versions of the program,
+/// unlike the hashers in the standard library.
+fn hash_to_u64(data: &[u8]) -> u64 {
+    let hash = blake3::hash(data);
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&hash.as_bytes()[..8]);
+    u64::from_le_bytes(bytes)
+}
+
+/// Like [hash_to_u64], but reads the file in parts instead of all at once.
+fn hash_file(path: &Path) -> std::io::Result<u64> {
+    let mut hasher = blake3::Hasher::new();
+    std::io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&hasher.finalize().as_bytes()[..8]);
+    Ok(u64::from_le_bytes(bytes))
+}
+
 /// The ListEntryId is needed because we need a unique identifier for entries in the playlist.
 /// If we don't have those, it is hard to refer to a specific playlist entry after
 /// the order of the entries changed.
-#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
+///
+/// Entry ids are unique among all playlists, so the entries of a playlist and its duplicate,
+/// or of any two playlists, can't be mixed up.
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug, Serialize)]
 pub struct ListEntryId(usize);
 
+/// The id of the next entry of any playlist.
+static NEXT_LIST_ENTRY_ID: AtomicUsize = AtomicUsize::new(0);
+
 impl ListEntryId {
-    fn next(&self) -> Self {
-        ListEntryId(self.0 + 1)
+    fn new() -> Self {
+        ListEntryId(NEXT_LIST_ENTRY_ID.fetch_add(1, AtomicOrdering::Relaxed))
+    }
+}
+
+/// Stored the same way as [ListEntryId].
+#[derive(Deserialize)]
+#[serde(rename = "ListEntryId")]
+struct StoredListEntryId(usize);
+
+impl<'de> serde::Deserialize<'de> for ListEntryId {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let StoredListEntryId(id) = StoredListEntryId::deserialize(deserializer)?;
+        // New entries don't get the id of an entry that was loaded.
+        NEXT_LIST_ENTRY_ID.fetch_max(id.saturating_add(1), AtomicOrdering::Relaxed);
+        Ok(ListEntryId(id))
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Clone)]
+#[derive(Debug, Default, Eq, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Song {
+    /// Taken from the tags if available, otherwise from the file name.
     pub title: String,
     pub path: PathBuf,
+    /// The [LibraryRoot] this song was found in.
+    pub root: PathBuf,
+    pub artist: Option<String>,
+    pub album_artist: Option<String>,
+    pub album: Option<String>,
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub year: Option<u32>,
+    pub genre: Option<String>,
+    pub duration: Option<Duration>,
+    /// State of the file when the song was read from it.
+    pub file_stamp: FileStamp,
+    /// Hash of the file contents. Used to recognize songs that were moved.
+    /// Only present when [ScanOptions::hash_contents] is enabled.
+    pub content_hash: Option<u64>,
+}
+
+/// What the library keeps track of for a song, next to what is read from its file.
+#[derive(Debug, Default, Eq, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub struct SongStats {
+    /// When the song was first added to the library.
+    pub date_added: Option<SystemTime>,
+    /// How often the song was started.
+    pub play_count: u32,
 }
 
-#[derive(Debug)]
+/// Size and modification time of a file.
+/// Used to detect whether a song file changed since it was last read.
+#[derive(Debug, Default, Eq, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub struct FileStamp {
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl FileStamp {
+    pub fn read<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let metadata = fs::metadata(path)?;
+
+        Ok(Self {
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+/// How many edits of a playlist can be undone.
+const PLAYLIST_HISTORY_SIZE: usize = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Playlist {
     songs: Vec<(ListEntryId, SongId)>,
-    /// Next id to use when inserting a new entry.
-    next_entry_id: ListEntryId,
+    /// Only kept while the program runs.
+    #[serde(skip)]
+    history: PlaylistHistory,
 }
 
 impl Playlist {
     pub fn new() -> Self {
         Self {
             songs: Vec::new(),
-            next_entry_id: ListEntryId(0),
+            history: PlaylistHistory::default(),
         }
     }
 
     pub fn clear(&mut self) {
-        self.songs.clear();
+        self.edit(PlaylistEdit::Remove {
+            index: 0,
+            entries: self.songs.clone(),
+        });
+    }
+
+    /// Replaces all entries with new entries for the songs, as a single edit.
+    pub fn replace_songs(&mut self, song_ids: Vec<SongId>) {
+        let after = Self::new_entries(song_ids);
+        self.edit(PlaylistEdit::Replace {
+            before: self.songs.clone(),
+            after,
+        });
+    }
+
+    /// A playlist with the same songs, but with entries of its own and nothing to undo.
+    pub fn duplicate(&self) -> Playlist {
+        Playlist {
+            songs: Self::new_entries(self.get_song_ids()),
+            history: PlaylistHistory::default(),
+        }
     }
 
     pub fn song_ids(&self) -> core::slice::Iter<'_, (ListEntryId, SongId)> {
@@ -133,8 +669,17 @@ impl Playlist {
     }
 
     pub fn add_song(&mut self, song_id: SongId) {
-        self.songs.push((self.next_entry_id, song_id));
-        self.next_entry_id = self.next_entry_id.next();
+        self.add_songs(vec![song_id]);
+    }
+
+    /// Entries whose song is no longer in the library, for example because it was removed
+    /// during a [rescan](Library::rescan).
+    pub fn entries_missing_from_library(&self, library: &Library) -> Vec<(ListEntryId, SongId)> {
+        self.songs
+            .iter()
+            .filter(|(_, song_id)| library.get_song(song_id).is_none())
+            .copied()
+            .collect()
     }
 
     pub fn get_song_index(&self, entry_id: ListEntryId) -> Option<usize> {
@@ -146,19 +691,42 @@ impl Playlist {
     }
 
     pub fn add_songs(&mut self, song_ids: Vec<SongId>) {
-        for song_id in song_ids {
-            self.add_song(song_id);
-        }
+        self.insert_songs(self.songs.len(), song_ids);
+    }
+
+    /// Inserts the songs before the entry at the index, in the given order.
+    /// Adds them to the end if the index is past the end of the playlist.
+    pub fn insert_songs(&mut self, index: usize, song_ids: Vec<SongId>) {
+        let index = index.min(self.songs.len());
+        let entries = Self::new_entries(song_ids);
+
+        self.edit(PlaylistEdit::Insert { index, entries });
+    }
+
+    /// Puts the entries in the order of the songs they refer to, permanently.
+    pub fn sort(&mut self, library: &Library, order: SortOrder) {
+        let mut sorted = self.songs.clone();
+        order.sort(library, &mut sorted, |&(_, song_id)| song_id);
+
+        self.edit(PlaylistEdit::Replace {
+            before: self.songs.clone(),
+            after: sorted,
+        });
     }
 
     pub fn remove_song(&mut self, entry_id: ListEntryId) {
         if let Some(idx) = self.songs.iter().position(|(id, _)| id == &entry_id) {
-            self.songs.remove(idx);
+            self.remove_song_by_index(idx);
         }
     }
 
     pub fn remove_song_by_index(&mut self, index: usize) {
-        self.songs.remove(index);
+        if let Some(&entry) = self.songs.get(index) {
+            self.edit(PlaylistEdit::Remove {
+                index,
+                entries: vec![entry],
+            });
+        }
     }
 
     pub fn move_from_index_to_target_index(&mut self, from: usize, target: usize) {
@@ -166,53 +734,139 @@ impl Playlist {
             return;
         }
 
-        let song_id = self.songs.remove(from);
-        self.songs.insert(target, song_id);
+        self.edit(PlaylistEdit::Move { from, target });
+    }
+
+    /// Makes the entries refer to other songs, for example to the new id of a song
+    /// that was [moved](RescanSummary::moved). The undo history is changed the same way,
+    /// and this is not an edit that can be undone itself.
+    pub fn replace_song_ids(&mut self, new_id: impl Fn(SongId) -> SongId) {
+        let history = self.history.undo.iter_mut().chain(&mut self.history.redo);
+        let history_entries = history.flat_map(|edit| edit.entries_mut());
+
+        for (_, song_id) in self.songs.iter_mut().chain(history_entries) {
+            *song_id = new_id(*song_id);
+        }
+    }
+
+    /// Adds the songs to the end, without making it an edit that can be undone.
+    /// For songs the program adds by itself, like the ones of the infinite playlist.
+    /// The songs stay at the end when earlier edits are undone or redone.
+    pub fn add_songs_without_history(&mut self, song_ids: Vec<SongId>) {
+        let entries = Self::new_entries(song_ids);
+        self.songs.extend(entries.iter().copied());
+
+        // The other edits only refer to the entries in front of the new ones.
+        let history = self.history.undo.iter_mut().chain(&mut self.history.redo);
+        for edit in history {
+            if let PlaylistEdit::Replace { before, after } = edit {
+                before.extend(entries.iter().copied());
+                after.extend(entries.iter().copied());
+            }
+        }
+    }
+
+    /// Removes the entry, without making it an edit that can be undone.
+    /// For entries the program removes by itself, like the played songs of the infinite playlist.
+    /// The entry doesn't come back when earlier edits are undone or redone.
+    pub fn remove_song_without_history(&mut self, entry_id: ListEntryId) {
+        if let Some(index) = self.get_song_index(entry_id) {
+            self.history.remove_entry(entry_id, &self.songs);
+            self.songs.remove(index);
+        }
+    }
+
+    /// Reverts the last edit. Returns false if there is nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        match self.history.undo.pop_back() {
+            Some(edit) => {
+                let inverse = edit.inverse();
+                inverse.apply(&mut self.songs);
+                self.history.redo.push(inverse);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Applies the last undone edit again. Returns false if there is nothing to redo.
+    /// Undone edits can no longer be redone once the playlist is edited.
+    pub fn redo(&mut self) -> bool {
+        match self.history.redo.pop() {
+            Some(undone) => {
+                let edit = undone.inverse();
+                edit.apply(&mut self.songs);
+                self.history.undo.push_back(edit);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Applies the edit and remembers it, so it can be undone.
+    fn edit(&mut self, edit: PlaylistEdit) {
+        if edit.is_empty() {
+            return;
+        }
+
+        edit.apply(&mut self.songs);
+
+        self.history.redo.clear();
+        if self.history.undo.len() == PLAYLIST_HISTORY_SIZE {
+            self.history.undo.pop_front();
+        }
+        self.history.undo.push_back(edit);
+    }
+
+    fn new_entries(song_ids: Vec<SongId>) -> Vec<(ListEntryId, SongId)> {
+        song_ids
+            .into_iter()
+            .map(|song_id| (ListEntryId::new(), song_id))
+            .collect()
     }
 
     /// Returns None if there is no next entry, or if the given entry is not in the playlist.
-    /// Loops to the first song if the last song is given.
+    /// Whether it loops to the first song after the last song depends on the repeat mode.
     /// Also gives the index of the song in the playlist.
     pub fn get_next_entry(
         &self,
         current_entry: ListEntryId,
+        repeat: RepeatMode,
     ) -> Option<(ListEntryId, SongId, usize)> {
-        if let Some(idx) = self.get_song_index(current_entry) {
-            if idx < self.songs.len() - 1 {
-                let next_idx = idx + 1;
-                self.songs
-                    .get(next_idx)
-                    .copied()
-                    .map(|(entry_id, song_id)| (entry_id, song_id, next_idx))
-            } else {
-                self.get_first_entry()
-            }
-        } else {
-            None
+        let idx = self.get_song_index(current_entry)?;
+
+        match repeat {
+            RepeatMode::One => self.get_entry_at_index(idx),
+            _ if idx < self.songs.len() - 1 => self.get_entry_at_index(idx + 1),
+            RepeatMode::All => self.get_first_entry(),
+            RepeatMode::Off => None,
         }
     }
 
     /// Returns None if there is no previous entry, or if the given entry is not in the playlist.
-    /// Loops to the last song if the first song is given.
+    /// Whether it loops to the last song before the first song depends on the repeat mode.
     pub fn get_previous_entry(
         &self,
         current_entry: ListEntryId,
+        repeat: RepeatMode,
     ) -> Option<(ListEntryId, SongId, usize)> {
-        if let Some(idx) = self.songs.iter().position(|(id, _)| id == &current_entry) {
-            if idx != 0 {
-                let prev_entry = idx - 1;
-                self.songs
-                    .get(prev_entry)
-                    .copied()
-                    .map(|(entry_id, song_id)| (entry_id, song_id, prev_entry))
-            } else {
-                self.get_last_entry()
-            }
-        } else {
-            None
+        let idx = self.get_song_index(current_entry)?;
+
+        match repeat {
+            RepeatMode::One => self.get_entry_at_index(idx),
+            _ if idx != 0 => self.get_entry_at_index(idx - 1),
+            RepeatMode::All => self.get_last_entry(),
+            RepeatMode::Off => None,
         }
     }
 
+    pub fn get_entry_at_index(&self, index: usize) -> Option<(ListEntryId, SongId, usize)> {
+        self.songs
+            .get(index)
+            .copied()
+            .map(|(entry_id, song_id)| (entry_id, song_id, index))
+    }
+
     pub fn get_first_entry(&self) -> Option<(ListEntryId, SongId, usize)> {
         self.songs
             .first()
@@ -234,63 +888,353 @@ impl Default for Playlist {
     }
 }
 
-pub fn scan_directory_for_songs<P: AsRef<Path>>(dir: P) -> Result<Vec<Song>> {
-    let path = dir.as_ref();
+/// What comes after the last song of a playlist, or before the first.
+#[derive(Debug, Default, Eq, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub enum RepeatMode {
+    /// Stop at the ends of the playlist.
+    #[default]
+    Off,
+    /// Loop around to the other end of the playlist.
+    All,
+    /// Keep playing the same song.
+    One,
+}
 
-    if !path.exists() {
-        anyhow::bail!(
-            "Could not scan directory '{}', it does not exist.",
-            path.display()
-        );
+impl RepeatMode {
+    /// The mode after this one, when toggling through them.
+    pub fn next(self) -> Self {
+        match self {
+            RepeatMode::Off => RepeatMode::All,
+            RepeatMode::All => RepeatMode::One,
+            RepeatMode::One => RepeatMode::Off,
+        }
     }
-    if !path.is_dir() {
-        anyhow::bail!(
-            "Could not scan directory '{}', it is not a directory.",
-            path.display()
-        );
+
+    /// The mode to use when the user skips a song.
+    /// Skipping should go to another song, so repeating one song loops around the playlist instead.
+    pub fn when_skipping(self) -> Self {
+        match self {
+            RepeatMode::One => RepeatMode::All,
+            mode => mode,
+        }
     }
+}
 
-    let glob_path = path.join("**").join("*".to_owned() + SONG_EXTENSION);
+/// The edits of a [Playlist] that can be undone, and the undone edits that can be redone.
+#[derive(Debug, Clone, Default)]
+struct PlaylistHistory {
+    /// The oldest edit is in front, it is dropped when there are too many.
+    undo: VecDeque<PlaylistEdit>,
+    /// The inverses of the undone edits, the last undone edit is at the end.
+    redo: Vec<PlaylistEdit>,
+}
 
-    let mut songs = Vec::new();
+impl PlaylistHistory {
+    /// Changes the edits as if the entry was never in the playlist.
+    /// `songs` are the entries of the playlist, which still include the entry.
+    fn remove_entry(&mut self, entry_id: ListEntryId, songs: &[(ListEntryId, SongId)]) {
+        // Go back through the undo edits, and forward through the redo edits,
+        // to know where the entry is before and after each edit.
+        let mut after = songs.to_vec();
+        let mut undo = VecDeque::with_capacity(self.undo.len());
+        while let Some(edit) = self.undo.pop_back() {
+            let mut before = after.clone();
+            edit.clone().inverse().apply(&mut before);
+            if let Some(edit) = edit.without_entry(entry_id, &before, &after) {
+                undo.push_front(edit);
+            }
+            after = before;
+        }
+        self.undo = undo;
+
+        // A redo edit goes from the entries after it is applied back to the ones before.
+        let mut before = songs.to_vec();
+        let mut redo = Vec::with_capacity(self.redo.len());
+        while let Some(undone) = self.redo.pop() {
+            let mut after = before.clone();
+            undone.clone().inverse().apply(&mut after);
+            if let Some(undone) = undone.without_entry(entry_id, &after, &before) {
+                redo.push(undone);
+            }
+            before = after;
+        }
+        redo.reverse();
+        self.redo = redo;
+    }
+}
 
-    for entry in glob::glob(glob_path.to_str().unwrap())
-        .expect("Failed to read glob pattern for scanning a directory for songs.")
-    {
-        match entry {
-            Ok(path) => {
-                if let Some(song) = song_from_file_path(path) {
-                    songs.push(song);
+/// A change to the entries of a [Playlist].
+/// Holds enough to be [inverted](PlaylistEdit::inverse), which is how it is undone.
+/// The inserted entries keep their ids, so a removed entry that is brought back is the same entry.
+#[derive(Debug, Clone)]
+enum PlaylistEdit {
+    /// The entries are inserted before the entry at the index.
+    Insert {
+        index: usize,
+        entries: Vec<(ListEntryId, SongId)>,
+    },
+    /// The entries are removed, starting at the index.
+    Remove {
+        index: usize,
+        entries: Vec<(ListEntryId, SongId)>,
+    },
+    /// The entry at `from` is moved, so it ends up at `target`.
+    Move { from: usize, target: usize },
+    /// All entries are replaced, like when sorting.
+    Replace {
+        before: Vec<(ListEntryId, SongId)>,
+        after: Vec<(ListEntryId, SongId)>,
+    },
+}
+
+impl PlaylistEdit {
+    fn apply(&self, songs: &mut Vec<(ListEntryId, SongId)>) {
+        match self {
+            PlaylistEdit::Insert { index, entries } => {
+                songs.splice(*index..*index, entries.iter().copied());
+            }
+            PlaylistEdit::Remove { index, entries } => {
+                songs.drain(*index..*index + entries.len());
+            }
+            PlaylistEdit::Move { from, target } => {
+                let entry = songs.remove(*from);
+                songs.insert(*target, entry);
+            }
+            PlaylistEdit::Replace { after, .. } => *songs = after.clone(),
+        }
+    }
+
+    /// The edit that reverts this one.
+    fn inverse(self) -> Self {
+        match self {
+            PlaylistEdit::Insert { index, entries } => PlaylistEdit::Remove { index, entries },
+            PlaylistEdit::Remove { index, entries } => PlaylistEdit::Insert { index, entries },
+            PlaylistEdit::Move { from, target } => PlaylistEdit::Move {
+                from: target,
+                target: from,
+            },
+            PlaylistEdit::Replace { before, after } => PlaylistEdit::Replace {
+                before: after,
+                after: before,
+            },
+        }
+    }
+
+    fn entries_mut(&mut self) -> impl Iterator<Item = &mut (ListEntryId, SongId)> {
+        let (first, second): (&mut [_], &mut [_]) = match self {
+            PlaylistEdit::Insert { entries, .. } | PlaylistEdit::Remove { entries, .. } => {
+                (entries, &mut [])
+            }
+            PlaylistEdit::Move { .. } => (&mut [], &mut []),
+            PlaylistEdit::Replace { before, after } => (before, after),
+        };
+        first.iter_mut().chain(second.iter_mut())
+    }
+
+    /// The edit as if the entry was never in the playlist.
+    /// `before` and `after` are the entries before and after the edit is applied.
+    /// Returns `None` if nothing is left of the edit.
+    fn without_entry(
+        self,
+        entry_id: ListEntryId,
+        before: &[(ListEntryId, SongId)],
+        after: &[(ListEntryId, SongId)],
+    ) -> Option<Self> {
+        let position_before = before.iter().position(|&(id, _)| id == entry_id);
+        let position_after = after.iter().position(|&(id, _)| id == entry_id);
+        // Indices behind the entry move one place to the front.
+        let shift = |index: usize, position: Option<usize>| match position {
+            Some(position) if position < index => index - 1,
+            _ => index,
+        };
+
+        let edit = match self {
+            PlaylistEdit::Insert { index, mut entries } => {
+                entries.retain(|&(id, _)| id != entry_id);
+                PlaylistEdit::Insert {
+                    index: shift(index, position_before),
+                    entries,
+                }
+            }
+            PlaylistEdit::Remove { index, mut entries } => {
+                entries.retain(|&(id, _)| id != entry_id);
+                PlaylistEdit::Remove {
+                    index: shift(index, position_before),
+                    entries,
                 }
             }
-            Err(e) => warn!("{}", e),
+            PlaylistEdit::Move { from, .. } if position_before == Some(from) => return None,
+            PlaylistEdit::Move { from, target } => PlaylistEdit::Move {
+                from: shift(from, position_before),
+                target: shift(target, position_after),
+            },
+            PlaylistEdit::Replace {
+                mut before,
+                mut after,
+            } => {
+                before.retain(|&(id, _)| id != entry_id);
+                after.retain(|&(id, _)| id != entry_id);
+                PlaylistEdit::Replace { before, after }
+            }
+        };
+
+        (!edit.is_empty()).then_some(edit)
+    }
+
+    /// Whether the edit doesn't change anything, so there is no need to undo it.
+    fn is_empty(&self) -> bool {
+        match self {
+            PlaylistEdit::Insert { entries, .. } | PlaylistEdit::Remove { entries, .. } => {
+                entries.is_empty()
+            }
+            PlaylistEdit::Move { from, target } => from == target,
+            PlaylistEdit::Replace { before, after } => before == after,
+        }
+    }
+}
+
+/// Settings for scanning for songs, which apply to all [LibraryRoot]s.
+#[derive(Debug, Clone, Default)]
+pub struct ScanOptions {
+    /// File extensions without the leading dot. These are matched case-insensitively.
+    pub extensions: Vec<String>,
+    /// Hash the contents of every song file, so songs that are moved to another path
+    /// are recognized and keep their stats. This reads every file completely, so it is slow.
+    pub hash_contents: bool,
+    /// Also scan directories whose name starts with a dot.
+    pub scan_hidden_directories: bool,
+    /// Follow symbolic links to files and directories. Otherwise they are skipped.
+    pub follow_symlinks: bool,
+}
+
+/// A directory which is scanned for songs.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LibraryRoot {
+    pub path: PathBuf,
+    /// Glob patterns, relative to the root.
+    /// If there are any, only files matching at least one of them are included.
+    pub include: Vec<String>,
+    /// Glob patterns, relative to the root.
+    /// Files matching any of them are left out, even if they match an include pattern.
+    pub exclude: Vec<String>,
+    /// Disabled roots are not scanned, and their songs are removed from the library.
+    pub enabled: bool,
+}
+
+impl LibraryRoot {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for LibraryRoot {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::new(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            enabled: true,
         }
     }
+}
+
+/// Recursively scans the enabled roots for song files, and merges them into one list.
+/// Hidden directories (unless [ScanOptions::scan_hidden_directories] is set), directories
+/// containing a [`.nomedia`](scanner::NO_MEDIA_FILE_NAME) file and paths matched by an
+/// [`.smpignore`](scanner::IGNORE_FILE_NAME) file are skipped, including everything below them.
+/// Fails if any of the enabled roots cannot be scanned.
+pub fn scan_directory_for_songs(roots: &[LibraryRoot], options: &ScanOptions) -> Result<Vec<Song>> {
+    let mut songs = Vec::new();
+
+    for root in roots.iter().filter(|root| root.enabled) {
+        let found = find_song_files(&root.path, &RootFilter::new(root), options)?;
+        for warning in found.warnings {
+            warn!("{}", warning);
+        }
+
+        songs.extend(
+            found
+                .files
+                .into_iter()
+                .filter_map(|path| song_from_file_path(path, &root.path, options)),
+        );
+    }
 
     Ok(songs)
 }
 
-fn song_from_file_path<P: AsRef<Path>>(file_path: P) -> Option<Song> {
+fn song_from_file_path<P: AsRef<Path>>(
+    file_path: P,
+    root: &Path,
+    options: &ScanOptions,
+) -> Option<Song> {
     let path = PathBuf::from(file_path.as_ref());
-    match path.file_stem() {
-        Some(title) => Some(Song {
-            title: title.to_string_lossy().to_string(),
-            path,
-        }),
-        None => {
-            warn!(
-                "Could not extract song title from path '{}'.",
-                path.display()
-            );
-            None
+    // Read before the tags, so a file that changes while reading is seen as changed next time.
+    let file_stamp = FileStamp::read(&path).unwrap_or_default();
+
+    let content_hash = if options.hash_contents {
+        match hash_file(&path) {
+            Ok(hash) => Some(hash),
+            Err(e) => {
+                warn!("Could not read '{}': {}", path.display(), e);
+                None
+            }
         }
-    }
+    } else {
+        None
+    };
+
+    let song_tags = match tags::read_tags(&path) {
+        Ok(song_tags) => song_tags,
+        Err(e) => {
+            debug!("Could not read tags from '{}': {}", path.display(), e);
+            Default::default()
+        }
+    };
+
+    let title = match song_tags.title {
+        Some(title) => title,
+        None => match path.file_stem() {
+            Some(title) => title.to_string_lossy().to_string(),
+            None => {
+                warn!(
+                    "Could not extract song title from path '{}'.",
+                    path.display()
+                );
+                return None;
+            }
+        },
+    };
+
+    Some(Song {
+        title,
+        path,
+        root: root.to_path_buf(),
+        artist: song_tags.artist,
+        album_artist: song_tags.album_artist,
+        album: song_tags.album,
+        track_number: song_tags.track_number,
+        disc_number: song_tags.disc_number,
+        year: song_tags.year,
+        genre: song_tags.genre,
+        duration: song_tags.duration,
+        file_stamp,
+        content_hash,
+    })
 }
 
 #[cfg(test)]
 mod test {
-    use crate::library::{Library, ListEntryId, Playlist, Song, SongId};
-    use std::path::PathBuf;
+    use crate::library::columns::{Column, SortOrder};
+    use crate::library::{
+        Library, ListEntryId, Playlist, RepeatMode, Song, SongId, PLAYLIST_HISTORY_SIZE,
+    };
+    use std::collections::HashSet;
+    use std::path::{Path, PathBuf};
     use test_log::test;
 
     #[test]
@@ -308,15 +1252,55 @@ mod test {
         let id1 = library.add_song(Song {
             title: String::new(),
             path: PathBuf::new(),
+            ..Default::default()
         });
         let id2 = library.add_song(Song {
             title: String::new(),
             path: PathBuf::new(),
+            ..Default::default()
         });
 
         assert_ne!(id1, id2);
     }
 
+    #[test]
+    fn library_song_id_is_derived_from_path() {
+        let mut library = Library::new();
+
+        let path = PathBuf::from("some_folder").join("song.ogg");
+        let id = library.add_song(Song {
+            path: path.clone(),
+            ..Default::default()
+        });
+
+        assert_eq!(id, SongId::from_path(&path));
+        assert_ne!(id, SongId::from_path(Path::new("song.ogg")));
+    }
+
+    #[test]
+    fn ids_survive_serialization() {
+        let song_id = SongId::from_path(Path::new("song.ogg"));
+        let entry_id = ListEntryId(3);
+
+        let bytes = bincode::serialize(&(song_id, entry_id)).unwrap();
+        let deserialized: (SongId, ListEntryId) = bincode::deserialize(&bytes).unwrap();
+
+        assert_eq!(deserialized, (song_id, entry_id));
+    }
+
+    #[test]
+    fn new_entries_do_not_get_the_ids_of_loaded_entries() {
+        let loaded_id = ListEntryId(1_000_000_000);
+        let bytes = bincode::serialize(&loaded_id).unwrap();
+        let _: ListEntryId = bincode::deserialize(&bytes).unwrap();
+
+        let mut playlist = Playlist::new();
+        playlist.add_songs(vec![SongId(1)]);
+
+        let &(new_id, _) = playlist.get_at_index(0).unwrap();
+        assert!(new_id.0 > loaded_id.0);
+    }
+
     #[test]
     fn library_get_song() {
         let mut library = Library::new();
@@ -327,10 +1311,12 @@ mod test {
         let id1 = library.add_song(Song {
             title: song_title1.to_owned(),
             path: PathBuf::new(),
+            ..Default::default()
         });
         let id2 = library.add_song(Song {
             title: song_title2.to_owned(),
             path: PathBuf::new(),
+            ..Default::default()
         });
 
         let song1 = library.get_song(&id1).unwrap();
@@ -398,6 +1384,90 @@ mod test {
         assert_eq!(list.get_first_entry().unwrap().1, id1);
     }
 
+    #[test]
+    fn playlist_insert_songs() {
+        let mut list = Playlist::new();
+
+        let id1 = SongId(1);
+        let id2 = SongId(2);
+        let id3 = SongId(3);
+        let id4 = SongId(4);
+
+        list.add_songs(vec![id1, id2]);
+
+        list.insert_songs(1, vec![id3, id4]);
+        assert_eq!(list.get_song_ids(), vec![id1, id3, id4, id2]);
+
+        list.insert_songs(17, vec![id1]);
+        assert_eq!(list.get_song_ids(), vec![id1, id3, id4, id2, id1]);
+
+        list.insert_songs(0, vec![id2]);
+        assert_eq!(list.get_song_ids(), vec![id2, id1, id3, id4, id2, id1]);
+
+        let entry_ids: HashSet<ListEntryId> = list.song_ids().map(|(entry, _)| *entry).collect();
+        assert_eq!(entry_ids.len(), list.song_count());
+    }
+
+    #[test]
+    fn playlist_sort() {
+        let mut library = Library::new();
+        let b = library.add_song(Song {
+            title: "b".to_string(),
+            path: PathBuf::from("b.mp3"),
+            ..Default::default()
+        });
+        let a = library.add_song(Song {
+            title: "a".to_string(),
+            path: PathBuf::from("a.mp3"),
+            ..Default::default()
+        });
+
+        let mut list = Playlist::new();
+        list.add_songs(vec![b, a, b]);
+        let first_entry = list.get_first_entry().unwrap().0;
+
+        list.sort(
+            &library,
+            SortOrder {
+                column: Column::Title,
+                descending: false,
+            },
+        );
+
+        assert_eq!(list.get_song_ids(), vec![a, b, b]);
+        // The entries move along with their songs.
+        assert_eq!(list.get_song_index(first_entry), Some(1));
+    }
+
+    #[test]
+    fn library_keeps_stats_of_songs() {
+        let mut library = Library::new();
+        let id = library.add_song(Song::default());
+
+        assert!(library.song_stats(&id).date_added.is_some());
+        assert_eq!(library.song_stats(&id).play_count, 0);
+
+        library.record_play(&id);
+        library.record_play(&SongId(17));
+        assert_eq!(library.song_stats(&id).play_count, 1);
+        assert_eq!(library.song_stats(&SongId(17)).play_count, 0);
+    }
+
+    #[test]
+    fn playlist_entries_missing_from_library() {
+        let mut library = Library::new();
+        let id1 = library.add_song(Song::default());
+
+        let mut list = Playlist::new();
+        list.add_song(id1);
+        list.add_song(SongId(17));
+        list.add_song(id1);
+
+        let missing = list.entries_missing_from_library(&library);
+
+        assert_eq!(missing, vec![(list.get_at_index(1).unwrap().0, SongId(17))]);
+    }
+
     #[test]
     fn playlist_get_last_entry() {
         let mut list = Playlist::new();
@@ -440,67 +1510,314 @@ mod test {
         assert_eq!(list.get_song_ids(), vec![id1, id2, id4, id3]);
     }
 
+    #[test]
+    fn playlist_undo_and_redo_add() {
+        let mut list = Playlist::new();
+        list.add_song(SongId(1));
+        list.add_songs(vec![SongId(2), SongId(3)]);
+        let entries: Vec<_> = list.song_ids().copied().collect();
+
+        assert!(list.undo());
+        assert_eq!(list.get_song_ids(), vec![SongId(1)]);
+        assert!(list.undo());
+        assert_eq!(list.song_count(), 0);
+        assert!(!list.undo());
+
+        assert!(list.redo());
+        assert!(list.redo());
+        assert!(!list.redo());
+        // The entries come back with the same ids.
+        assert_eq!(list.song_ids().copied().collect::<Vec<_>>(), entries);
+    }
+
+    #[test]
+    fn playlist_undo_and_redo_insert() {
+        let mut list = Playlist::new();
+        list.add_songs(vec![SongId(1), SongId(2)]);
+        list.insert_songs(1, vec![SongId(3), SongId(4)]);
+
+        assert!(list.undo());
+        assert_eq!(list.get_song_ids(), vec![SongId(1), SongId(2)]);
+        assert!(list.redo());
+        assert_eq!(
+            list.get_song_ids(),
+            vec![SongId(1), SongId(3), SongId(4), SongId(2)]
+        );
+    }
+
+    #[test]
+    fn playlist_undo_and_redo_remove() {
+        let mut list = Playlist::new();
+        list.add_songs(vec![SongId(1), SongId(2), SongId(3)]);
+        let (removed_entry, _, _) = list
+            .get_next_entry(list.get_first_entry().unwrap().0, RepeatMode::Off)
+            .unwrap();
+
+        list.remove_song(removed_entry);
+        list.remove_song_by_index(0);
+        assert_eq!(list.get_song_ids(), vec![SongId(3)]);
+
+        assert!(list.undo());
+        assert!(list.undo());
+        assert_eq!(list.get_song_ids(), vec![SongId(1), SongId(2), SongId(3)]);
+        assert_eq!(list.get_song_index(removed_entry), Some(1));
+
+        assert!(list.redo());
+        assert_eq!(list.get_song_ids(), vec![SongId(1), SongId(3)]);
+    }
+
+    #[test]
+    fn playlist_undo_and_redo_move() {
+        let mut list = Playlist::new();
+        list.add_songs(vec![SongId(1), SongId(2), SongId(3), SongId(4)]);
+
+        list.move_from_index_to_target_index(0, 2);
+        list.move_from_index_to_target_index(3, 1);
+        assert_eq!(
+            list.get_song_ids(),
+            vec![SongId(2), SongId(4), SongId(3), SongId(1)]
+        );
+
+        assert!(list.undo());
+        assert_eq!(
+            list.get_song_ids(),
+            vec![SongId(2), SongId(3), SongId(1), SongId(4)]
+        );
+        assert!(list.undo());
+        assert_eq!(
+            list.get_song_ids(),
+            vec![SongId(1), SongId(2), SongId(3), SongId(4)]
+        );
+
+        assert!(list.redo());
+        assert_eq!(
+            list.get_song_ids(),
+            vec![SongId(2), SongId(3), SongId(1), SongId(4)]
+        );
+    }
+
+    #[test]
+    fn playlist_undo_and_redo_clear() {
+        let mut list = Playlist::new();
+        list.add_songs(vec![SongId(1), SongId(2)]);
+
+        list.clear();
+        assert_eq!(list.song_count(), 0);
+        // Clearing an empty playlist is not an edit.
+        list.clear();
+
+        assert!(list.undo());
+        assert_eq!(list.get_song_ids(), vec![SongId(1), SongId(2)]);
+        assert!(list.redo());
+        assert_eq!(list.song_count(), 0);
+    }
+
+    #[test]
+    fn playlist_undo_and_redo_bulk_edits() {
+        let mut library = Library::new();
+        let b = library.add_song(Song {
+            title: "b".to_string(),
+            path: PathBuf::from("b.mp3"),
+            ..Default::default()
+        });
+        let a = library.add_song(Song {
+            title: "a".to_string(),
+            path: PathBuf::from("a.mp3"),
+            ..Default::default()
+        });
+
+        let mut list = Playlist::new();
+        list.add_songs(vec![b, a]);
+        list.sort(
+            &library,
+            SortOrder {
+                column: Column::Title,
+                descending: false,
+            },
+        );
+        list.replace_songs(vec![a, a, a]);
+
+        assert!(list.undo());
+        assert_eq!(list.get_song_ids(), vec![a, b]);
+        assert!(list.undo());
+        assert_eq!(list.get_song_ids(), vec![b, a]);
+
+        assert!(list.redo());
+        assert!(list.redo());
+        assert_eq!(list.get_song_ids(), vec![a, a, a]);
+    }
+
+    #[test]
+    fn playlist_edit_after_undo_drops_redo() {
+        let mut list = Playlist::new();
+        list.add_song(SongId(1));
+        list.add_song(SongId(2));
+
+        assert!(list.undo());
+        list.add_song(SongId(3));
+
+        assert!(!list.redo());
+        assert_eq!(list.get_song_ids(), vec![SongId(1), SongId(3)]);
+    }
+
+    #[test]
+    fn playlist_songs_added_without_history_are_not_undone() {
+        let mut list = Playlist::new();
+        list.add_songs(vec![SongId(1), SongId(2)]);
+        list.replace_songs(vec![SongId(4), SongId(5)]);
+
+        list.add_songs_without_history(vec![SongId(3)]);
+        assert_eq!(list.get_song_ids(), vec![SongId(4), SongId(5), SongId(3)]);
+
+        assert!(list.undo());
+        assert_eq!(list.get_song_ids(), vec![SongId(1), SongId(2), SongId(3)]);
+        assert!(list.undo());
+        assert_eq!(list.get_song_ids(), vec![SongId(3)]);
+        assert!(!list.undo());
+
+        assert!(list.redo());
+        assert!(list.redo());
+        assert_eq!(list.get_song_ids(), vec![SongId(4), SongId(5), SongId(3)]);
+    }
+
+    #[test]
+    fn playlist_entries_removed_without_history_do_not_come_back() {
+        let mut list = Playlist::new();
+        list.add_songs(vec![SongId(1), SongId(2), SongId(3)]);
+        list.move_from_index_to_target_index(2, 1);
+        list.remove_song_by_index(2);
+        assert_eq!(list.get_song_ids(), vec![SongId(1), SongId(3)]);
+
+        list.remove_song_without_history(list.get_first_entry().unwrap().0);
+        assert_eq!(list.get_song_ids(), vec![SongId(3)]);
+
+        assert!(list.undo());
+        assert_eq!(list.get_song_ids(), vec![SongId(3), SongId(2)]);
+        assert!(list.undo());
+        assert_eq!(list.get_song_ids(), vec![SongId(2), SongId(3)]);
+        assert!(list.undo());
+        assert_eq!(list.song_count(), 0);
+        assert!(!list.undo());
+
+        // Going forward again also leaves the entry out.
+        assert!(list.redo());
+        assert!(list.redo());
+        assert_eq!(list.get_song_ids(), vec![SongId(3), SongId(2)]);
+        list.remove_song_without_history(list.get_first_entry().unwrap().0);
+        assert_eq!(list.get_song_ids(), vec![SongId(2)]);
+        assert!(list.redo());
+        assert_eq!(list.song_count(), 0);
+        assert!(!list.redo());
+    }
+
+    #[test]
+    fn playlist_history_is_bounded() {
+        let mut list = Playlist::new();
+        for id in 0..PLAYLIST_HISTORY_SIZE as u64 + 10 {
+            list.add_song(SongId(id));
+        }
+
+        let mut undone = 0;
+        while list.undo() {
+            undone += 1;
+        }
+
+        assert_eq!(undone, PLAYLIST_HISTORY_SIZE);
+        assert_eq!(list.song_count(), 10);
+    }
+
     #[test]
     fn playlist_get_next_entry_returns_none_when_no_songs() {
         let list = Playlist::new();
 
-        assert_eq!(list.get_next_entry(ListEntryId(0)), None);
+        for repeat in [RepeatMode::Off, RepeatMode::All, RepeatMode::One] {
+            assert_eq!(list.get_next_entry(ListEntryId(0), repeat), None);
+            assert_eq!(list.get_previous_entry(ListEntryId(0), repeat), None);
+        }
+    }
+
+    fn playlist_of_four_songs() -> Playlist {
+        let mut list = Playlist::new();
+        list.add_songs(vec![SongId(1), SongId(2), SongId(3), SongId(4)]);
+        list
     }
 
     #[test]
     fn playlist_get_next_entry_returns_next_entry() {
-        let mut list = Playlist::new();
+        let list = playlist_of_four_songs();
 
-        let id1 = SongId(1);
-        let id2 = SongId(2);
-        let id3 = SongId(3);
-        let id4 = SongId(4);
+        let first = list.get_first_entry().unwrap();
+        assert_eq!(first.1, SongId(1));
+
+        for repeat in [RepeatMode::Off, RepeatMode::All] {
+            let next = list.get_next_entry(first.0, repeat).unwrap();
+            assert_eq!(next.1, SongId(2));
+            let next = list.get_next_entry(next.0, repeat).unwrap();
+            assert_eq!(next.1, SongId(3));
+            let next = list.get_next_entry(next.0, repeat).unwrap();
+            assert_eq!(next, list.get_last_entry().unwrap());
+        }
+    }
 
-        list.add_song(id1);
-        list.add_song(id2);
-        list.add_song(id3);
-        list.add_song(id4);
+    #[test]
+    fn playlist_get_next_entry_at_the_end() {
+        let list = playlist_of_four_songs();
+        let last = list.get_last_entry().unwrap();
 
-        let first = list.get_first_entry().unwrap();
-        assert_eq!(first.1, id1);
+        assert_eq!(list.get_next_entry(last.0, RepeatMode::Off), None);
+        assert_eq!(
+            list.get_next_entry(last.0, RepeatMode::All),
+            list.get_first_entry()
+        );
+        assert_eq!(list.get_next_entry(last.0, RepeatMode::One), Some(last));
+    }
 
-        let next = list.get_next_entry(first.0).unwrap();
-        assert_eq!(next.1, id2);
-        let next = list.get_next_entry(next.0).unwrap();
-        assert_eq!(next.1, id3);
-        let next = list.get_next_entry(next.0).unwrap();
-        assert_eq!(next.1, id4);
+    #[test]
+    fn playlist_get_next_entry_repeats_one() {
+        let list = playlist_of_four_songs();
+        let first = list.get_first_entry().unwrap();
 
-        // `get_next_entry` wraps when at the end.
-        let should_be_first = list.get_next_entry(next.0).unwrap();
-        assert_eq!(should_be_first.1, id1);
+        assert_eq!(list.get_next_entry(first.0, RepeatMode::One), Some(first));
     }
 
     #[test]
     fn playlist_get_previous_entry_returns_previous_entry() {
-        let mut list = Playlist::new();
+        let list = playlist_of_four_songs();
 
-        let id1 = SongId(1);
-        let id2 = SongId(2);
-        let id3 = SongId(3);
-        let id4 = SongId(4);
+        let last = list.get_last_entry().unwrap();
+        assert_eq!(last.1, SongId(4));
+
+        for repeat in [RepeatMode::Off, RepeatMode::All] {
+            let prev = list.get_previous_entry(last.0, repeat).unwrap();
+            assert_eq!(prev.1, SongId(3));
+            let prev = list.get_previous_entry(prev.0, repeat).unwrap();
+            assert_eq!(prev.1, SongId(2));
+            let prev = list.get_previous_entry(prev.0, repeat).unwrap();
+            assert_eq!(prev, list.get_first_entry().unwrap());
+        }
+    }
 
-        list.add_song(id1);
-        list.add_song(id2);
-        list.add_song(id3);
-        list.add_song(id4);
+    #[test]
+    fn playlist_get_previous_entry_at_the_start() {
+        let list = playlist_of_four_songs();
+        let first = list.get_first_entry().unwrap();
 
-        let last = list.get_last_entry().unwrap();
-        assert_eq!(last.1, id4);
+        assert_eq!(list.get_previous_entry(first.0, RepeatMode::Off), None);
+        assert_eq!(
+            list.get_previous_entry(first.0, RepeatMode::All),
+            list.get_last_entry()
+        );
+        assert_eq!(
+            list.get_previous_entry(first.0, RepeatMode::One),
+            Some(first)
+        );
+    }
 
-        let prev = list.get_previous_entry(last.0).unwrap();
-        assert_eq!(prev.1, id3);
-        let prev = list.get_previous_entry(prev.0).unwrap();
-        assert_eq!(prev.1, id2);
-        let prev = list.get_previous_entry(prev.0).unwrap();
-        assert_eq!(prev.1, id1);
+    #[test]
+    fn playlist_get_previous_entry_repeats_one() {
+        let list = playlist_of_four_songs();
+        let last = list.get_last_entry().unwrap();
 
-        // `get_previous_entry` wraps when at the start.
-        assert_eq!(list.get_previous_entry(prev.0).unwrap().1, id4);
+        assert_eq!(list.get_previous_entry(last.0, RepeatMode::One), Some(last));
     }
 }
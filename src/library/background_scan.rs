@@ -0,0 +1,327 @@
+use crate::library::scanner::{find_song_files, RootFilter};
+use crate::library::{
+    mark_removed, song_from_file_path, FileStamp, Library, LibraryRoot, RescanSummary, ScanOptions,
+    Song, SongId,
+};
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+
+/// Something that happened during a [BackgroundScan].
+#[derive(Debug)]
+pub enum ScanEvent {
+    /// This many more song files were found.
+    FilesFound(usize),
+    /// All roots have been walked, so no more files will be found.
+    AllFilesFound,
+    /// A new or changed song file was read. `None` if it could not be read as a song.
+    SongRead {
+        path: PathBuf,
+        song: Option<Box<Song>>,
+    },
+    /// A song file did not change since it was last read.
+    SongUnchanged(PathBuf),
+    /// A root or directory could not be scanned. The songs below it are kept as they are.
+    Unavailable(PathBuf),
+    /// A problem that did not stop the scan.
+    Error(String),
+    /// The scan is done, or it was cancelled. No more events will follow.
+    Finished,
+}
+
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct ScanProgress {
+    pub files_found: usize,
+    /// Whether all roots have been walked, so `files_found` will not change anymore.
+    pub all_files_found: bool,
+    pub files_processed: usize,
+    pub errors: usize,
+}
+
+impl ScanProgress {
+    /// How much of the found files have been processed, between 0 and 1.
+    pub fn fraction_processed(&self) -> f32 {
+        if self.files_found == 0 {
+            0.0
+        } else {
+            self.files_processed as f32 / self.files_found as f32
+        }
+    }
+}
+
+/// Does the same as [Library::rescan], but without blocking.
+/// The files are found and read on other threads, and the results are sent back as [ScanEvent]s.
+/// These are applied to the library with [apply_events](Self::apply_events),
+/// so the songs that have been read so far are already available while the scan is running.
+///
+/// Nothing else should change the library while a scan is running.
+pub struct BackgroundScan {
+    events: Receiver<ScanEvent>,
+    cancelled: Arc<AtomicBool>,
+    finished: bool,
+    progress: ScanProgress,
+    ids_by_path: HashMap<PathBuf, SongId>,
+    found_paths: HashSet<PathBuf>,
+    /// The songs below these paths are kept, because they could not be scanned.
+    unavailable_paths: Vec<PathBuf>,
+    /// Everything that changed since the scan started.
+    summary: RescanSummary,
+}
+
+impl BackgroundScan {
+    /// Starts scanning the enabled roots.
+    /// `on_event` is called (from another thread) every time there is a new event,
+    /// which can be used to wake up the UI so that it calls [apply_events](Self::apply_events).
+    pub fn start<F>(
+        library: &Library,
+        roots: Vec<LibraryRoot>,
+        options: ScanOptions,
+        on_event: F,
+    ) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        let (sender, events) = channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        let stamps: HashMap<PathBuf, FileStamp> = library
+            .songs()
+            .map(|(_, song)| (song.path.clone(), song.file_stamp))
+            .collect();
+
+        let sender = EventSender {
+            sender,
+            on_event: Arc::new(on_event),
+            cancelled: cancelled.clone(),
+        };
+        thread::spawn(move || scan(roots, options, stamps, sender));
+
+        Self {
+            events,
+            cancelled,
+            finished: false,
+            progress: ScanProgress::default(),
+            ids_by_path: library.ids_by_path(),
+            found_paths: HashSet::new(),
+            unavailable_paths: Vec::new(),
+            summary: RescanSummary::default(),
+        }
+    }
+
+    /// Stops the scan as soon as possible. The songs that have been read up to now are kept,
+    /// but songs that were not found (yet) are not removed from the library.
+    /// The scan is finished once the last events have been applied.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Whether all events have been applied, and the library is up to date.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn progress(&self) -> ScanProgress {
+        self.progress
+    }
+
+    /// Everything that changed since the scan started.
+    pub fn summary(&self) -> &RescanSummary {
+        &self.summary
+    }
+
+    /// Applies the events that came in since the last call to the library.
+    /// Returns what changed because of them, or `None` if there were no events.
+    pub fn apply_events(&mut self, library: &mut Library) -> Option<RescanSummary> {
+        let mut changes = RescanSummary::default();
+        let mut any_events = false;
+
+        while !self.finished {
+            let event = match self.events.try_recv() {
+                Ok(event) => event,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    // The scanning thread stopped without saying it was done, so it must have
+                    // crashed. Treat it as cancelled, so no songs are removed by accident.
+                    self.cancel();
+                    changes
+                        .warnings
+                        .push("Scanning the library stopped unexpectedly.".to_string());
+                    ScanEvent::Finished
+                }
+            };
+            any_events = true;
+
+            match event {
+                ScanEvent::FilesFound(count) => self.progress.files_found += count,
+                ScanEvent::AllFilesFound => self.progress.all_files_found = true,
+                ScanEvent::SongRead { path, song } => {
+                    self.progress.files_processed += 1;
+                    let song = song.map(|song| *song);
+                    library.apply_read_song(&path, song, &mut self.ids_by_path, &mut changes);
+                    self.found_paths.insert(path);
+                }
+                ScanEvent::SongUnchanged(path) => {
+                    self.progress.files_processed += 1;
+                    changes.unchanged += 1;
+                    self.found_paths.insert(path);
+                }
+                ScanEvent::Unavailable(path) => self.unavailable_paths.push(path),
+                ScanEvent::Error(error) => {
+                    self.progress.errors += 1;
+                    changes.warnings.push(error);
+                }
+                ScanEvent::Finished => {
+                    self.finished = true;
+
+                    if self.is_cancelled() {
+                        changes.warnings.push(
+                            "Scanning the library was cancelled, songs that were not found are kept."
+                                .to_string(),
+                        );
+                    } else {
+                        let not_found: HashSet<PathBuf> = self
+                            .ids_by_path
+                            .keys()
+                            .filter(|path| {
+                                !self.found_paths.contains(*path)
+                                    && !self
+                                        .unavailable_paths
+                                        .iter()
+                                        .any(|unavailable| path.starts_with(unavailable))
+                            })
+                            .cloned()
+                            .collect();
+                        mark_removed(not_found, &mut self.ids_by_path, &mut changes);
+                    }
+                }
+            }
+        }
+
+        if !any_events {
+            return None;
+        }
+
+        if !changes.removed.is_empty() {
+            self.remove_or_move_songs(library, &mut changes);
+        }
+
+        self.summary.extend(changes.clone());
+        Some(changes)
+    }
+
+    /// Removes the songs that are marked as removed in `changes`.
+    /// Moves are recognized against all songs that were added since the scan started,
+    /// not only the ones in `changes`.
+    fn remove_or_move_songs(&mut self, library: &mut Library, changes: &mut RescanSummary) {
+        let mut combined = RescanSummary {
+            added: self
+                .summary
+                .added
+                .iter()
+                .chain(changes.added.iter())
+                .copied()
+                .collect(),
+            removed: std::mem::take(&mut changes.removed),
+            ..Default::default()
+        };
+        library.remove_or_move_songs(&mut combined);
+
+        // Songs that turned out to be moved were not added after all.
+        let still_added: HashSet<SongId> = combined.added.into_iter().collect();
+        self.summary.added.retain(|id| still_added.contains(id));
+        changes.added.retain(|id| still_added.contains(id));
+
+        changes.removed = combined.removed;
+        changes.moved.extend(combined.moved);
+    }
+}
+
+impl Drop for BackgroundScan {
+    fn drop(&mut self) {
+        // Nobody is listening anymore, so there is no use in continuing.
+        self.cancel();
+    }
+}
+
+#[derive(Clone)]
+struct EventSender {
+    sender: Sender<ScanEvent>,
+    on_event: Arc<dyn Fn() + Send + Sync>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl EventSender {
+    fn send(&self, event: ScanEvent) {
+        if self.sender.send(event).is_ok() {
+            (self.on_event)();
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Runs on the scanning thread. First finds all song files, then reads them in parallel.
+fn scan(
+    roots: Vec<LibraryRoot>,
+    options: ScanOptions,
+    stamps: HashMap<PathBuf, FileStamp>,
+    sender: EventSender,
+) {
+    let mut files = Vec::new();
+
+    for root in roots.iter().filter(|root| root.enabled) {
+        if sender.is_cancelled() {
+            break;
+        }
+
+        match find_song_files(&root.path, &RootFilter::new(root), &options) {
+            Ok(found) => {
+                sender.send(ScanEvent::FilesFound(found.files.len()));
+                for warning in found.warnings {
+                    sender.send(ScanEvent::Error(warning));
+                }
+                for dir in found.unreadable_dirs {
+                    sender.send(ScanEvent::Unavailable(dir));
+                }
+                files.extend(found.files.into_iter().map(|path| (path, &root.path)));
+            }
+            Err(e) => {
+                sender.send(ScanEvent::Error(e.to_string()));
+                sender.send(ScanEvent::Unavailable(root.path.clone()));
+            }
+        }
+    }
+    sender.send(ScanEvent::AllFilesFound);
+
+    files
+        .into_par_iter()
+        .for_each_with(sender.clone(), |sender, (path, root)| {
+            if sender.is_cancelled() {
+                return;
+            }
+
+            let unchanged = match (stamps.get(&path), FileStamp::read(&path)) {
+                (Some(old_stamp), Ok(stamp)) => *old_stamp == stamp,
+                _ => false,
+            };
+
+            if unchanged {
+                sender.send(ScanEvent::SongUnchanged(path));
+            } else {
+                let song = song_from_file_path(&path, root, &options).map(Box::new);
+                sender.send(ScanEvent::SongRead { path, song });
+            }
+        });
+
+    sender.send(ScanEvent::Finished);
+}
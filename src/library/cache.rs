@@ -0,0 +1,74 @@
+use crate::library::Library;
+use anyhow::Result;
+use log::info;
+use std::fs;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Marks a file as a library cache, so we never try to read an unrelated file as one.
+const CACHE_MAGIC: [u8; 4] = *b"SMPL";
+/// Increase this whenever the layout of the [Library] or [Song](crate::library::Song) changes.
+/// Caches written with another version are discarded instead of read.
+const CACHE_FORMAT_VERSION: u32 = 4;
+
+const CACHE_FILE_NAME: &str = "library_cache.bin";
+
+/// Location of the library cache inside the user data directory.
+/// `None` if the platform has no user data directory.
+pub fn default_cache_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("simple_music_player").join(CACHE_FILE_NAME))
+}
+
+/// Writes the library to the cache file, creating the parent directories if needed.
+pub fn save_library_cache(library: &Library, path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // Write to a temporary file first, so a crash halfway through
+    // does not leave a broken cache behind.
+    let temp_path = path.with_extension("tmp");
+    {
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        writer.write_all(&CACHE_MAGIC)?;
+        bincode::serialize_into(&mut writer, &CACHE_FORMAT_VERSION)?;
+        bincode::serialize_into(&mut writer, library)?;
+        writer.flush()?;
+    }
+    fs::rename(&temp_path, path)?;
+
+    Ok(())
+}
+
+/// Reads the library from the cache file.
+/// Returns `None` if there is no cache, or if it was written by an incompatible version.
+pub fn load_library_cache(path: &Path) -> Result<Option<Library>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut magic = [0; CACHE_MAGIC.len()];
+    if reader.read_exact(&mut magic).is_err() || magic != CACHE_MAGIC {
+        info!(
+            "Discarding library cache '{}', it is not a library cache.",
+            path.display()
+        );
+        return Ok(None);
+    }
+
+    let version: u32 = bincode::deserialize_from(&mut reader)?;
+    if version != CACHE_FORMAT_VERSION {
+        info!(
+            "Discarding library cache '{}', it has version {} instead of {}.",
+            path.display(),
+            version,
+            CACHE_FORMAT_VERSION
+        );
+        return Ok(None);
+    }
+
+    Ok(Some(bincode::deserialize_from(&mut reader)?))
+}
@@ -0,0 +1,180 @@
+use icu_collator::{CaseFirst, Collator, CollatorOptions, Numeric, Strength};
+use icu_provider::DataLocale;
+use log::warn;
+use std::cmp::Ordering;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// The text the way searching compares it: lowercase and without accents,
+/// so "bjork" finds "Björk" and "cafe" finds "Café".
+///
+/// The text is decomposed (NFKD), after which the accents are separate characters that are
+/// left out. This also turns compatibility characters like "ﬁ" and "①" into plain ones.
+/// A few letters that don't decompose, like "ø" and "æ", are spelled out instead.
+pub fn fold(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+
+    for c in text.nfkd().filter(|c| !is_combining_mark(*c)) {
+        match c {
+            'ø' | 'Ø' => folded.push('o'),
+            'ł' | 'Ł' => folded.push('l'),
+            'đ' | 'Đ' => folded.push('d'),
+            'æ' | 'Æ' => folded.push_str("ae"),
+            'œ' | 'Œ' => folded.push_str("oe"),
+            'ß' => folded.push_str("ss"),
+            c => folded.extend(c.to_lowercase()),
+        }
+    }
+
+    folded
+}
+
+/// Compares texts the way people expect them to be sorted. See [SortKey].
+pub fn natural_cmp(first: &str, second: &str) -> Ordering {
+    SortKey::new(first).cmp(&SortKey::new(second))
+}
+
+thread_local! {
+    /// A collator can't be shared between threads, so every thread has its own.
+    static SYSTEM_COLLATION: Collation = Collation::for_system_locale();
+}
+
+/// Sorts texts the way people expect, instead of by their raw characters.
+///
+/// - Letters are sorted in the order of the language of the system,
+///   so in Swedish "ö" comes after "z", while in German it is sorted like "o".
+/// - Case and accents only matter when texts are otherwise the same,
+///   so "björk", "Björk" and "Bjork" end up next to each other.
+/// - Numbers are sorted by their value, so "Track 2" comes before "Track 10".
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SortKey {
+    text: String,
+}
+
+impl SortKey {
+    pub fn new(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+        }
+    }
+}
+
+impl Ord for SortKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        SYSTEM_COLLATION.with(|collation| collation.compare(&self.text, &other.text))
+    }
+}
+
+impl PartialOrd for SortKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The order of the letters of a language, see [SortKey].
+pub struct Collation {
+    collator: Collator,
+}
+
+impl Collation {
+    /// The order of the language of the system, or the default Unicode order if it isn't known.
+    pub fn for_system_locale() -> Self {
+        Self::new(&sys_locale::get_locale().unwrap_or_default())
+    }
+
+    /// The order of the language of a BCP 47 locale, like "sv-SE".
+    /// Languages without an order of their own use the default Unicode order.
+    pub fn new(locale: &str) -> Self {
+        let locale: DataLocale = locale.parse().unwrap_or_default();
+
+        let mut options = CollatorOptions::new();
+        options.strength = Some(Strength::Tertiary);
+        // Upper case first, so "Björk" and "björk" are in the same order as their characters.
+        options.case_first = Some(CaseFirst::UpperFirst);
+        options.numeric = Some(Numeric::On);
+
+        let collator = Collator::try_new(&locale, options).unwrap_or_else(|e| {
+            warn!(
+                "No collation for locale '{:?}', using the default: {}",
+                locale, e
+            );
+            Collator::try_new(&DataLocale::default(), options)
+                .expect("the default collation is built in")
+        });
+
+        Self { collator }
+    }
+
+    /// Texts that the collator sees as the same, like with different Unicode normalization,
+    /// are still ordered by their characters, so only equal texts are equal.
+    pub fn compare(&self, first: &str, second: &str) -> Ordering {
+        self.collator
+            .compare(first, second)
+            .then_with(|| first.cmp(second))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::library::collation::{fold, natural_cmp, Collation};
+    use std::cmp::Ordering;
+    use test_log::test;
+
+    #[test]
+    fn fold_removes_case_and_accents() {
+        assert_eq!(fold("Björk"), "bjork");
+        assert_eq!(fold("Café"), "cafe");
+        assert_eq!(fold("Sigur Rós"), "sigur ros");
+        assert_eq!(fold("Mø"), "mo");
+        assert_eq!(fold("Straße"), "strasse");
+        assert_eq!(fold("ﬁre"), "fire");
+    }
+
+    #[test]
+    fn natural_cmp_sorts_numbers_by_value() {
+        assert_eq!(natural_cmp("Track 2", "Track 10"), Ordering::Less);
+        assert_eq!(natural_cmp("Track 02", "Track 10"), Ordering::Less);
+        assert_eq!(natural_cmp("Track 10", "Track 9"), Ordering::Greater);
+        assert_eq!(natural_cmp("1999", "Angel"), Ordering::Less);
+    }
+
+    #[test]
+    fn natural_cmp_ignores_case_and_accents_before_letters() {
+        let mut titles = vec![
+            "zebra", "Émile", "apple", "Bjork", "björk", "Banana", "Björk",
+        ];
+        titles.sort_by(|first, second| natural_cmp(first, second));
+        assert_eq!(
+            titles,
+            vec!["apple", "Banana", "Bjork", "Björk", "björk", "Émile", "zebra"]
+        );
+    }
+
+    #[test]
+    fn collation_sorts_letters_in_the_order_of_the_language() {
+        let sort = |locale: &str| {
+            let collation = Collation::new(locale);
+            let mut artists = vec!["Zara Larsson", "Östen med Resten", "Ola Salo", "Åsa Jinder"];
+            artists.sort_by(|first, second| collation.compare(first, second));
+            artists
+        };
+
+        assert_eq!(
+            sort("sv-SE"),
+            vec!["Ola Salo", "Zara Larsson", "Åsa Jinder", "Östen med Resten"]
+        );
+        assert_eq!(
+            sort("de-DE"),
+            vec!["Åsa Jinder", "Ola Salo", "Östen med Resten", "Zara Larsson"]
+        );
+    }
+
+    #[test]
+    fn collation_sorts_numbers_by_value_in_every_language() {
+        for locale in ["sv", "de", "und"] {
+            let collation = Collation::new(locale);
+            assert_eq!(collation.compare("Track 9", "Track 10"), Ordering::Less);
+            assert_eq!(collation.compare("Track 02", "Track 10"), Ordering::Less);
+        }
+    }
+}
@@ -0,0 +1,373 @@
+use crate::library::collation::SortKey;
+use crate::library::{Library, Song, SongId, SongStats};
+use serde_derive::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Something about a song that can be shown in a column of a song list.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum Column {
+    Title,
+    Artist,
+    Album,
+    Duration,
+    PlayCount,
+    DateAdded,
+    /// The file extension.
+    Format,
+    /// Relative to the root the song was found in.
+    Path,
+}
+
+impl Column {
+    pub const ALL: [Column; 8] = [
+        Column::Title,
+        Column::Artist,
+        Column::Album,
+        Column::Duration,
+        Column::PlayCount,
+        Column::DateAdded,
+        Column::Format,
+        Column::Path,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Column::Title => "Title",
+            Column::Artist => "Artist",
+            Column::Album => "Album",
+            Column::Duration => "Duration",
+            Column::PlayCount => "Plays",
+            Column::DateAdded => "Added",
+            Column::Format => "Format",
+            Column::Path => "Path",
+        }
+    }
+
+    /// Empty if the song doesn't have a value for this column.
+    pub fn text(&self, song: &Song, stats: SongStats) -> String {
+        match self {
+            Column::Title => song.title.clone(),
+            Column::Artist => song.artist.clone().unwrap_or_default(),
+            Column::Album => song.album.clone().unwrap_or_default(),
+            Column::Duration => song.duration.map(format_duration).unwrap_or_default(),
+            Column::PlayCount => stats.play_count.to_string(),
+            Column::DateAdded => stats.date_added.map(format_date).unwrap_or_default(),
+            Column::Format => format(song).unwrap_or_default(),
+            Column::Path => relative_path(song),
+        }
+    }
+
+    /// `None` if the song doesn't have a value for this column.
+    fn sort_key(&self, song: &Song, stats: SongStats) -> Option<ColumnKey> {
+        let text = |text: &str| Some(ColumnKey::Text(SortKey::new(text)));
+
+        match self {
+            Column::Title => text(&song.title),
+            Column::Artist => text(song.artist.as_deref()?),
+            Column::Album => text(song.album.as_deref()?),
+            Column::Duration => song.duration.map(ColumnKey::Duration),
+            Column::PlayCount => Some(ColumnKey::Number(stats.play_count.into())),
+            Column::DateAdded => stats.date_added.map(ColumnKey::Time),
+            Column::Format => text(&format(song)?),
+            Column::Path => text(&relative_path(song)),
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd)]
+enum ColumnKey {
+    Text(SortKey),
+    Number(u64),
+    Duration(Duration),
+    Time(SystemTime),
+}
+
+/// A column that is shown, and how wide it is.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ColumnWidth {
+    pub column: Column,
+    pub width: f32,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SortOrder {
+    pub column: Column,
+    pub descending: bool,
+}
+
+impl SortOrder {
+    /// Sorts the items by the songs they refer to. The sort is stable.
+    /// Songs without a value for the column, or that are not in the library, always come last.
+    pub fn sort<T, F>(&self, library: &Library, items: &mut [T], song_id: F)
+    where
+        T: Copy,
+        F: Fn(&T) -> SongId,
+    {
+        let mut keyed: Vec<(Option<ColumnKey>, T)> = items
+            .iter()
+            .map(|item| {
+                let id = song_id(item);
+                let key = library
+                    .get_song(&id)
+                    .and_then(|song| self.column.sort_key(song, library.song_stats(&id)));
+                (key, *item)
+            })
+            .collect();
+
+        keyed.sort_by(|(first, _), (second, _)| match (first, second) {
+            (Some(first), Some(second)) if self.descending => second.cmp(first),
+            (Some(first), Some(second)) => first.cmp(second),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        });
+
+        for (item, (_, sorted)) in items.iter_mut().zip(keyed) {
+            *item = sorted;
+        }
+    }
+}
+
+/// Which columns a song list shows, and what it is sorted by.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColumnLayout {
+    pub columns: Vec<ColumnWidth>,
+    /// `None` keeps the order of the list itself.
+    pub sort: Option<SortOrder>,
+}
+
+impl Default for ColumnLayout {
+    fn default() -> Self {
+        Self {
+            columns: vec![
+                ColumnWidth {
+                    column: Column::Title,
+                    width: 250.0,
+                },
+                ColumnWidth {
+                    column: Column::Artist,
+                    width: 150.0,
+                },
+                ColumnWidth {
+                    column: Column::Duration,
+                    width: 60.0,
+                },
+            ],
+            sort: None,
+        }
+    }
+}
+
+impl ColumnLayout {
+    pub const DEFAULT_WIDTH: f32 = 120.0;
+
+    pub fn is_shown(&self, column: Column) -> bool {
+        self.columns.iter().any(|shown| shown.column == column)
+    }
+
+    /// Shows the column at the end, or hides it if it was shown.
+    /// The last column can't be hidden.
+    pub fn toggle_column(&mut self, column: Column) {
+        if self.is_shown(column) {
+            if self.columns.len() > 1 {
+                self.columns.retain(|shown| shown.column != column);
+            }
+        } else {
+            self.columns.push(ColumnWidth {
+                column,
+                width: Self::DEFAULT_WIDTH,
+            });
+        }
+    }
+
+    /// What clicking the header of a column does: sort by it, then sort the other way around,
+    /// and then go back to the order of the list itself.
+    pub fn cycle_sort(&mut self, column: Column) {
+        self.sort = match self.sort {
+            Some(SortOrder {
+                column: sorted,
+                descending: false,
+            }) if sorted == column => Some(SortOrder {
+                column,
+                descending: true,
+            }),
+            Some(SortOrder {
+                column: sorted,
+                descending: true,
+            }) if sorted == column => None,
+            _ => Some(SortOrder {
+                column,
+                descending: false,
+            }),
+        };
+    }
+}
+
+/// Like "3:07", or "1:02:03" for songs of an hour or longer.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+/// Like "2022-04-29", in UTC.
+pub fn format_date(time: SystemTime) -> String {
+    let seconds = match time.duration_since(UNIX_EPOCH) {
+        Ok(since_epoch) => since_epoch.as_secs() as i64,
+        Err(before_epoch) => {
+            let before_epoch = before_epoch.duration();
+            // Part of a second before a whole second is still in the second before it.
+            -(before_epoch.as_secs() as i64) - i64::from(before_epoch.subsec_nanos() > 0)
+        }
+    };
+    // Rounded down, also before 1970, so the day before the epoch is -1.
+    let days = seconds.div_euclid(86400);
+
+    // Converts days since 1970-01-01 to a date, from http://howardhinnant.github.io/date_algorithms.html
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+fn format(song: &Song) -> Option<String> {
+    song.path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_uppercase())
+}
+
+fn relative_path(song: &Song) -> String {
+    song.path
+        .strip_prefix(&song.root)
+        .unwrap_or(&song.path)
+        .display()
+        .to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::library::columns::{format_date, format_duration, Column, ColumnLayout, SortOrder};
+    use crate::library::{Library, Song, SongId};
+    use std::path::PathBuf;
+    use std::time::{Duration, UNIX_EPOCH};
+    use test_log::test;
+
+    fn add_song(library: &mut Library, title: &str, duration: Option<u64>) -> SongId {
+        library.add_song(Song {
+            title: title.to_string(),
+            path: PathBuf::from(format!("{}.mp3", title)),
+            duration: duration.map(Duration::from_secs),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn sort_order_sorts_by_column_and_puts_missing_values_last() {
+        let mut library = Library::new();
+        let track_10 = add_song(&mut library, "Track 10", Some(200));
+        let track_2 = add_song(&mut library, "Track 2", None);
+        let apple = add_song(&mut library, "apple", Some(100));
+        let missing = SongId(17);
+
+        let sorted = |library: &Library, column, descending| {
+            let mut ids = vec![missing, track_10, track_2, apple];
+            SortOrder { column, descending }.sort(library, &mut ids, |&id| id);
+            ids
+        };
+
+        assert_eq!(
+            sorted(&library, Column::Title, false),
+            vec![apple, track_2, track_10, missing]
+        );
+        assert_eq!(
+            sorted(&library, Column::Title, true),
+            vec![track_10, track_2, apple, missing]
+        );
+        assert_eq!(
+            sorted(&library, Column::Duration, true),
+            vec![track_10, apple, missing, track_2]
+        );
+
+        library.record_play(&apple);
+        library.record_play(&apple);
+        library.record_play(&track_2);
+        assert_eq!(
+            sorted(&library, Column::PlayCount, true),
+            vec![apple, track_2, track_10, missing]
+        );
+    }
+
+    #[test]
+    fn column_layout_toggles_columns_and_cycles_sort() {
+        let mut layout = ColumnLayout::default();
+
+        layout.toggle_column(Column::Path);
+        assert!(layout.is_shown(Column::Path));
+        layout.toggle_column(Column::Path);
+        assert!(!layout.is_shown(Column::Path));
+
+        for column in Column::ALL {
+            if layout.is_shown(column) {
+                layout.toggle_column(column);
+            }
+        }
+        assert_eq!(layout.columns.len(), 1);
+
+        layout.cycle_sort(Column::Title);
+        assert_eq!(
+            layout.sort,
+            Some(SortOrder {
+                column: Column::Title,
+                descending: false
+            })
+        );
+        layout.cycle_sort(Column::Title);
+        assert!(layout.sort.unwrap().descending);
+        layout.cycle_sort(Column::Title);
+        assert_eq!(layout.sort, None);
+    }
+
+    #[test]
+    fn formats_durations_and_dates() {
+        assert_eq!(format_duration(Duration::from_secs(187)), "3:07");
+        assert_eq!(format_duration(Duration::from_secs(3723)), "1:02:03");
+
+        assert_eq!(format_date(UNIX_EPOCH), "1970-01-01");
+        let day = Duration::from_secs(86400);
+        assert_eq!(format_date(UNIX_EPOCH + day * 19111), "2022-04-29");
+        assert_eq!(format_date(UNIX_EPOCH + day * 11016), "2000-02-29");
+
+        assert_eq!(
+            format_date(UNIX_EPOCH - Duration::from_nanos(1)),
+            "1969-12-31"
+        );
+        assert_eq!(
+            format_date(UNIX_EPOCH - Duration::from_secs(86400)),
+            "1969-12-31"
+        );
+        assert_eq!(
+            format_date(UNIX_EPOCH - Duration::from_secs(86401)),
+            "1969-12-30"
+        );
+        assert_eq!(format_date(UNIX_EPOCH - day * 365), "1969-01-01");
+    }
+}
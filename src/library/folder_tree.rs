@@ -0,0 +1,214 @@
+use crate::library::collation::natural_cmp;
+use crate::library::{Library, SongId};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::path::{Component, PathBuf};
+
+/// The songs of the library, grouped by the folders they are in.
+/// There is a top level folder for every [LibraryRoot](crate::library::LibraryRoot)
+/// that has songs, the folders below it follow the paths relative to that root.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FolderTree {
+    pub roots: Vec<Folder>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Folder {
+    /// For a root this is the whole path, otherwise only the name of the folder.
+    pub name: String,
+    pub path: PathBuf,
+    /// Sorted by name.
+    pub folders: Vec<Folder>,
+    /// The songs directly in this folder, sorted by file name.
+    pub songs: Vec<FolderSong>,
+    /// The songs in this folder and all folders below it.
+    song_count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FolderSong {
+    pub file_name: String,
+    pub id: SongId,
+}
+
+impl FolderTree {
+    /// Groups the given songs. Songs that are not in the library are left out.
+    pub fn new(library: &Library, song_ids: impl IntoIterator<Item = SongId>) -> Self {
+        let mut roots: BTreeMap<PathBuf, FolderBuilder> = BTreeMap::new();
+
+        for id in song_ids {
+            let song = match library.get_song(&id) {
+                Some(song) => song,
+                None => continue,
+            };
+
+            let relative_path = song.path.strip_prefix(&song.root).unwrap_or(&song.path);
+            let mut folder = roots.entry(song.root.clone()).or_default();
+
+            let mut components: Vec<String> = relative_path
+                .components()
+                .filter_map(|component| match component {
+                    Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+                    _ => None,
+                })
+                .collect();
+            let file_name = components.pop().unwrap_or_default();
+
+            for name in components {
+                folder = folder.folders.entry(name).or_default();
+            }
+            folder.songs.push(FolderSong { file_name, id });
+        }
+
+        Self {
+            roots: roots
+                .into_iter()
+                .map(|(path, root)| root.build(path.display().to_string(), path))
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roots.is_empty()
+    }
+}
+
+impl Folder {
+    /// The songs in this folder and all folders below it.
+    pub fn song_count(&self) -> usize {
+        self.song_count
+    }
+
+    /// All songs in this folder and the folders below it, in the order of their paths.
+    pub fn all_songs(&self) -> Vec<SongId> {
+        let mut song_ids = Vec::with_capacity(self.song_count);
+        self.collect_songs(&mut song_ids);
+        song_ids
+    }
+
+    fn collect_songs(&self, song_ids: &mut Vec<SongId>) {
+        let mut folders = self.folders.iter().peekable();
+        let mut songs = self.songs.iter().peekable();
+
+        // Both are sorted by name already, so they only have to be merged.
+        loop {
+            let folder_is_next = match (folders.peek(), songs.peek()) {
+                (Some(folder), Some(song)) => {
+                    natural_cmp(&folder.name, &song.file_name) != Ordering::Greater
+                }
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => break,
+            };
+
+            if folder_is_next {
+                if let Some(folder) = folders.next() {
+                    folder.collect_songs(song_ids);
+                }
+            } else if let Some(song) = songs.next() {
+                song_ids.push(song.id);
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct FolderBuilder {
+    folders: BTreeMap<String, FolderBuilder>,
+    songs: Vec<FolderSong>,
+}
+
+impl FolderBuilder {
+    fn build(self, name: String, path: PathBuf) -> Folder {
+        let mut folders: Vec<Folder> = self
+            .folders
+            .into_iter()
+            .map(|(name, folder)| {
+                let path = path.join(&name);
+                folder.build(name, path)
+            })
+            .collect();
+        folders.sort_by(|first, second| natural_cmp(&first.name, &second.name));
+
+        let mut songs = self.songs;
+        songs.sort_by(|first, second| natural_cmp(&first.file_name, &second.file_name));
+
+        let song_count = songs.len() + folders.iter().map(Folder::song_count).sum::<usize>();
+
+        Folder {
+            name,
+            path,
+            folders,
+            songs,
+            song_count,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::library::folder_tree::FolderTree;
+    use crate::library::{Library, Song, SongId};
+    use std::path::PathBuf;
+    use test_log::test;
+
+    fn add_song(library: &mut Library, root: &str, relative_path: &str) -> SongId {
+        let root = PathBuf::from(root);
+        library.add_song(Song {
+            path: root.join(relative_path),
+            root,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn folder_tree_groups_songs_by_folder() {
+        let mut library = Library::new();
+        let loose = add_song(&mut library, "music", "loose.mp3");
+        let track_10 = add_song(&mut library, "music", "Artist/Album/10 - Ten.mp3");
+        let track_2 = add_song(&mut library, "music", "Artist/Album/2 - Two.mp3");
+        let single = add_song(&mut library, "music", "Artist/single.mp3");
+        let other = add_song(&mut library, "other", "podcast.mp3");
+
+        let tree = FolderTree::new(&library, [loose, track_10, track_2, single, other]);
+
+        assert_eq!(tree.roots.len(), 2);
+        let music = &tree.roots[0];
+        assert_eq!(music.name, "music");
+        assert_eq!(music.song_count(), 4);
+        assert_eq!(music.songs.len(), 1);
+        assert_eq!(music.songs[0].id, loose);
+
+        let artist = &music.folders[0];
+        assert_eq!(artist.name, "Artist");
+        assert_eq!(artist.path, PathBuf::from("music").join("Artist"));
+        assert_eq!(artist.song_count(), 3);
+
+        let album = &artist.folders[0];
+        assert_eq!(album.song_count(), 2);
+        assert_eq!(album.songs[0].file_name, "2 - Two.mp3");
+
+        assert_eq!(tree.roots[1].all_songs(), vec![other]);
+    }
+
+    #[test]
+    fn folder_all_songs_is_in_path_order() {
+        let mut library = Library::new();
+        let a = add_song(&mut library, "music", "a.mp3");
+        let b = add_song(&mut library, "music", "b/song.mp3");
+        let c = add_song(&mut library, "music", "c.mp3");
+        let d_2 = add_song(&mut library, "music", "d/2.mp3");
+        let d_10 = add_song(&mut library, "music", "d/10.mp3");
+
+        let tree = FolderTree::new(&library, [d_10, c, b, a, d_2]);
+
+        assert_eq!(tree.roots[0].all_songs(), vec![a, b, c, d_2, d_10]);
+    }
+
+    #[test]
+    fn folder_tree_leaves_out_unknown_songs() {
+        let library = Library::new();
+
+        assert!(FolderTree::new(&library, [SongId(3)]).is_empty());
+    }
+}
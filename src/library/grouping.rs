@@ -0,0 +1,323 @@
+use crate::library::collation::{fold, SortKey};
+use crate::library::{Library, Song, SongId};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+pub const UNKNOWN_ARTIST: &str = "Unknown artist";
+pub const UNKNOWN_ALBUM: &str = "Unknown album";
+
+/// The songs of an artist, grouped by album.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArtistGroup {
+    pub name: String,
+    /// Oldest first. Albums without a year come last.
+    pub albums: Vec<AlbumGroup>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlbumGroup {
+    pub title: String,
+    /// The earliest year of the songs on the album.
+    pub year: Option<u32>,
+    /// Sorted by disc and track number.
+    pub songs: Vec<SongId>,
+}
+
+impl ArtistGroup {
+    pub fn song_count(&self) -> usize {
+        self.albums.iter().map(|album| album.songs.len()).sum()
+    }
+
+    /// All songs of the artist, album by album.
+    pub fn all_songs(&self) -> Vec<SongId> {
+        self.albums
+            .iter()
+            .flat_map(|album| album.songs.iter().copied())
+            .collect()
+    }
+}
+
+/// Keyed by the [folded](fold) name, so names that only differ in case or accents
+/// end up together. Next to the value are the ways the name was written.
+type ByFoldedName<T> = HashMap<String, (Spellings, T)>;
+
+/// How often each way of writing a name was used.
+#[derive(Debug, Default)]
+struct Spellings(HashMap<String, usize>);
+
+impl Spellings {
+    fn add(&mut self, name: &str) {
+        match self.0.get_mut(name) {
+            Some(count) => *count += 1,
+            None => {
+                self.0.insert(name.to_string(), 1);
+            }
+        }
+    }
+
+    /// The way the name was written most often. Of spellings that are used equally often,
+    /// the one that is sorted first, so the name doesn't depend on the order of the songs.
+    fn most_common(self) -> String {
+        self.0
+            .into_iter()
+            .map(|(name, count)| (Reverse(count), SortKey::new(&name), name))
+            .min()
+            .map(|(_, _, name)| name)
+            .unwrap_or_default()
+    }
+}
+
+/// See [Library::group_by_artist].
+pub(crate) fn group_by_artist(
+    library: &Library,
+    song_ids: impl IntoIterator<Item = SongId>,
+) -> Vec<ArtistGroup> {
+    let mut artists: ByFoldedName<ByFoldedName<Vec<SongId>>> = HashMap::new();
+
+    for id in song_ids {
+        let song = match library.get_song(&id) {
+            Some(song) => song,
+            None => continue,
+        };
+
+        let artist = song
+            .album_artist
+            .as_deref()
+            .or(song.artist.as_deref())
+            .unwrap_or(UNKNOWN_ARTIST);
+        let album = song.album.as_deref().unwrap_or(UNKNOWN_ALBUM);
+
+        let (artist_spellings, albums) = artists.entry(fold(artist)).or_default();
+        artist_spellings.add(artist);
+        let (album_spellings, songs) = albums.entry(fold(album)).or_default();
+        album_spellings.add(album);
+        songs.push(id);
+    }
+
+    let mut artists: Vec<ArtistGroup> = artists
+        .into_values()
+        .map(|(spellings, albums)| {
+            let mut albums: Vec<AlbumGroup> = albums
+                .into_values()
+                .map(|(spellings, songs)| album_group(library, spellings.most_common(), songs))
+                .collect();
+            albums.sort_by_cached_key(|album| {
+                (album.year.is_none(), album.year, SortKey::new(&album.title))
+            });
+
+            ArtistGroup {
+                name: spellings.most_common(),
+                albums,
+            }
+        })
+        .collect();
+    artists.sort_by_cached_key(|artist| SortKey::new(&artist.name));
+
+    artists
+}
+
+fn album_group(library: &Library, title: String, mut songs: Vec<SongId>) -> AlbumGroup {
+    let song = |id: &SongId| library.get_song(id);
+
+    songs.sort_by_cached_key(|id| {
+        let song: Option<&Song> = song(id);
+        (
+            song.and_then(|song| song.disc_number).unwrap_or(0),
+            // Songs without a track number come after the numbered ones.
+            song.and_then(|song| song.track_number).unwrap_or(u32::MAX),
+            song.map(|song| SortKey::new(&song.title)),
+        )
+    });
+
+    let year = songs
+        .iter()
+        .filter_map(|id| song(id).and_then(|song| song.year))
+        .min();
+
+    AlbumGroup { title, year, songs }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::library::grouping::{UNKNOWN_ALBUM, UNKNOWN_ARTIST};
+    use crate::library::{Library, Song, SongId};
+    use std::path::PathBuf;
+    use test_log::test;
+
+    fn add_song(
+        library: &mut Library,
+        title: &str,
+        artist: Option<&str>,
+        album: Option<&str>,
+        year: Option<u32>,
+        disc_and_track: (Option<u32>, Option<u32>),
+    ) -> SongId {
+        library.add_song(Song {
+            title: title.to_string(),
+            path: PathBuf::from(title),
+            artist: artist.map(str::to_string),
+            album: album.map(str::to_string),
+            year,
+            disc_number: disc_and_track.0,
+            track_number: disc_and_track.1,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn group_by_artist_orders_albums_by_year_and_tracks_by_number() {
+        let mut library = Library::new();
+        let kid_a = Some("Kid A");
+        let ok_computer = Some("OK Computer");
+        let radiohead = Some("Radiohead");
+
+        let idioteque = add_song(
+            &mut library,
+            "Idioteque",
+            radiohead,
+            kid_a,
+            Some(2000),
+            (None, Some(8)),
+        );
+        let everything = add_song(
+            &mut library,
+            "Everything",
+            radiohead,
+            kid_a,
+            Some(2000),
+            (None, Some(1)),
+        );
+        let police = add_song(
+            &mut library,
+            "Karma Police",
+            radiohead,
+            ok_computer,
+            Some(1997),
+            (Some(1), Some(6)),
+        );
+        let bonus = add_song(
+            &mut library,
+            "Bonus",
+            radiohead,
+            ok_computer,
+            Some(1997),
+            (Some(2), Some(1)),
+        );
+        let android = add_song(
+            &mut library,
+            "Paranoid Android",
+            radiohead,
+            ok_computer,
+            Some(1997),
+            (Some(1), Some(2)),
+        );
+        let untitled = add_song(
+            &mut library,
+            "Untitled",
+            radiohead,
+            Some("Bootleg"),
+            None,
+            (None, None),
+        );
+
+        let artists = library.group_by_artist(library.songs().map(|(&id, _)| id));
+
+        assert_eq!(artists.len(), 1);
+        let albums = &artists[0].albums;
+        assert_eq!(
+            albums
+                .iter()
+                .map(|album| album.title.as_str())
+                .collect::<Vec<_>>(),
+            vec!["OK Computer", "Kid A", "Bootleg"]
+        );
+        assert_eq!(albums[0].songs, vec![android, police, bonus]);
+        assert_eq!(albums[1].songs, vec![everything, idioteque]);
+        assert_eq!(albums[1].year, Some(2000));
+        assert_eq!(albums[2].songs, vec![untitled]);
+        assert_eq!(artists[0].song_count(), 6);
+    }
+
+    #[test]
+    fn group_by_artist_merges_spellings_and_handles_missing_tags() {
+        let mut library = Library::new();
+
+        let joga = add_song(
+            &mut library,
+            "Jóga",
+            Some("Björk"),
+            Some("Homogenic"),
+            None,
+            (None, Some(1)),
+        );
+        let bachelorette = add_song(
+            &mut library,
+            "Bachelorette",
+            Some("bjork"),
+            Some("homogenic"),
+            None,
+            (None, Some(2)),
+        );
+        let untagged = add_song(&mut library, "untagged", None, None, None, (None, None));
+        let abba = add_song(
+            &mut library,
+            "Waterloo",
+            Some("ABBA"),
+            None,
+            None,
+            (None, None),
+        );
+
+        let artists = library.group_by_artist([untagged, bachelorette, joga, abba]);
+
+        assert_eq!(
+            artists
+                .iter()
+                .map(|artist| artist.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["ABBA", "bjork", UNKNOWN_ARTIST]
+        );
+        assert_eq!(artists[0].albums[0].title, UNKNOWN_ALBUM);
+        assert_eq!(artists[1].albums.len(), 1);
+        assert_eq!(artists[1].all_songs(), vec![joga, bachelorette]);
+        assert_eq!(artists[2].all_songs(), vec![untagged]);
+    }
+
+    #[test]
+    fn group_by_artist_names_groups_by_the_most_common_spelling() {
+        let mut library = Library::new();
+        let mut add = |title, artist, album| {
+            add_song(
+                &mut library,
+                title,
+                Some(artist),
+                Some(album),
+                None,
+                (None, None),
+            )
+        };
+        let songs = vec![
+            add("Hoppípolla", "Sigur Ros", "Takk..."),
+            add("Glósóli", "Sigur Rós", "Takk..."),
+            add("Sé lest", "Sigur Rós", "TAKK..."),
+            add("Svefn-g-englar", "Sigur Rós", "Ágætis byrjun"),
+            add("Starálfur", "Sigur Ros", "Agaetis byrjun"),
+        ];
+
+        for order in [songs.clone(), songs.iter().rev().copied().collect()] {
+            let artists = library.group_by_artist(order);
+
+            assert_eq!(artists.len(), 1);
+            assert_eq!(artists[0].name, "Sigur Rós");
+            // Of spellings that are used equally often, the one sorted first is used.
+            assert_eq!(
+                artists[0]
+                    .albums
+                    .iter()
+                    .map(|album| album.title.as_str())
+                    .collect::<Vec<_>>(),
+                vec!["Agaetis byrjun", "Takk..."]
+            );
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use crate::library::SongId;
+use std::collections::VecDeque;
+
+/// Songs to play next, before continuing with the playlist.
+/// Unlike a playlist, songs leave the queue once they are played.
+#[derive(Debug, Default)]
+pub struct PlayQueue {
+    songs: VecDeque<SongId>,
+}
+
+impl PlayQueue {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The songs in the order they will be played.
+    pub fn iter(&self) -> impl Iterator<Item = &SongId> {
+        self.songs.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.songs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.songs.is_empty()
+    }
+
+    /// Adds the songs to the end of the queue, in the given order.
+    pub fn add_songs(&mut self, song_ids: Vec<SongId>) {
+        self.songs.extend(song_ids);
+    }
+
+    /// Takes the song that should be played next out of the queue.
+    pub fn pop_next(&mut self) -> Option<SongId> {
+        self.songs.pop_front()
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        self.songs.remove(index);
+    }
+
+    /// Moves the song at `from`, so it ends up at `target`.
+    /// Does nothing if either index is past the end of the queue.
+    pub fn move_song(&mut self, from: usize, target: usize) {
+        if from >= self.songs.len() || target >= self.songs.len() {
+            return;
+        }
+
+        if let Some(song_id) = self.songs.remove(from) {
+            self.songs.insert(target, song_id);
+        }
+    }
+
+    /// Makes the queue refer to other songs, like [Playlist::replace_song_ids](crate::library::Playlist::replace_song_ids).
+    pub fn replace_song_ids(&mut self, new_id: impl Fn(SongId) -> SongId) {
+        for song_id in self.songs.iter_mut() {
+            *song_id = new_id(*song_id);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.songs.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::library::play_queue::PlayQueue;
+    use crate::library::SongId;
+    use test_log::test;
+
+    fn song_ids(queue: &PlayQueue) -> Vec<SongId> {
+        queue.iter().copied().collect()
+    }
+
+    #[test]
+    fn play_queue_plays_songs_in_order() {
+        let mut queue = PlayQueue::new();
+        queue.add_songs(vec![SongId(1), SongId(2)]);
+        queue.add_songs(vec![SongId(3)]);
+
+        assert_eq!(queue.pop_next(), Some(SongId(1)));
+        assert_eq!(queue.pop_next(), Some(SongId(2)));
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.pop_next(), Some(SongId(3)));
+        assert_eq!(queue.pop_next(), None);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn play_queue_reorder_and_remove() {
+        let mut queue = PlayQueue::new();
+        queue.add_songs(vec![SongId(1), SongId(2), SongId(3), SongId(4)]);
+
+        queue.move_song(3, 0);
+        assert_eq!(
+            song_ids(&queue),
+            vec![SongId(4), SongId(1), SongId(2), SongId(3)]
+        );
+        queue.move_song(1, 2);
+        assert_eq!(
+            song_ids(&queue),
+            vec![SongId(4), SongId(2), SongId(1), SongId(3)]
+        );
+        queue.move_song(0, 4);
+        assert_eq!(
+            song_ids(&queue),
+            vec![SongId(4), SongId(2), SongId(1), SongId(3)]
+        );
+
+        queue.remove(1);
+        assert_eq!(song_ids(&queue), vec![SongId(4), SongId(1), SongId(3)]);
+
+        queue.clear();
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,531 @@
+use crate::library::{Library, Playlist, SongId};
+use anyhow::{anyhow, Result};
+use log::warn;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+pub mod m3u;
+pub mod pls;
+pub mod xspf;
+
+/// A song in a playlist file.
+/// The file can be written by another program, so the song is not necessarily in the library.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaylistFileEntry {
+    /// As it is written in the file.
+    /// Relative paths are relative to the directory the playlist file is in.
+    pub path: PathBuf,
+    pub title: Option<String>,
+    /// Only formats with a separate field for it have this.
+    /// The others often have it in the title, like "Artist - Title".
+    pub artist: Option<String>,
+    pub duration: Option<Duration>,
+}
+
+impl PlaylistFileEntry {
+    /// The title for formats without a separate field for the artist.
+    fn display_title(&self) -> String {
+        match (&self.artist, &self.title) {
+            (Some(artist), Some(title)) => format!("{} - {}", artist, title),
+            (Some(artist), None) => artist.clone(),
+            (None, title) => title.clone().unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PlaylistFormat {
+    /// Extended M3U. Read as UTF-8, or as Latin-1 if it isn't valid UTF-8.
+    M3u,
+    /// Extended M3U that is always UTF-8.
+    M3u8,
+    /// XML Shareable Playlist Format.
+    Xspf,
+    /// Like an INI file. Read as UTF-8, or as Latin-1 if it isn't valid UTF-8.
+    Pls,
+}
+
+impl PlaylistFormat {
+    /// Extensions of all files that can be loaded as a playlist.
+    pub const EXTENSIONS: [&'static str; 4] = ["m3u", "m3u8", "xspf", "pls"];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PlaylistFormat::M3u => "M3U",
+            PlaylistFormat::M3u8 => "M3U8",
+            PlaylistFormat::Xspf => "XSPF",
+            PlaylistFormat::Pls => "PLS",
+        }
+    }
+
+    /// Recognizes the format by the file extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+
+        match extension.as_str() {
+            "m3u" => Some(PlaylistFormat::M3u),
+            "m3u8" => Some(PlaylistFormat::M3u8),
+            "xspf" => Some(PlaylistFormat::Xspf),
+            "pls" => Some(PlaylistFormat::Pls),
+            _ => None,
+        }
+    }
+}
+
+/// How the paths of songs are written to a playlist file.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PathStyle {
+    /// Relative to the directory of the playlist file, so the playlist keeps working
+    /// when it is moved together with the songs.
+    /// Songs on another drive than the playlist get an absolute path anyway.
+    Relative,
+    Absolute,
+}
+
+/// A playlist file, with its entries looked up in the library.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LoadedPlaylist {
+    /// The songs that are in the library, in the order of the file.
+    pub songs: Vec<SongId>,
+    /// The entries of songs that are not in the library. These are left out of `songs`.
+    pub missing: Vec<PlaylistFileEntry>,
+}
+
+/// Writes the songs of the playlist to a file, in the format that matches its extension.
+/// Entries whose song is no longer in the library are left out, since their path is unknown.
+pub fn save_playlist(
+    playlist: &Playlist,
+    library: &Library,
+    path: &Path,
+    path_style: PathStyle,
+) -> Result<()> {
+    let format = PlaylistFormat::from_path(path)
+        .ok_or_else(|| anyhow!("'{}' is not a known playlist format", path.display()))?;
+
+    let playlist_dir = absolute_path(path)?
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+
+    let mut entries = Vec::with_capacity(playlist.song_count());
+    let mut missing_songs = 0;
+
+    for (_, song_id) in playlist.song_ids() {
+        let song = match library.get_song(song_id) {
+            Some(song) => song,
+            None => {
+                missing_songs += 1;
+                continue;
+            }
+        };
+
+        let song_path = absolute_path(&song.path)?;
+        let path = match path_style {
+            PathStyle::Relative => relative_path(&playlist_dir, &song_path).unwrap_or(song_path),
+            PathStyle::Absolute => song_path,
+        };
+
+        entries.push(PlaylistFileEntry {
+            path,
+            title: Some(song.title.clone()),
+            artist: song.artist.clone(),
+            duration: song.duration,
+        });
+    }
+
+    if missing_songs > 0 {
+        warn!(
+            "Left {} songs out of playlist '{}', they are no longer in the library.",
+            missing_songs,
+            path.display()
+        );
+    }
+
+    let contents = match format {
+        PlaylistFormat::M3u | PlaylistFormat::M3u8 => m3u::write(&entries),
+        PlaylistFormat::Xspf => xspf::write(&entries),
+        PlaylistFormat::Pls => pls::write(&entries),
+    };
+
+    fs::write(path, contents)?;
+
+    Ok(())
+}
+
+/// Reads a playlist file, in the format that matches its extension,
+/// and looks up its entries in the library.
+pub fn load_playlist(path: &Path, library: &Library) -> Result<LoadedPlaylist> {
+    let format = PlaylistFormat::from_path(path)
+        .ok_or_else(|| anyhow!("'{}' is not a known playlist format", path.display()))?;
+
+    let bytes = fs::read(path)?;
+    let entries = match format {
+        PlaylistFormat::M3u => m3u::read(&decode_text(&bytes)),
+        PlaylistFormat::M3u8 => m3u::read(&String::from_utf8(bytes)?),
+        PlaylistFormat::Xspf => xspf::read(&String::from_utf8(bytes)?)?,
+        PlaylistFormat::Pls => pls::read(&decode_text(&bytes))?,
+    };
+
+    let playlist_dir = absolute_path(path)?
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+
+    let song_paths = SongPaths::new(library)?;
+    let mut loaded = LoadedPlaylist::default();
+
+    for entry in entries {
+        match song_paths.find(&normalize_path(&playlist_dir.join(&entry.path))) {
+            Some(id) => loaded.songs.push(id),
+            None => loaded.missing.push(entry),
+        }
+    }
+
+    Ok(loaded)
+}
+
+/// Why a playlist file could not be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlaylistFileError {
+    pub kind: PlaylistFileErrorKind,
+    /// Line in the file where the problem was found, starting at 1.
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlaylistFileErrorKind {
+    /// The file is not a playlist of the format its extension says it is.
+    WrongFormat(PlaylistFormat),
+    /// XML that is not well-formed, like a tag that is not closed.
+    InvalidXml(String),
+    /// XML elements nested deeper than any playlist needs.
+    TooDeeplyNested,
+    MissingElement(&'static str),
+    /// A line that is not a `key=value` pair.
+    InvalidLine(String),
+    InvalidNumber(String),
+    /// The title or length of an entry is given, but not its file.
+    MissingFile(u32),
+    /// The amount of entries the file says it has doesn't match the entries that are in it.
+    WrongEntryCount {
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for PlaylistFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            PlaylistFileErrorKind::WrongFormat(format) => {
+                write!(f, "The file is not a {} playlist", format.name())
+            }
+            PlaylistFileErrorKind::InvalidXml(problem) => write!(f, "Invalid XML: {}", problem),
+            PlaylistFileErrorKind::TooDeeplyNested => {
+                write!(f, "The XML elements are nested too deeply")
+            }
+            PlaylistFileErrorKind::MissingElement(name) => {
+                write!(f, "The <{}> element is missing", name)
+            }
+            PlaylistFileErrorKind::InvalidLine(line) => {
+                write!(f, "'{}' is not a key=value pair", line)
+            }
+            PlaylistFileErrorKind::InvalidNumber(value) => {
+                write!(f, "'{}' is not a number", value)
+            }
+            PlaylistFileErrorKind::MissingFile(entry) => {
+                write!(f, "Entry {} has no file", entry)
+            }
+            PlaylistFileErrorKind::WrongEntryCount { expected, found } => write!(
+                f,
+                "The playlist should have {} entries, but {} were found",
+                expected, found
+            ),
+        }?;
+        write!(f, " (on line {})", self.line)
+    }
+}
+
+impl std::error::Error for PlaylistFileError {}
+
+/// Turns the contents of a file without a fixed encoding into text.
+/// Most are UTF-8 nowadays, older ones are often Latin-1.
+fn decode_text(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        // Every byte is a character in Latin-1.
+        Err(_) => bytes.iter().map(|&byte| char::from(byte)).collect(),
+    }
+}
+
+/// Turns a path into a URI, like `file:///music/My%20Song.mp3`.
+/// Relative paths become relative references, like `My%20Song.mp3`.
+fn path_to_uri(path: &Path) -> String {
+    let mut segments = Vec::new();
+
+    for component in path.components() {
+        match component {
+            // A drive, like `C:`.
+            Component::Prefix(prefix) => {
+                segments.push(prefix.as_os_str().to_string_lossy().replace('\\', "/"))
+            }
+            Component::RootDir => {}
+            Component::CurDir => segments.push(".".to_string()),
+            Component::ParentDir => segments.push("..".to_string()),
+            Component::Normal(name) => segments.push(percent_encode(&name.to_string_lossy())),
+        }
+    }
+
+    if path.has_root() {
+        format!("file:///{}", segments.join("/"))
+    } else {
+        segments.join("/")
+    }
+}
+
+/// Turns a URI that was made by [path_to_uri] back into a path.
+/// Other URIs, like `http://`, are kept as they are. They won't be found in the library.
+fn uri_to_path(uri: &str) -> PathBuf {
+    let file_uri = uri
+        .get(..5)
+        .filter(|scheme| scheme.eq_ignore_ascii_case("file:"))
+        .map(|_| &uri[5..]);
+
+    match file_uri {
+        Some(rest) => {
+            // Skip the host, which is normally empty or `localhost`.
+            let path = match rest.strip_prefix("//") {
+                Some(authority_and_path) => match authority_and_path.find('/') {
+                    Some(path_start) => &authority_and_path[path_start..],
+                    None => "",
+                },
+                None => rest,
+            };
+            let path = percent_decode(path);
+
+            // `/C:/music` is `C:/music` on Windows.
+            let is_drive = path.len() > 2 && path.as_bytes()[2] == b':';
+            if cfg!(windows) && is_drive {
+                PathBuf::from(&path[1..])
+            } else {
+                PathBuf::from(path)
+            }
+        }
+        None if uri.contains("://") => PathBuf::from(uri),
+        None => PathBuf::from(percent_decode(uri)),
+    }
+}
+
+/// Escapes everything that is not allowed in a segment of a URI path.
+fn percent_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~!$&'()*+,;=:@".contains(&byte) {
+            encoded.push(char::from(byte));
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+
+    encoded
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+
+    while index < bytes.len() {
+        let escaped = bytes
+            .get(index + 1..index + 3)
+            .filter(|hex| bytes[index] == b'%' && hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Finds songs in the library by their absolute path.
+struct SongPaths {
+    ids_by_path: HashMap<PathBuf, SongId>,
+}
+
+impl SongPaths {
+    fn new(library: &Library) -> Result<Self> {
+        let mut ids_by_path = HashMap::new();
+        // Resolving symbolic links needs a lookup of the file system, so only do it once per root.
+        let mut canonical_roots: HashMap<&Path, Option<PathBuf>> = HashMap::new();
+
+        for (&id, song) in library.songs() {
+            ids_by_path.insert(absolute_path(&song.path)?, id);
+
+            let canonical_root = canonical_roots
+                .entry(&song.root)
+                .or_insert_with(|| fs::canonicalize(&song.root).ok());
+            if let (Some(canonical_root), Ok(relative_path)) =
+                (canonical_root, song.path.strip_prefix(&song.root))
+            {
+                ids_by_path
+                    .entry(normalize_path(&canonical_root.join(relative_path)))
+                    .or_insert(id);
+            }
+        }
+
+        Ok(Self { ids_by_path })
+    }
+
+    fn find(&self, path: &Path) -> Option<SongId> {
+        if let Some(&id) = self.ids_by_path.get(path) {
+            return Some(id);
+        }
+
+        // The playlist can refer to the song through a symbolic link.
+        let canonical_path = fs::canonicalize(path).ok()?;
+        self.ids_by_path.get(&canonical_path).copied()
+    }
+}
+
+/// Makes the path absolute, without resolving symbolic links like [fs::canonicalize] does.
+/// This also works for paths that don't exist.
+fn absolute_path(path: &Path) -> Result<PathBuf> {
+    if path.is_absolute() {
+        Ok(normalize_path(path))
+    } else {
+        Ok(normalize_path(&env::current_dir()?.join(path)))
+    }
+}
+
+/// Removes the `.` and `..` parts of the path.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    normalized.push(component);
+                }
+            }
+            _ => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+/// The path to get from the directory to the target. Both should be absolute.
+/// `None` if there is no relative path, like when they are on different drives.
+fn relative_path(dir: &Path, target: &Path) -> Option<PathBuf> {
+    let mut dir_components = dir.components().peekable();
+    let mut target_components = target.components().peekable();
+
+    match (dir_components.peek(), target_components.peek()) {
+        (Some(dir_root), Some(target_root)) if dir_root == target_root => {}
+        _ => return None,
+    }
+
+    while let (Some(dir_component), Some(target_component)) =
+        (dir_components.peek(), target_components.peek())
+    {
+        if dir_component != target_component {
+            break;
+        }
+        dir_components.next();
+        target_components.next();
+    }
+
+    let mut relative = PathBuf::new();
+    for _ in dir_components {
+        relative.push(Component::ParentDir);
+    }
+    relative.extend(target_components);
+
+    Some(relative)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::library::playlist_file::{
+        decode_text, normalize_path, path_to_uri, relative_path, uri_to_path,
+    };
+    use std::path::{Path, PathBuf};
+    use test_log::test;
+
+    #[test]
+    fn decode_text_falls_back_to_latin_1() {
+        assert_eq!(decode_text("Jóga".as_bytes()), "Jóga");
+        assert_eq!(decode_text(b"J\xf3ga"), "Jóga");
+    }
+
+    #[test]
+    fn paths_and_uris_round_trip() {
+        for (path, uri) in [
+            ("song.mp3", "song.mp3"),
+            ("../Björk/01 Jóga.flac", "../Bj%C3%B6rk/01%20J%C3%B3ga.flac"),
+            ("/music/100% #1.mp3", "file:///music/100%25%20%231.mp3"),
+        ] {
+            assert_eq!(path_to_uri(Path::new(path)), uri);
+            assert_eq!(uri_to_path(uri), PathBuf::from(path));
+        }
+    }
+
+    #[test]
+    fn uri_to_path_skips_the_host() {
+        assert_eq!(
+            uri_to_path("file://localhost/music/song.mp3"),
+            PathBuf::from("/music/song.mp3")
+        );
+        assert_eq!(
+            uri_to_path("FILE:/music/song.mp3"),
+            PathBuf::from("/music/song.mp3")
+        );
+        assert_eq!(
+            uri_to_path("http://example.com/a%20b.mp3"),
+            PathBuf::from("http://example.com/a%20b.mp3")
+        );
+    }
+
+    #[test]
+    fn normalize_path_removes_dots() {
+        assert_eq!(
+            normalize_path(Path::new("/music/./playlists/../album/song.mp3")),
+            PathBuf::from("/music/album/song.mp3")
+        );
+        assert_eq!(
+            normalize_path(Path::new("../song.mp3")),
+            PathBuf::from("../song.mp3")
+        );
+    }
+
+    #[test]
+    fn relative_path_goes_up_to_the_common_directory() {
+        assert_eq!(
+            relative_path(
+                Path::new("/music/playlists"),
+                Path::new("/music/album/song.mp3")
+            ),
+            Some(PathBuf::from("../album/song.mp3"))
+        );
+        assert_eq!(
+            relative_path(Path::new("/music"), Path::new("/music/album/song.mp3")),
+            Some(PathBuf::from("album/song.mp3"))
+        );
+    }
+}
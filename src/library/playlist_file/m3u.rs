@@ -0,0 +1,137 @@
+use crate::library::playlist_file::PlaylistFileEntry;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const HEADER: &str = "#EXTM3U";
+const EXTINF: &str = "#EXTINF:";
+
+/// Reads the entries of an extended M3U playlist: a path on every line,
+/// with an `#EXTINF` line in front that has the duration and title of the song.
+/// Plain M3U files without `#EXTINF` lines are read as well. Other lines are skipped.
+pub fn read(text: &str) -> Vec<PlaylistFileEntry> {
+    let mut entries = Vec::new();
+    // The info of the song on the next line.
+    let mut info: Option<(Option<Duration>, Option<String>)> = None;
+
+    for line in text.trim_start_matches('\u{feff}').lines() {
+        let line = line.trim();
+
+        if let Some(extinf) = line.strip_prefix(EXTINF) {
+            info = Some(parse_extinf(extinf));
+        } else if !line.is_empty() && !line.starts_with('#') {
+            let (duration, title) = info.take().unwrap_or_default();
+            entries.push(PlaylistFileEntry {
+                path: PathBuf::from(line),
+                title,
+                artist: None,
+                duration,
+            });
+        }
+    }
+
+    entries
+}
+
+/// Parses what comes after `#EXTINF:`, like `187,Artist - Title`.
+/// The duration is in seconds, and -1 if it is unknown.
+fn parse_extinf(extinf: &str) -> (Option<Duration>, Option<String>) {
+    let (duration, title) = extinf.split_once(',').unwrap_or((extinf, ""));
+
+    // The duration can be followed by attributes, like `-1 tvg-id="..."`.
+    let duration = duration
+        .split_whitespace()
+        .next()
+        .and_then(|seconds| seconds.parse::<f64>().ok())
+        .filter(|&seconds| seconds >= 0.0 && seconds.is_finite())
+        .map(Duration::from_secs_f64);
+
+    let title = title.trim();
+    let title = (!title.is_empty()).then(|| title.to_string());
+
+    (duration, title)
+}
+
+pub fn write(entries: &[PlaylistFileEntry]) -> String {
+    let mut text = format!("{}\n", HEADER);
+
+    for entry in entries {
+        let seconds = entry
+            .duration
+            .map_or(-1, |duration| duration.as_secs_f64().round() as i64);
+        // A line break in the title would end up as a path.
+        let title = entry.display_title().replace('\n', " ");
+
+        text.push_str(&format!("{}{},{}\n", EXTINF, seconds, title));
+        text.push_str(&format!("{}\n", entry.path.display()));
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod test {
+    use crate::library::playlist_file::m3u::{read, write};
+    use crate::library::playlist_file::PlaylistFileEntry;
+    use std::path::PathBuf;
+    use std::time::Duration;
+    use test_log::test;
+
+    #[test]
+    fn read_extended_m3u() {
+        let text = "\u{feff}#EXTM3U\r\n\
+                    #EXTINF:187,Radiohead - Airbag\r\n\
+                    album/01 Airbag.mp3\r\n\
+                    \r\n\
+                    # A comment\r\n\
+                    /music/loose.ogg\r\n\
+                    #EXTINF:-1 tvg-id=\"x\",\r\n\
+                    stream.mp3\r\n";
+
+        assert_eq!(
+            read(text),
+            vec![
+                PlaylistFileEntry {
+                    path: PathBuf::from("album/01 Airbag.mp3"),
+                    title: Some("Radiohead - Airbag".to_string()),
+                    artist: None,
+                    duration: Some(Duration::from_secs(187)),
+                },
+                PlaylistFileEntry {
+                    path: PathBuf::from("/music/loose.ogg"),
+                    title: None,
+                    artist: None,
+                    duration: None,
+                },
+                PlaylistFileEntry {
+                    path: PathBuf::from("stream.mp3"),
+                    title: None,
+                    artist: None,
+                    duration: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn write_and_read_back() {
+        let entries = vec![
+            PlaylistFileEntry {
+                path: PathBuf::from("../Björk/Jóga.flac"),
+                title: Some("Björk - Jóga".to_string()),
+                artist: None,
+                duration: Some(Duration::from_secs(305)),
+            },
+            PlaylistFileEntry {
+                path: PathBuf::from("/music/untitled.mp3"),
+                title: None,
+                artist: None,
+                duration: None,
+            },
+        ];
+
+        let text = write(&entries);
+
+        assert!(text.starts_with("#EXTM3U\n#EXTINF:305,Björk - Jóga\n../Björk/Jóga.flac\n"));
+        assert_eq!(read(&text), entries);
+    }
+}
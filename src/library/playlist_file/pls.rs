@@ -0,0 +1,276 @@
+use crate::library::playlist_file::{
+    uri_to_path, PlaylistFileEntry, PlaylistFileError, PlaylistFileErrorKind, PlaylistFormat,
+};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const HEADER: &str = "[playlist]";
+
+/// The parts of an entry, which are spread over several lines.
+#[derive(Default)]
+struct PlsEntry {
+    file: Option<String>,
+    title: Option<String>,
+    length: Option<Duration>,
+    /// Where the entry was first seen, to point at it when something is wrong.
+    line: usize,
+}
+
+/// Reads the entries of a PLS playlist, like:
+///
+/// ```text
+/// [playlist]
+/// File1=album/song.mp3
+/// Title1=Artist - Title
+/// Length1=187
+/// NumberOfEntries=1
+/// Version=2
+/// ```
+///
+/// The entries are put in the order of their numbers. Unknown keys are skipped.
+pub fn read(text: &str) -> Result<Vec<PlaylistFileEntry>, PlaylistFileError> {
+    let mut entries: BTreeMap<u32, PlsEntry> = BTreeMap::new();
+    let mut number_of_entries = None;
+    let mut found_header = false;
+
+    for (index, line) in text.trim_start_matches('\u{feff}').lines().enumerate() {
+        let line_number = index + 1;
+        let error = |kind| PlaylistFileError {
+            kind,
+            line: line_number,
+        };
+
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+
+        if !found_header {
+            if !line.eq_ignore_ascii_case(HEADER) {
+                return Err(error(PlaylistFileErrorKind::WrongFormat(
+                    PlaylistFormat::Pls,
+                )));
+            }
+            found_header = true;
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| error(PlaylistFileErrorKind::InvalidLine(line.to_string())))?;
+        let (key, value) = (key.trim(), value.trim());
+        let lowercase_key = key.to_ascii_lowercase();
+
+        let parse_number = |number: &str| {
+            number
+                .parse::<u32>()
+                .map_err(|_| error(PlaylistFileErrorKind::InvalidNumber(number.to_string())))
+        };
+
+        if lowercase_key == "numberofentries" {
+            number_of_entries = Some((parse_number(value)?, line_number));
+            continue;
+        }
+
+        let field = ["file", "title", "length"]
+            .into_iter()
+            .find(|field| lowercase_key.starts_with(field));
+        let (field, number) = match field {
+            // Only ASCII is lowercased, so the number starts at the same place in the original key.
+            Some(field) if key.len() > field.len() => (field, parse_number(&key[field.len()..])?),
+            // Like `Version`.
+            _ => continue,
+        };
+
+        let entry = entries.entry(number).or_insert_with(|| PlsEntry {
+            line: line_number,
+            ..Default::default()
+        });
+        match field {
+            "file" => entry.file = Some(value.to_string()),
+            "title" => entry.title = Some(value.to_string()).filter(|title| !title.is_empty()),
+            _ => {
+                // -1 is used for an unknown length, like for streams.
+                let seconds: i64 = value
+                    .parse()
+                    .map_err(|_| error(PlaylistFileErrorKind::InvalidNumber(value.to_string())))?;
+                entry.length = u64::try_from(seconds).ok().map(Duration::from_secs);
+            }
+        }
+    }
+
+    if !found_header {
+        return Err(PlaylistFileError {
+            kind: PlaylistFileErrorKind::WrongFormat(PlaylistFormat::Pls),
+            line: 1,
+        });
+    }
+
+    if let Some((expected, line)) = number_of_entries {
+        if expected as usize != entries.len() {
+            return Err(PlaylistFileError {
+                kind: PlaylistFileErrorKind::WrongEntryCount {
+                    expected: expected as usize,
+                    found: entries.len(),
+                },
+                line,
+            });
+        }
+    }
+
+    entries
+        .into_iter()
+        .map(|(number, entry)| {
+            let file = entry.file.ok_or(PlaylistFileError {
+                kind: PlaylistFileErrorKind::MissingFile(number),
+                line: entry.line,
+            })?;
+
+            Ok(PlaylistFileEntry {
+                path: uri_or_path(&file),
+                title: entry.title,
+                artist: None,
+                duration: entry.length,
+            })
+        })
+        .collect()
+}
+
+/// Files can be given as a path or as a URI.
+fn uri_or_path(file: &str) -> PathBuf {
+    let is_file_uri = matches!(file.get(..5), Some(scheme) if scheme.eq_ignore_ascii_case("file:"));
+
+    if is_file_uri || file.contains("://") {
+        uri_to_path(file)
+    } else {
+        PathBuf::from(file)
+    }
+}
+
+pub fn write(entries: &[PlaylistFileEntry]) -> String {
+    let mut text = format!("{}\n", HEADER);
+
+    for (index, entry) in entries.iter().enumerate() {
+        let number = index + 1;
+        let seconds = entry
+            .duration
+            .map_or(-1, |duration| duration.as_secs_f64().round() as i64);
+        // A line break would end the value.
+        let title = entry.display_title().replace('\n', " ");
+
+        text.push_str(&format!("File{}={}\n", number, entry.path.display()));
+        if !title.is_empty() {
+            text.push_str(&format!("Title{}={}\n", number, title));
+        }
+        text.push_str(&format!("Length{}={}\n", number, seconds));
+    }
+
+    text.push_str(&format!("NumberOfEntries={}\nVersion=2\n", entries.len()));
+    text
+}
+
+#[cfg(test)]
+mod test {
+    use crate::library::playlist_file::pls::{read, write};
+    use crate::library::playlist_file::{PlaylistFileEntry, PlaylistFileErrorKind, PlaylistFormat};
+    use std::path::PathBuf;
+    use std::time::Duration;
+    use test_log::test;
+
+    #[test]
+    fn read_pls() {
+        let text = "[Playlist]\r\n\
+                    ; A comment\r\n\
+                    File2=file:///music/Two%20Songs.mp3\r\n\
+                    File1=album/one.ogg\r\n\
+                    Title1=Artist - One\r\n\
+                    Length1=187\r\n\
+                    Length2=-1\r\n\
+                    NumberOfEntries=2\r\n\
+                    Version=2\r\n";
+
+        assert_eq!(
+            read(text).unwrap(),
+            vec![
+                PlaylistFileEntry {
+                    path: PathBuf::from("album/one.ogg"),
+                    title: Some("Artist - One".to_string()),
+                    artist: None,
+                    duration: Some(Duration::from_secs(187)),
+                },
+                PlaylistFileEntry {
+                    path: PathBuf::from("/music/Two Songs.mp3"),
+                    title: None,
+                    artist: None,
+                    duration: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn write_and_read_back() {
+        let entries = vec![
+            PlaylistFileEntry {
+                path: PathBuf::from("../Björk/Jóga.flac"),
+                title: Some("Jóga".to_string()),
+                artist: Some("Björk".to_string()),
+                duration: Some(Duration::from_secs(305)),
+            },
+            PlaylistFileEntry {
+                path: PathBuf::from("/music/untitled.mp3"),
+                title: None,
+                artist: None,
+                duration: None,
+            },
+        ];
+
+        let text = write(&entries);
+        let read_back = read(&text).unwrap();
+
+        assert!(text.starts_with("[playlist]\nFile1=../Björk/Jóga.flac\nTitle1=Björk - Jóga\n"));
+        assert_eq!(read_back[0].title.as_deref(), Some("Björk - Jóga"));
+        assert_eq!(read_back[0].duration, entries[0].duration);
+        assert_eq!(read_back[1], entries[1]);
+    }
+
+    #[test]
+    fn read_malformed_pls() {
+        let error = |text| read(text).unwrap_err();
+
+        assert_eq!(
+            error("#EXTM3U\nsong.mp3\n").kind,
+            PlaylistFileErrorKind::WrongFormat(PlaylistFormat::Pls)
+        );
+        assert_eq!(error("").line, 1);
+
+        let invalid_line = error("[playlist]\nFile1=a.mp3\nsong.mp3\n");
+        assert_eq!(
+            invalid_line.kind,
+            PlaylistFileErrorKind::InvalidLine("song.mp3".to_string())
+        );
+        assert_eq!(invalid_line.line, 3);
+
+        assert_eq!(
+            error("[playlist]\nFile1=a.mp3\nLength1=long\n").kind,
+            PlaylistFileErrorKind::InvalidNumber("long".to_string())
+        );
+        assert_eq!(
+            error("[playlist]\nFileX=a.mp3\n").kind,
+            PlaylistFileErrorKind::InvalidNumber("X".to_string())
+        );
+
+        let missing_file = error("[playlist]\nFile1=a.mp3\nTitle2=B\n");
+        assert_eq!(missing_file.kind, PlaylistFileErrorKind::MissingFile(2));
+        assert_eq!(missing_file.line, 3);
+
+        assert_eq!(
+            error("[playlist]\nFile1=a.mp3\nNumberOfEntries=2\n").kind,
+            PlaylistFileErrorKind::WrongEntryCount {
+                expected: 2,
+                found: 1
+            }
+        );
+    }
+}
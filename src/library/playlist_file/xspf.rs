@@ -0,0 +1,594 @@
+use crate::library::playlist_file::{
+    path_to_uri, uri_to_path, PlaylistFileEntry, PlaylistFileError, PlaylistFileErrorKind,
+    PlaylistFormat,
+};
+use std::cell::Cell;
+use std::time::Duration;
+
+const NAMESPACE: &str = "http://xspf.org/ns/0/";
+/// How deep elements can be nested. Playlists need only a few levels,
+/// this keeps a malicious file from overflowing the stack.
+const MAX_DEPTH: usize = 100;
+
+/// Reads the tracks of an XSPF playlist, like:
+///
+/// ```text
+/// <playlist version="1" xmlns="http://xspf.org/ns/0/">
+///   <trackList>
+///     <track>
+///       <location>file:///music/song.mp3</location>
+///       <title>Title</title>
+///       <creator>Artist</creator>
+///       <duration>187000</duration>
+///     </track>
+///   </trackList>
+/// </playlist>
+/// ```
+///
+/// Tracks without a location get an empty path, so they show up as missing from the library.
+pub fn read(text: &str) -> Result<Vec<PlaylistFileEntry>, PlaylistFileError> {
+    let playlist = XmlParser::new(text).parse_document()?;
+
+    if playlist.name != "playlist" {
+        return Err(PlaylistFileError {
+            kind: PlaylistFileErrorKind::WrongFormat(PlaylistFormat::Xspf),
+            line: playlist.line,
+        });
+    }
+
+    let track_list = playlist.child("trackList").ok_or(PlaylistFileError {
+        kind: PlaylistFileErrorKind::MissingElement("trackList"),
+        line: playlist.line,
+    })?;
+
+    track_list
+        .children("track")
+        .map(|track| {
+            let text_of = |name| track.child(name).map(XmlElement::text);
+
+            let duration = match text_of("duration") {
+                Some(milliseconds) => Some(
+                    milliseconds
+                        .parse::<u64>()
+                        .map(Duration::from_millis)
+                        .map_err(|_| PlaylistFileError {
+                            kind: PlaylistFileErrorKind::InvalidNumber(milliseconds.clone()),
+                            line: track.child("duration").map_or(track.line, |d| d.line),
+                        })?,
+                ),
+                None => None,
+            };
+
+            Ok(PlaylistFileEntry {
+                path: text_of("location")
+                    .map(|location| uri_to_path(&location))
+                    .unwrap_or_default(),
+                title: text_of("title").filter(|title| !title.is_empty()),
+                artist: text_of("creator").filter(|artist| !artist.is_empty()),
+                duration,
+            })
+        })
+        .collect()
+}
+
+pub fn write(entries: &[PlaylistFileEntry]) -> String {
+    let mut text = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    text.push_str(&format!(
+        "<playlist version=\"1\" xmlns=\"{}\">\n  <trackList>\n",
+        NAMESPACE
+    ));
+
+    for entry in entries {
+        text.push_str("    <track>\n");
+        let mut push_element = |name: &str, value: &str| {
+            text.push_str(&format!(
+                "      <{}>{}</{}>\n",
+                name,
+                escape_xml(value),
+                name
+            ));
+        };
+
+        push_element("location", &path_to_uri(&entry.path));
+        if let Some(title) = &entry.title {
+            push_element("title", title);
+        }
+        if let Some(artist) = &entry.artist {
+            push_element("creator", artist);
+        }
+        if let Some(duration) = entry.duration {
+            push_element("duration", &duration.as_millis().to_string());
+        }
+
+        text.push_str("    </track>\n");
+    }
+
+    text.push_str("  </trackList>\n</playlist>\n");
+    text
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Just enough of an XML element for reading playlists. Attributes are not kept.
+#[derive(Debug)]
+struct XmlElement {
+    /// Without the namespace prefix.
+    name: String,
+    children: Vec<XmlElement>,
+    /// All text directly in this element.
+    text: String,
+    line: usize,
+}
+
+impl XmlElement {
+    fn child<'a>(&'a self, name: &'a str) -> Option<&'a XmlElement> {
+        self.children(name).next()
+    }
+
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a XmlElement> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    fn text(&self) -> String {
+        self.text.trim().to_string()
+    }
+}
+
+/// Reads a tree of [XmlElement]s. Doesn't support everything XML can do, like custom entities,
+/// but does make sure the file is well-formed.
+struct XmlParser<'a> {
+    text: &'a str,
+    /// Byte position of the next character to read.
+    position: usize,
+    /// A position before the current one and the line it is on. Lines are counted from there,
+    /// so every element doesn't count the lines from the start of the text again.
+    counted_lines: Cell<(usize, usize)>,
+}
+
+impl<'a> XmlParser<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            text: text.trim_start_matches('\u{feff}'),
+            position: 0,
+            counted_lines: Cell::new((0, 1)),
+        }
+    }
+
+    fn parse_document(&mut self) -> Result<XmlElement, PlaylistFileError> {
+        self.skip_misc()?;
+        if self.rest().is_empty() {
+            return Err(self.error(PlaylistFileErrorKind::WrongFormat(PlaylistFormat::Xspf)));
+        }
+
+        let root = self.parse_element(1)?;
+
+        self.skip_misc()?;
+        if !self.rest().is_empty() {
+            return Err(self.invalid("content after the root element"));
+        }
+
+        Ok(root)
+    }
+
+    /// Skips whitespace, comments, the XML declaration and the doctype.
+    fn skip_misc(&mut self) -> Result<(), PlaylistFileError> {
+        loop {
+            self.skip_whitespace();
+
+            if self.rest().starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if self.rest().starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if self.rest().starts_with("<!DOCTYPE") {
+                self.skip_past(">")?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    /// The root element has depth 1.
+    fn parse_element(&mut self, depth: usize) -> Result<XmlElement, PlaylistFileError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error(PlaylistFileErrorKind::TooDeeplyNested));
+        }
+
+        let line = self.line();
+        self.expect("<")?;
+        let qualified_name = self.parse_name()?;
+
+        let mut element = XmlElement {
+            name: local_name(&qualified_name).to_string(),
+            children: Vec::new(),
+            text: String::new(),
+            line,
+        };
+
+        // The attributes are checked, but not kept.
+        loop {
+            self.skip_whitespace();
+
+            if self.rest().starts_with("/>") {
+                self.position += 2;
+                return Ok(element);
+            } else if self.rest().starts_with('>') {
+                self.position += 1;
+                break;
+            }
+
+            self.parse_name()?;
+            self.skip_whitespace();
+            self.expect("=")?;
+            self.skip_whitespace();
+
+            let quote = match self.rest().chars().next() {
+                Some(quote @ ('"' | '\'')) => quote,
+                _ => return Err(self.invalid("attribute value without quotes")),
+            };
+            self.position += 1;
+            let value_length = self
+                .rest()
+                .find(quote)
+                .ok_or_else(|| self.invalid("attribute value is not closed"))?;
+            let value = &self.rest()[..value_length];
+            decode_entities(value).map_err(|problem| self.invalid(&problem))?;
+            self.position += value_length + 1;
+        }
+
+        loop {
+            if self.rest().starts_with("</") {
+                self.position += 2;
+                let end_name = self.parse_name()?;
+                if end_name != qualified_name {
+                    return Err(self.invalid(&format!(
+                        "expected </{}>, but found </{}>",
+                        qualified_name, end_name
+                    )));
+                }
+                self.skip_whitespace();
+                self.expect(">")?;
+                return Ok(element);
+            } else if self.rest().starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if self.rest().starts_with("<![CDATA[") {
+                self.position += "<![CDATA[".len();
+                let start = self.position;
+                self.skip_past("]]>")?;
+                element
+                    .text
+                    .push_str(&self.text[start..self.position - "]]>".len()]);
+            } else if self.rest().starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if self.rest().starts_with('<') {
+                element.children.push(self.parse_element(depth + 1)?);
+            } else if self.rest().is_empty() {
+                return Err(self.invalid(&format!("<{}> is not closed", qualified_name)));
+            } else {
+                let text_length = self.rest().find('<').unwrap_or(self.rest().len());
+                let text = decode_entities(&self.rest()[..text_length])
+                    .map_err(|problem| self.invalid(&problem))?;
+                element.text.push_str(&text);
+                self.position += text_length;
+            }
+        }
+    }
+
+    fn parse_name(&mut self) -> Result<String, PlaylistFileError> {
+        let length = self
+            .rest()
+            .find(|c: char| c.is_whitespace() || "/>=<\"'".contains(c))
+            .unwrap_or(self.rest().len());
+
+        if length == 0 {
+            return Err(self.invalid("expected a name"));
+        }
+
+        let name = self.rest()[..length].to_string();
+        self.position += length;
+        Ok(name)
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.position..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn skip_past(&mut self, end: &str) -> Result<(), PlaylistFileError> {
+        match self.rest().find(end) {
+            Some(index) => {
+                self.position += index + end.len();
+                Ok(())
+            }
+            None => Err(self.invalid(&format!("expected '{}', but the file ended", end))),
+        }
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), PlaylistFileError> {
+        if self.rest().starts_with(expected) {
+            self.position += expected.len();
+            Ok(())
+        } else {
+            Err(self.invalid(&format!("expected '{}'", expected)))
+        }
+    }
+
+    fn line(&self) -> usize {
+        let (counted_position, counted_line) = self.counted_lines.get();
+        let line = counted_line
+            + self.text[counted_position..self.position]
+                .matches('\n')
+                .count();
+        self.counted_lines.set((self.position, line));
+        line
+    }
+
+    fn error(&self, kind: PlaylistFileErrorKind) -> PlaylistFileError {
+        PlaylistFileError {
+            kind,
+            line: self.line(),
+        }
+    }
+
+    fn invalid(&self, problem: &str) -> PlaylistFileError {
+        self.error(PlaylistFileErrorKind::InvalidXml(problem.to_string()))
+    }
+}
+
+/// `xspf:track` is `track`.
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+/// Replaces `&amp;` and the like by the characters they stand for.
+fn decode_entities(text: &str) -> Result<String, String> {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = rest
+            .find(';')
+            .ok_or_else(|| "'&' without a ';' after it".to_string())?;
+        let entity = &rest[1..end];
+
+        let character = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => match entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+            {
+                Some(hex) => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+                None => entity
+                    .strip_prefix('#')
+                    .and_then(|decimal| decimal.parse().ok())
+                    .and_then(char::from_u32),
+            },
+        };
+
+        decoded.push(character.ok_or_else(|| format!("unknown entity '&{};'", entity))?);
+        rest = &rest[end + 1..];
+    }
+
+    decoded.push_str(rest);
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::library::playlist_file::xspf::{read, write};
+    use crate::library::playlist_file::{PlaylistFileEntry, PlaylistFileErrorKind, PlaylistFormat};
+    use std::path::PathBuf;
+    use std::time::Duration;
+    use test_log::test;
+
+    #[test]
+    fn read_xspf() {
+        let text = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!-- Made by another player -->
+<playlist version="1" xmlns="http://xspf.org/ns/0/">
+  <title>Mix</title>
+  <trackList>
+    <track>
+      <location>file:///music/Rock%20%26%20Roll.mp3</location>
+      <title>Rock &amp; Roll</title>
+      <creator><![CDATA[Led <Zeppelin>]]></creator>
+      <duration>220500</duration>
+      <extension application="x"><anything/></extension>
+    </track>
+    <track><location>relative/song.ogg</location></track>
+  </trackList>
+</playlist>
+"#;
+
+        assert_eq!(
+            read(text).unwrap(),
+            vec![
+                PlaylistFileEntry {
+                    path: PathBuf::from("/music/Rock & Roll.mp3"),
+                    title: Some("Rock & Roll".to_string()),
+                    artist: Some("Led <Zeppelin>".to_string()),
+                    duration: Some(Duration::from_millis(220500)),
+                },
+                PlaylistFileEntry {
+                    path: PathBuf::from("relative/song.ogg"),
+                    title: None,
+                    artist: None,
+                    duration: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn write_and_read_back() {
+        let entries = vec![
+            PlaylistFileEntry {
+                path: PathBuf::from("../Björk/Jóga & more.flac"),
+                title: Some("Jóga <live>".to_string()),
+                artist: Some("Björk".to_string()),
+                duration: Some(Duration::from_millis(305_250)),
+            },
+            PlaylistFileEntry {
+                path: PathBuf::from("/music/untitled.mp3"),
+                title: None,
+                artist: None,
+                duration: None,
+            },
+        ];
+
+        let text = write(&entries);
+
+        assert!(text.contains("<location>../Bj%C3%B6rk/J%C3%B3ga%20&amp;%20more.flac</location>"));
+        assert!(text.contains("<title>Jóga &lt;live&gt;</title>"));
+        assert_eq!(read(&text).unwrap(), entries);
+    }
+
+    #[test]
+    fn read_malformed_xspf() {
+        let error = |text| read(text).unwrap_err();
+
+        assert_eq!(
+            error("").kind,
+            PlaylistFileErrorKind::WrongFormat(PlaylistFormat::Xspf)
+        );
+        assert_eq!(
+            error("<html></html>").kind,
+            PlaylistFileErrorKind::WrongFormat(PlaylistFormat::Xspf)
+        );
+        assert_eq!(
+            error("<playlist><title>Mix</title></playlist>").kind,
+            PlaylistFileErrorKind::MissingElement("trackList")
+        );
+
+        let not_closed = error("<playlist>\n<trackList>\n<track>\n</trackList>\n</playlist>");
+        assert_eq!(
+            not_closed.kind,
+            PlaylistFileErrorKind::InvalidXml(
+                "expected </track>, but found </trackList>".to_string()
+            )
+        );
+        assert_eq!(not_closed.line, 4);
+
+        assert_eq!(
+            error("<playlist><trackList>").kind,
+            PlaylistFileErrorKind::InvalidXml("<trackList> is not closed".to_string())
+        );
+        assert_eq!(
+            error("<playlist><trackList/></playlist><playlist/>").kind,
+            PlaylistFileErrorKind::InvalidXml("content after the root element".to_string())
+        );
+        assert_eq!(
+            error("<playlist><trackList>&nbsp;</trackList></playlist>").kind,
+            PlaylistFileErrorKind::InvalidXml("unknown entity '&nbsp;'".to_string())
+        );
+        assert_eq!(
+            error(
+                "<playlist><trackList><track><duration>3:07</duration></track>\
+                 </trackList></playlist>"
+            )
+            .kind,
+            PlaylistFileErrorKind::InvalidNumber("3:07".to_string())
+        );
+
+        let nested = format!(
+            "<playlist><trackList>\n{}",
+            "<track>".repeat(super::MAX_DEPTH)
+        );
+        let too_deep = error(&nested);
+        assert_eq!(too_deep.kind, PlaylistFileErrorKind::TooDeeplyNested);
+        assert_eq!(too_deep.line, 2);
+    }
+
+    #[test]
+    fn read_entities_and_cdata() {
+        let text = "<playlist><trackList><track>\
+             <title>&lt;Live&gt; &quot;Rock&quot; &apos;n&apos; &#82;oll &#x26; more</title>\
+             <creator>Led <![CDATA[<Zeppelin> & &amp;]]> &amp; <![CDATA[]]>friends</creator>\
+             <location attribute=\"&amp;\">song.mp3</location>\
+             </track></trackList></playlist>";
+
+        let entries = read(text).unwrap();
+        assert_eq!(
+            entries[0].title.as_deref(),
+            Some("<Live> \"Rock\" 'n' Roll & more")
+        );
+        assert_eq!(
+            entries[0].artist.as_deref(),
+            Some("Led <Zeppelin> & &amp; & friends")
+        );
+
+        let error = |text| read(text).unwrap_err().kind;
+        assert_eq!(
+            error("<playlist><trackList>&#xD800;</trackList></playlist>"),
+            PlaylistFileErrorKind::InvalidXml("unknown entity '&#xD800;'".to_string())
+        );
+        assert_eq!(
+            error("<playlist><trackList>R&B</trackList></playlist>"),
+            PlaylistFileErrorKind::InvalidXml("'&' without a ';' after it".to_string())
+        );
+        assert_eq!(
+            error("<playlist><trackList><![CDATA[ not closed </trackList></playlist>"),
+            PlaylistFileErrorKind::InvalidXml("expected ']]>', but the file ended".to_string())
+        );
+    }
+
+    #[test]
+    fn read_nesting_up_to_the_depth_limit() {
+        // The playlist and track list are two of the levels.
+        let nested = |depth: usize| {
+            format!(
+                "<playlist><trackList>{}{}</trackList></playlist>",
+                "<track>".repeat(depth - 2),
+                "</track>".repeat(depth - 2)
+            )
+        };
+
+        assert!(read(&nested(super::MAX_DEPTH)).is_ok());
+        assert_eq!(
+            read(&nested(super::MAX_DEPTH + 1)).unwrap_err().kind,
+            PlaylistFileErrorKind::TooDeeplyNested
+        );
+    }
+
+    #[test]
+    fn read_reports_the_line_of_errors_after_many_lines() {
+        let tracks = "<track><location>song.mp3</location></track>\n".repeat(10_000);
+        let text = format!(
+            "<playlist>\n<trackList>\n{}<track>\n<![CDATA[\n\n]]><duration>soon</duration>\n\
+             </track>\n</trackList>\n</playlist>",
+            tracks
+        );
+
+        let error = read(&text).unwrap_err();
+        assert_eq!(
+            error.kind,
+            PlaylistFileErrorKind::InvalidNumber("soon".to_string())
+        );
+        assert_eq!(error.line, 10_000 + 6);
+    }
+}
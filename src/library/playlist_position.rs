@@ -0,0 +1,363 @@
+use crate::library::shuffle::ShuffleOrder;
+use crate::library::{Library, ListEntryId, Playlist, RepeatMode, SongId};
+
+/// Where playback is in the playing [Playlist]: at the entry that is playing,
+/// or at the entry the playlist continues after once the songs of the play queue are played.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum PlaylistPosition {
+    #[default]
+    Stopped,
+    Entry(ListEntryId, SongId),
+    /// The current entry was removed from the playlist, while its song may still be playing.
+    /// The entry that took its place at the index is the next one.
+    Removed {
+        entry_id: ListEntryId,
+        song_id: SongId,
+        index: usize,
+    },
+}
+
+impl PlaylistPosition {
+    pub fn is_stopped(&self) -> bool {
+        *self == PlaylistPosition::Stopped
+    }
+
+    /// The current entry, if it is still in the playlist.
+    pub fn entry(&self) -> Option<(ListEntryId, SongId)> {
+        match *self {
+            PlaylistPosition::Entry(entry_id, song_id) => Some((entry_id, song_id)),
+            _ => None,
+        }
+    }
+
+    fn entry_id(&self) -> Option<ListEntryId> {
+        match *self {
+            PlaylistPosition::Stopped => None,
+            PlaylistPosition::Entry(entry_id, _) | PlaylistPosition::Removed { entry_id, .. } => {
+                Some(entry_id)
+            }
+        }
+    }
+
+    /// Edits the playlist this is a position in.
+    /// When the edit removes the current entry, playback continues where the entry was.
+    /// When it brings the entry back, like undoing its removal, that is the current entry again.
+    pub fn edit_playlist<R>(
+        &mut self,
+        playlist: &mut Playlist,
+        edit: impl FnOnce(&mut Playlist) -> R,
+    ) -> R {
+        let index = self
+            .entry_id()
+            .and_then(|entry_id| playlist.get_song_index(entry_id));
+        let result = edit(playlist);
+
+        match *self {
+            PlaylistPosition::Entry(entry_id, song_id) => {
+                if let (Some(index), None) = (index, playlist.get_song_index(entry_id)) {
+                    *self = PlaylistPosition::Removed {
+                        entry_id,
+                        song_id,
+                        index,
+                    };
+                }
+            }
+            PlaylistPosition::Removed {
+                entry_id, song_id, ..
+            } => {
+                if playlist.get_song_index(entry_id).is_some() {
+                    *self = PlaylistPosition::Entry(entry_id, song_id);
+                }
+            }
+            PlaylistPosition::Stopped => {}
+        }
+
+        result
+    }
+
+    /// Where songs are inserted to be played next.
+    pub fn next_index(&self, playlist: &Playlist) -> usize {
+        match *self {
+            PlaylistPosition::Stopped => 0,
+            PlaylistPosition::Entry(entry_id, _) => playlist
+                .get_song_index(entry_id)
+                .map_or(0, |index| index + 1),
+            PlaylistPosition::Removed { index, .. } => index.min(playlist.length()),
+        }
+    }
+
+    /// The entry to play after this position. Without a position, the playlist starts.
+    /// Goes through the shuffled order instead of the playlist order when it is given.
+    /// Entries whose song is no longer in the library are skipped.
+    pub fn next_entry(
+        &self,
+        playlist: &Playlist,
+        library: &Library,
+        mut shuffle: Option<&mut ShuffleOrder>,
+        repeat: RepeatMode,
+    ) -> Option<(ListEntryId, SongId, usize)> {
+        self.skip_missing_songs(playlist, library, repeat, |position, repeat| {
+            position.next_entry_of_playlist(playlist, shuffle.as_deref_mut(), repeat)
+        })
+    }
+
+    /// The entry to play before this position. Without a position, the last entry is played.
+    /// Entries whose song is no longer in the library are skipped.
+    pub fn previous_entry(
+        &self,
+        playlist: &Playlist,
+        library: &Library,
+        mut shuffle: Option<&mut ShuffleOrder>,
+        repeat: RepeatMode,
+    ) -> Option<(ListEntryId, SongId, usize)> {
+        self.skip_missing_songs(playlist, library, repeat, |position, repeat| {
+            position.previous_entry_of_playlist(playlist, shuffle.as_deref_mut(), repeat)
+        })
+    }
+
+    /// Steps through the playlist until an entry with a song in the library comes up.
+    /// Every entry is tried at most once, so a playlist of missing songs has no entry to play.
+    fn skip_missing_songs(
+        &self,
+        playlist: &Playlist,
+        library: &Library,
+        mut repeat: RepeatMode,
+        mut step: impl FnMut(&PlaylistPosition, RepeatMode) -> Option<(ListEntryId, SongId, usize)>,
+    ) -> Option<(ListEntryId, SongId, usize)> {
+        let mut position = *self;
+        for _ in 0..=playlist.length() {
+            let (entry_id, song_id, index) = step(&position, repeat)?;
+            if library.get_song(&song_id).is_some() {
+                return Some((entry_id, song_id, index));
+            }
+
+            // A missing song is passed over, also when repeating one song.
+            position = PlaylistPosition::Entry(entry_id, song_id);
+            repeat = repeat.when_skipping();
+        }
+
+        None
+    }
+
+    fn next_entry_of_playlist(
+        &self,
+        playlist: &Playlist,
+        shuffle: Option<&mut ShuffleOrder>,
+        repeat: RepeatMode,
+    ) -> Option<(ListEntryId, SongId, usize)> {
+        if let Some(shuffle) = shuffle {
+            return shuffle.get_next_entry(playlist, self.entry_id(), repeat);
+        }
+
+        match *self {
+            PlaylistPosition::Stopped => playlist.get_first_entry(),
+            PlaylistPosition::Entry(entry_id, _) => playlist.get_next_entry(entry_id, repeat),
+            PlaylistPosition::Removed { index, .. } => match playlist.get_entry_at_index(index) {
+                Some(entry) => Some(entry),
+                None if repeat != RepeatMode::Off => playlist.get_first_entry(),
+                None => None,
+            },
+        }
+    }
+
+    fn previous_entry_of_playlist(
+        &self,
+        playlist: &Playlist,
+        shuffle: Option<&mut ShuffleOrder>,
+        repeat: RepeatMode,
+    ) -> Option<(ListEntryId, SongId, usize)> {
+        if let Some(shuffle) = shuffle {
+            return shuffle.get_previous_entry(playlist, self.entry_id(), repeat);
+        }
+
+        match *self {
+            PlaylistPosition::Stopped => playlist.get_last_entry(),
+            PlaylistPosition::Entry(entry_id, _) => playlist.get_previous_entry(entry_id, repeat),
+            PlaylistPosition::Removed { index, .. } => {
+                match index.min(playlist.length()).checked_sub(1) {
+                    Some(previous) => playlist.get_entry_at_index(previous),
+                    None if repeat != RepeatMode::Off => playlist.get_last_entry(),
+                    None => None,
+                }
+            }
+        }
+    }
+
+    /// Makes the position refer to another song, like [Playlist::replace_song_ids].
+    pub fn replace_song_ids(&mut self, new_id: impl Fn(SongId) -> SongId) {
+        match self {
+            PlaylistPosition::Stopped => {}
+            PlaylistPosition::Entry(_, song_id) | PlaylistPosition::Removed { song_id, .. } => {
+                *song_id = new_id(*song_id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::library::play_queue::PlayQueue;
+    use crate::library::playlist_position::PlaylistPosition;
+    use crate::library::shuffle::ShuffleOrder;
+    use crate::library::{Library, Playlist, RepeatMode, Song, SongId};
+    use std::path::PathBuf;
+    use test_log::test;
+
+    /// A playlist of songs that are in the library, in the order of the returned ids.
+    fn library_and_playlist(count: usize) -> (Library, Playlist, Vec<SongId>) {
+        let mut library = Library::new();
+        let mut playlist = Playlist::new();
+        let mut song_ids = Vec::new();
+        for number in 0..count {
+            let song_id = library.add_song(Song {
+                title: format!("Song {number}"),
+                path: PathBuf::from(format!("song_{number}.mp3")),
+                ..Default::default()
+            });
+            playlist.add_song(song_id);
+            song_ids.push(song_id);
+        }
+        (library, playlist, song_ids)
+    }
+
+    fn position_at(playlist: &Playlist, index: usize) -> PlaylistPosition {
+        let &(entry_id, song_id) = playlist.get_at_index(index).unwrap();
+        PlaylistPosition::Entry(entry_id, song_id)
+    }
+
+    fn next_song(
+        position: &PlaylistPosition,
+        playlist: &Playlist,
+        library: &Library,
+        repeat: RepeatMode,
+    ) -> Option<SongId> {
+        position
+            .next_entry(playlist, library, None, repeat)
+            .map(|(_, song_id, _)| song_id)
+    }
+
+    #[test]
+    fn playlist_position_continues_where_an_undone_entry_was() {
+        let (library, mut playlist, songs) = library_and_playlist(4);
+        let mut position = position_at(&playlist, 3);
+
+        // Undoing the add of the current entry removes it.
+        position.edit_playlist(&mut playlist, |playlist| playlist.undo());
+        assert_eq!(position.entry(), None);
+        assert!(!position.is_stopped());
+
+        assert_eq!(
+            next_song(&position, &playlist, &library, RepeatMode::Off),
+            None
+        );
+        assert_eq!(
+            next_song(&position, &playlist, &library, RepeatMode::All),
+            Some(songs[0])
+        );
+        let previous = position.previous_entry(&playlist, &library, None, RepeatMode::Off);
+        assert_eq!(previous.map(|(_, song_id, _)| song_id), Some(songs[2]));
+
+        // Redoing it brings the entry back.
+        position.edit_playlist(&mut playlist, |playlist| playlist.redo());
+        assert_eq!(position, position_at(&playlist, 3));
+    }
+
+    #[test]
+    fn playlist_position_continues_with_the_entry_in_place_of_the_removed_one() {
+        let (library, mut playlist, songs) = library_and_playlist(4);
+        let mut position = position_at(&playlist, 1);
+        let (entry_id, _) = position.entry().unwrap();
+
+        position.edit_playlist(&mut playlist, |playlist| playlist.remove_song(entry_id));
+
+        assert_eq!(
+            next_song(&position, &playlist, &library, RepeatMode::Off),
+            Some(songs[2])
+        );
+        assert_eq!(position.next_index(&playlist), 1);
+    }
+
+    #[test]
+    fn playlist_position_continues_after_a_queued_song_where_the_removed_entry_was() {
+        let (library, mut playlist, songs) = library_and_playlist(3);
+        let mut position = position_at(&playlist, 1);
+        let (entry_id, _) = position.entry().unwrap();
+
+        let mut play_queue = PlayQueue::new();
+        play_queue.add_songs(vec![songs[0]]);
+        assert_eq!(play_queue.pop_next(), Some(songs[0]));
+
+        // The entry is removed while the queued song plays, and then next is pressed.
+        position.edit_playlist(&mut playlist, |playlist| playlist.remove_song(entry_id));
+        assert_eq!(play_queue.pop_next(), None);
+        assert_eq!(
+            next_song(
+                &position,
+                &playlist,
+                &library,
+                RepeatMode::One.when_skipping()
+            ),
+            Some(songs[2])
+        );
+    }
+
+    #[test]
+    fn playlist_position_skips_songs_that_are_not_in_the_library() {
+        let (library, mut playlist, songs) = library_and_playlist(2);
+        let missing = SongId(u64::MAX);
+        playlist.insert_songs(1, vec![missing]);
+
+        let mut position = PlaylistPosition::Stopped;
+        let mut played = Vec::new();
+        while let Some((entry_id, song_id, _)) =
+            position.next_entry(&playlist, &library, None, RepeatMode::Off)
+        {
+            played.push(song_id);
+            position = PlaylistPosition::Entry(entry_id, song_id);
+        }
+        assert_eq!(played, songs);
+
+        let previous = position.previous_entry(&playlist, &library, None, RepeatMode::Off);
+        assert_eq!(previous.map(|(_, song_id, _)| song_id), Some(songs[0]));
+
+        // Only missing songs: there is nothing to play, also not when repeating.
+        let mut missing_only = Playlist::new();
+        missing_only.add_songs(vec![missing, missing]);
+        let mut shuffle = ShuffleOrder::new();
+        let next = PlaylistPosition::Stopped.next_entry(
+            &missing_only,
+            &library,
+            Some(&mut shuffle),
+            RepeatMode::All,
+        );
+        assert_eq!(next, None);
+    }
+
+    #[test]
+    fn playlist_position_continues_the_shuffled_order_after_a_removed_entry() {
+        let (library, mut playlist, _) = library_and_playlist(6);
+        let mut shuffle = ShuffleOrder::new();
+
+        let mut position = PlaylistPosition::Stopped;
+        let mut played = Vec::new();
+        for _ in 0..3 {
+            let (entry_id, song_id, _) = position
+                .next_entry(&playlist, &library, Some(&mut shuffle), RepeatMode::Off)
+                .unwrap();
+            played.push(entry_id);
+            position = PlaylistPosition::Entry(entry_id, song_id);
+        }
+
+        position.edit_playlist(&mut playlist, |playlist| playlist.remove_song(played[2]));
+
+        let mut rest = Vec::new();
+        while let Some((entry_id, song_id, _)) =
+            position.next_entry(&playlist, &library, Some(&mut shuffle), RepeatMode::Off)
+        {
+            rest.push(entry_id);
+            position = PlaylistPosition::Entry(entry_id, song_id);
+        }
+        assert_eq!(rest.len(), 3);
+        assert!(!rest.iter().any(|entry_id| played.contains(entry_id)));
+    }
+}
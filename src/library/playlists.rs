@@ -0,0 +1,282 @@
+use crate::library::Playlist;
+use serde_derive::{Deserialize, Serialize};
+
+/// Identifies a playlist in [Playlists]. Stays the same when playlists are renamed or removed.
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct PlaylistId(u64);
+
+impl PlaylistId {
+    fn next(&self) -> Self {
+        PlaylistId(self.0 + 1)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NamedPlaylist {
+    id: PlaylistId,
+    pub name: String,
+    pub playlist: Playlist,
+}
+
+impl NamedPlaylist {
+    pub fn id(&self) -> PlaylistId {
+        self.id
+    }
+}
+
+/// The playlists the user made, in the order they are shown.
+/// There is always at least one.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(from = "StoredPlaylists")]
+pub struct Playlists {
+    playlists: Vec<NamedPlaylist>,
+    /// Next id to use when creating a playlist.
+    next_id: PlaylistId,
+}
+
+/// What is stored of [Playlists]. Can be empty, unlike [Playlists] itself.
+#[derive(Deserialize)]
+struct StoredPlaylists {
+    playlists: Vec<NamedPlaylist>,
+    next_id: PlaylistId,
+}
+
+impl From<StoredPlaylists> for Playlists {
+    fn from(stored: StoredPlaylists) -> Self {
+        let mut playlists = Playlists {
+            playlists: stored.playlists,
+            next_id: stored.next_id,
+        };
+        if playlists.playlists.is_empty() {
+            playlists.create(Playlists::DEFAULT_NAME);
+        }
+        playlists
+    }
+}
+
+impl Playlists {
+    pub const DEFAULT_NAME: &'static str = "Playlist";
+
+    /// A single empty playlist.
+    pub fn new() -> Self {
+        let mut playlists = Self {
+            playlists: Vec::new(),
+            next_id: PlaylistId(0),
+        };
+        playlists.create(Self::DEFAULT_NAME);
+        playlists
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &NamedPlaylist> {
+        self.playlists.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut NamedPlaylist> {
+        self.playlists.iter_mut()
+    }
+
+    pub fn len(&self) -> usize {
+        self.playlists.len()
+    }
+
+    /// Always false, there is always at least one playlist.
+    pub fn is_empty(&self) -> bool {
+        self.playlists.is_empty()
+    }
+
+    pub fn first_id(&self) -> PlaylistId {
+        self.playlists[0].id
+    }
+
+    pub fn contains(&self, id: PlaylistId) -> bool {
+        self.get(id).is_some()
+    }
+
+    pub fn get(&self, id: PlaylistId) -> Option<&NamedPlaylist> {
+        self.playlists.iter().find(|playlist| playlist.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: PlaylistId) -> Option<&mut NamedPlaylist> {
+        self.playlists.iter_mut().find(|playlist| playlist.id == id)
+    }
+
+    /// The playlist with the id, or the first playlist if it was removed.
+    pub fn get_or_first(&self, id: PlaylistId) -> &NamedPlaylist {
+        let index = self.index_of(id).unwrap_or(0);
+        &self.playlists[index]
+    }
+
+    /// The playlist with the id, or the first playlist if it was removed.
+    pub fn get_or_first_mut(&mut self, id: PlaylistId) -> &mut NamedPlaylist {
+        let index = self.index_of(id).unwrap_or(0);
+        &mut self.playlists[index]
+    }
+
+    /// Adds an empty playlist at the end.
+    /// A number is added to the name if there already is a playlist with that name.
+    pub fn create(&mut self, name: &str) -> PlaylistId {
+        self.insert(self.playlists.len(), name, Playlist::new())
+    }
+
+    /// Adds a copy of the playlist right after it. The copy has entries of its own.
+    /// Returns `None` if there is no playlist with the id.
+    pub fn duplicate(&mut self, id: PlaylistId) -> Option<PlaylistId> {
+        let index = self.index_of(id)?;
+        let original = &self.playlists[index];
+        let name = format!("{} (copy)", original.name);
+        let playlist = original.playlist.duplicate();
+
+        Some(self.insert(index + 1, &name, playlist))
+    }
+
+    /// Empty names are ignored.
+    pub fn rename(&mut self, id: PlaylistId, name: &str) {
+        let name = name.trim();
+        if name.is_empty() {
+            return;
+        }
+
+        if let Some(playlist) = self.get_mut(id) {
+            playlist.name = name.to_string();
+        }
+    }
+
+    /// Returns whether it was removed. The last playlist can't be removed.
+    pub fn remove(&mut self, id: PlaylistId) -> bool {
+        match self.index_of(id) {
+            Some(index) if self.playlists.len() > 1 => {
+                self.playlists.remove(index);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn insert(&mut self, index: usize, name: &str, playlist: Playlist) -> PlaylistId {
+        let id = self.next_id;
+        self.next_id = self.next_id.next();
+
+        let name = self.unique_name(name);
+        self.playlists
+            .insert(index, NamedPlaylist { id, name, playlist });
+
+        id
+    }
+
+    fn index_of(&self, id: PlaylistId) -> Option<usize> {
+        self.playlists.iter().position(|playlist| playlist.id == id)
+    }
+
+    /// The name, or the name with the lowest number after it that is not used yet.
+    fn unique_name(&self, name: &str) -> String {
+        let is_used = |name: &str| self.playlists.iter().any(|playlist| playlist.name == name);
+
+        if !is_used(name) {
+            return name.to_string();
+        }
+
+        (2..)
+            .map(|number| format!("{} {}", name, number))
+            .find(|numbered| !is_used(numbered))
+            .unwrap_or_default()
+    }
+}
+
+impl Default for Playlists {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::library::playlists::Playlists;
+    use crate::library::{ListEntryId, Playlist, SongId};
+    use std::collections::HashSet;
+    use test_log::test;
+
+    fn names(playlists: &Playlists) -> Vec<&str> {
+        playlists
+            .iter()
+            .map(|playlist| playlist.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn playlists_are_created_renamed_duplicated_and_removed() {
+        let mut playlists = Playlists::new();
+        let first = playlists.first_id();
+        playlists
+            .get_mut(first)
+            .unwrap()
+            .playlist
+            .add_songs(vec![SongId(1), SongId(2)]);
+
+        let second = playlists.create(Playlists::DEFAULT_NAME);
+        let copy = playlists.duplicate(first).unwrap();
+        assert_eq!(
+            names(&playlists),
+            vec!["Playlist", "Playlist (copy)", "Playlist 2"]
+        );
+        let copied = &mut playlists.get_mut(copy).unwrap().playlist;
+        assert_eq!(copied.get_song_ids(), vec![SongId(1), SongId(2)]);
+        assert!(!copied.undo());
+        let entry_ids = |playlist: &Playlist| -> HashSet<ListEntryId> {
+            playlist.song_ids().map(|&(entry_id, _)| entry_id).collect()
+        };
+        let copied_entries = entry_ids(&playlists.get(copy).unwrap().playlist);
+        let original_entries = entry_ids(&playlists.get(first).unwrap().playlist);
+        assert!(copied_entries.is_disjoint(&original_entries));
+
+        // Entries added to either playlist afterwards don't get the ids of the other's entries.
+        for id in [first, copy] {
+            playlists
+                .get_mut(id)
+                .unwrap()
+                .playlist
+                .add_songs(vec![SongId(3), SongId(4)]);
+        }
+        let copied_entries = entry_ids(&playlists.get(copy).unwrap().playlist);
+        let original_entries = entry_ids(&playlists.get(first).unwrap().playlist);
+        assert_eq!(copied_entries.len(), 4);
+        assert_eq!(original_entries.len(), 4);
+        assert!(copied_entries.is_disjoint(&original_entries));
+
+        playlists.rename(second, "  Road trip ");
+        playlists.rename(copy, "");
+        assert_eq!(
+            names(&playlists),
+            vec!["Playlist", "Playlist (copy)", "Road trip"]
+        );
+
+        assert!(playlists.remove(first));
+        assert!(!playlists.contains(first));
+        assert_eq!(playlists.get_or_first(first).id(), copy);
+
+        assert!(playlists.remove(copy));
+        assert!(!playlists.remove(second));
+        assert_eq!(names(&playlists), vec!["Road trip"]);
+    }
+
+    #[test]
+    fn playlists_are_stored_and_loaded() {
+        let mut playlists = Playlists::new();
+        let id = playlists.create("Favourites");
+        playlists
+            .get_mut(id)
+            .unwrap()
+            .playlist
+            .add_songs(vec![SongId(3), SongId(1)]);
+
+        let stored = bincode::serialize(&playlists).unwrap();
+        let mut loaded: Playlists = bincode::deserialize(&stored).unwrap();
+
+        assert_eq!(names(&loaded), vec!["Playlist", "Favourites"]);
+        assert_eq!(
+            loaded.get(id).unwrap().playlist.get_song_ids(),
+            vec![SongId(3), SongId(1)]
+        );
+        // New playlists don't reuse the ids of the loaded ones.
+        assert_ne!(loaded.create("New"), id);
+    }
+}
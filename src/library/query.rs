@@ -0,0 +1,798 @@
+use crate::library::collation::fold;
+use crate::library::search::fuzzy_match;
+use crate::library::Song;
+use std::fmt;
+use std::time::Duration;
+
+/// A parsed search query, like `artist:radiohead year:>2000 -live dur:<5m`.
+///
+/// - Words are fuzzily matched against the title, artist and album of a song.
+/// - `"quoted text"` has to appear exactly like that in the title, artist or album.
+/// - `field:value` only matches songs with that value in the field.
+///   Number fields can be compared, like `year:>2000`, and durations can be given
+///   like `dur:<5m`, `dur:3m30s` or `length:3:30`.
+///   Other words with a colon are searched for as they are, like `Re: Stacks`.
+/// - `-` in front of anything only matches songs that do not match it.
+/// - Everything has to match, unless it is separated by `OR`.
+/// - Parentheses group things, like `(artist:a OR artist:b) -live`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Query {
+    /// Matches every song. This is what an empty query parses to.
+    All,
+    Word(String),
+    Phrase(String),
+    Field(FieldPredicate),
+    Not(Box<Query>),
+    And(Vec<Query>),
+    Or(Vec<Query>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextField {
+    Title,
+    Artist,
+    AlbumArtist,
+    Album,
+    Genre,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumberField {
+    Year,
+    Track,
+    Disc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldPredicate {
+    /// The field contains the text, ignoring case.
+    Text { field: TextField, text: String },
+    Number {
+        field: NumberField,
+        comparison: Comparison,
+        value: u32,
+    },
+    Duration {
+        comparison: Comparison,
+        value: Duration,
+    },
+}
+
+/// Why a query could not be parsed, and where.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryError {
+    pub kind: QueryErrorKind,
+    /// Position in the query, in characters, where the problem was found.
+    pub position: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryErrorKind {
+    /// A field without a value, like `artist:`.
+    MissingValue(String),
+    InvalidNumber(String),
+    InvalidDuration(String),
+    /// Comparisons like `>` only work on numbers and durations.
+    ComparisonOnTextField(String),
+    UnclosedQuote,
+    UnclosedParenthesis,
+    UnexpectedClosingParenthesis,
+    /// `OR` or `-` without something to apply to, or empty parentheses.
+    MissingTerm,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            QueryErrorKind::MissingValue(field) => write!(f, "Field '{}' needs a value", field),
+            QueryErrorKind::InvalidNumber(value) => write!(f, "'{}' is not a number", value),
+            QueryErrorKind::InvalidDuration(value) => {
+                write!(f, "'{}' is not a duration, like 3m30s or 3:30", value)
+            }
+            QueryErrorKind::ComparisonOnTextField(field) => {
+                write!(f, "Field '{}' is text, and can't be compared", field)
+            }
+            QueryErrorKind::UnclosedQuote => write!(f, "Quote is not closed"),
+            QueryErrorKind::UnclosedParenthesis => write!(f, "Parenthesis is not closed"),
+            QueryErrorKind::UnexpectedClosingParenthesis => {
+                write!(f, "Closing parenthesis without an opening one")
+            }
+            QueryErrorKind::MissingTerm => write!(f, "Something to search for is missing"),
+        }?;
+        write!(f, " (at character {})", self.position + 1)
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+impl Query {
+    pub fn parse(query: &str) -> Result<Query, QueryError> {
+        let tokens = tokenize(query)?;
+        let mut parser = Parser {
+            tokens,
+            next: 0,
+            end: query.chars().count(),
+        };
+
+        if parser.tokens.is_empty() {
+            return Ok(Query::All);
+        }
+
+        let query = parser.parse_or()?;
+        match parser.peek() {
+            None => Ok(query),
+            Some((Token::CloseParen, position)) => Err(QueryError {
+                kind: QueryErrorKind::UnexpectedClosingParenthesis,
+                position,
+            }),
+            Some((_, position)) => Err(QueryError {
+                kind: QueryErrorKind::MissingTerm,
+                position,
+            }),
+        }
+    }
+
+    /// Scores how well the song matches, higher is better. `None` if it does not match.
+    /// Only words and phrases add to the score, field predicates either match or don't.
+    pub fn score(&self, song: &Song) -> Option<i64> {
+        self.score_with(song, false)
+    }
+
+    pub fn matches(&self, song: &Song) -> bool {
+        self.score(song).is_some()
+    }
+
+    /// When `exact` is set, words only match if they appear in the text as they are.
+    /// This is used for negations, where the typo allowance would exclude far too much.
+    fn score_with(&self, song: &Song, exact: bool) -> Option<i64> {
+        match self {
+            Query::All => Some(0),
+            Query::Word(word) if exact => contains_in_search_fields(song, word).then_some(0),
+            Query::Word(word) => search_fields(song)
+                .filter_map(|text| fuzzy_match(word, text))
+                .max(),
+            Query::Phrase(phrase) => search_fields(song)
+                .filter(|text| contains_folded(text, phrase))
+                .map(|text| fuzzy_match(phrase, text).unwrap_or(0))
+                .max(),
+            Query::Field(predicate) => predicate.matches(song).then_some(0),
+            Query::Not(query) => match query.score_with(song, true) {
+                Some(_) => None,
+                None => Some(0),
+            },
+            Query::And(queries) => queries
+                .iter()
+                .map(|query| query.score_with(song, exact))
+                .sum(),
+            Query::Or(queries) => queries
+                .iter()
+                .filter_map(|query| query.score_with(song, exact))
+                .max(),
+        }
+    }
+}
+
+impl FieldPredicate {
+    pub fn matches(&self, song: &Song) -> bool {
+        match self {
+            FieldPredicate::Text { field, text } => field
+                .value(song)
+                .is_some_and(|value| contains_folded(value, text)),
+            FieldPredicate::Number {
+                field,
+                comparison,
+                value,
+            } => field
+                .value(song)
+                .is_some_and(|song_value| comparison.compare(song_value, *value)),
+            FieldPredicate::Duration { comparison, value } => {
+                song.duration.is_some_and(|duration| {
+                    // Compare whole seconds, so `dur:3:30` matches a song of 3:30.4.
+                    comparison.compare(duration.as_secs(), value.as_secs())
+                })
+            }
+        }
+    }
+}
+
+impl TextField {
+    fn value<'a>(&self, song: &'a Song) -> Option<&'a str> {
+        match self {
+            TextField::Title => Some(&song.title),
+            TextField::Artist => song.artist.as_deref(),
+            TextField::AlbumArtist => song.album_artist.as_deref(),
+            TextField::Album => song.album.as_deref(),
+            TextField::Genre => song.genre.as_deref(),
+        }
+    }
+}
+
+impl NumberField {
+    fn value(&self, song: &Song) -> Option<u32> {
+        match self {
+            NumberField::Year => song.year,
+            NumberField::Track => song.track_number,
+            NumberField::Disc => song.disc_number,
+        }
+    }
+}
+
+impl Comparison {
+    fn compare<T: Ord>(&self, song_value: T, query_value: T) -> bool {
+        match self {
+            Comparison::Equal => song_value == query_value,
+            Comparison::Less => song_value < query_value,
+            Comparison::LessOrEqual => song_value <= query_value,
+            Comparison::Greater => song_value > query_value,
+            Comparison::GreaterOrEqual => song_value >= query_value,
+        }
+    }
+}
+
+/// The fields that words and phrases without a field are searched in.
+fn search_fields(song: &Song) -> impl Iterator<Item = &str> {
+    [
+        Some(song.title.as_str()),
+        song.artist.as_deref(),
+        song.album.as_deref(),
+    ]
+    .into_iter()
+    .flatten()
+}
+
+fn contains_in_search_fields(song: &Song, text: &str) -> bool {
+    search_fields(song).any(|field| contains_folded(field, text))
+}
+
+fn contains_folded(text: &str, part: &str) -> bool {
+    fold(text).contains(&fold(part))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Phrase(String),
+    /// `name:value`, where the value may start with a comparison like `>=`.
+    Field {
+        name: String,
+        field: Field,
+        value: String,
+    },
+    Minus,
+    Or,
+    OpenParen,
+    CloseParen,
+}
+
+/// Splits the query into tokens, each with the position of its first character.
+fn tokenize(query: &str) -> Result<Vec<(Token, usize)>, QueryError> {
+    let chars: Vec<char> = query.chars().collect();
+    let mut tokens = Vec::new();
+    let mut position = 0;
+
+    while position < chars.len() {
+        let start = position;
+        let c = chars[position];
+
+        if c.is_whitespace() {
+            position += 1;
+        } else if c == '(' {
+            tokens.push((Token::OpenParen, start));
+            position += 1;
+        } else if c == ')' {
+            tokens.push((Token::CloseParen, start));
+            position += 1;
+        } else if c == '-'
+            && chars
+                .get(position + 1)
+                .is_some_and(|next| !next.is_whitespace())
+        {
+            tokens.push((Token::Minus, start));
+            position += 1;
+        } else if c == '"' {
+            let (phrase, end) = read_quoted(&chars, position)?;
+            tokens.push((Token::Phrase(phrase), start));
+            position = end;
+        } else {
+            let mut word = String::new();
+            let mut field_value = None;
+
+            while position < chars.len() {
+                let c = chars[position];
+                if c.is_whitespace() || c == '(' || c == ')' {
+                    break;
+                }
+
+                // Only known fields are fields, so `Re: Stacks` and a time like `12:30` are words.
+                // Field values can contain colons too, like `length:12:30`.
+                if c == ':' && field_value.is_none() && field_named(&word).is_some() {
+                    field_value = Some(String::new());
+                    position += 1;
+                } else if c == '"' && field_value.is_some() {
+                    // Quoted field values, like `artist:"the national"`.
+                    let (quoted, end) = read_quoted(&chars, position)?;
+                    field_value.as_mut().unwrap().push_str(&quoted);
+                    position = end;
+                } else {
+                    match &mut field_value {
+                        Some(value) => value.push(c),
+                        None => word.push(c),
+                    }
+                    position += 1;
+                }
+            }
+
+            let token = match (field_value, field_named(&word)) {
+                (Some(value), Some(field)) => Token::Field {
+                    name: word,
+                    field,
+                    value,
+                },
+                _ if word == "OR" => Token::Or,
+                _ => Token::Word(word),
+            };
+            tokens.push((token, start));
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Text(TextField),
+    Number(NumberField),
+    Duration,
+}
+
+fn field_named(name: &str) -> Option<Field> {
+    match name.to_lowercase().as_str() {
+        "title" => Some(Field::Text(TextField::Title)),
+        "artist" => Some(Field::Text(TextField::Artist)),
+        "albumartist" | "album_artist" => Some(Field::Text(TextField::AlbumArtist)),
+        "album" => Some(Field::Text(TextField::Album)),
+        "genre" => Some(Field::Text(TextField::Genre)),
+        "year" => Some(Field::Number(NumberField::Year)),
+        "track" => Some(Field::Number(NumberField::Track)),
+        "disc" => Some(Field::Number(NumberField::Disc)),
+        "dur" | "duration" | "length" => Some(Field::Duration),
+        _ => None,
+    }
+}
+
+/// Reads the text between the quote at `start` and the closing quote.
+/// Returns the text and the position after the closing quote.
+fn read_quoted(chars: &[char], start: usize) -> Result<(String, usize), QueryError> {
+    match chars[start + 1..].iter().position(|&c| c == '"') {
+        Some(length) => Ok((
+            chars[start + 1..start + 1 + length].iter().collect(),
+            start + length + 2,
+        )),
+        None => Err(QueryError {
+            kind: QueryErrorKind::UnclosedQuote,
+            position: start,
+        }),
+    }
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    next: usize,
+    /// Position just after the query, for errors at the very end.
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<(Token, usize)> {
+        self.tokens.get(self.next).cloned()
+    }
+
+    fn position(&self) -> usize {
+        self.peek().map_or(self.end, |(_, position)| position)
+    }
+
+    fn parse_or(&mut self) -> Result<Query, QueryError> {
+        let mut queries = vec![self.parse_and()?];
+
+        while let Some((Token::Or, _)) = self.peek() {
+            self.next += 1;
+            queries.push(self.parse_and()?);
+        }
+
+        Ok(if queries.len() == 1 {
+            queries.pop().unwrap()
+        } else {
+            Query::Or(queries)
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<Query, QueryError> {
+        let mut queries = Vec::new();
+
+        while let Some((token, _)) = self.peek() {
+            if token == Token::Or || token == Token::CloseParen {
+                break;
+            }
+            queries.push(self.parse_term()?);
+        }
+
+        match queries.len() {
+            0 => Err(QueryError {
+                kind: QueryErrorKind::MissingTerm,
+                position: self.position(),
+            }),
+            1 => Ok(queries.pop().unwrap()),
+            _ => Ok(Query::And(queries)),
+        }
+    }
+
+    fn parse_term(&mut self) -> Result<Query, QueryError> {
+        let (token, position) = match self.peek() {
+            Some(token) => token,
+            None => {
+                return Err(QueryError {
+                    kind: QueryErrorKind::MissingTerm,
+                    position: self.end,
+                })
+            }
+        };
+        self.next += 1;
+
+        match token {
+            Token::Word(word) => Ok(Query::Word(word)),
+            Token::Phrase(phrase) => Ok(Query::Phrase(phrase)),
+            Token::Field { name, field, value } => {
+                parse_field(&name, field, &value, position).map(Query::Field)
+            }
+            Token::Minus => Ok(Query::Not(Box::new(self.parse_term()?))),
+            Token::OpenParen => {
+                let query = self.parse_or()?;
+                match self.peek() {
+                    Some((Token::CloseParen, _)) => {
+                        self.next += 1;
+                        Ok(query)
+                    }
+                    _ => Err(QueryError {
+                        kind: QueryErrorKind::UnclosedParenthesis,
+                        position,
+                    }),
+                }
+            }
+            Token::CloseParen => Err(QueryError {
+                kind: QueryErrorKind::UnexpectedClosingParenthesis,
+                position,
+            }),
+            Token::Or => Err(QueryError {
+                kind: QueryErrorKind::MissingTerm,
+                position,
+            }),
+        }
+    }
+}
+
+fn parse_field(
+    name: &str,
+    field: Field,
+    value: &str,
+    position: usize,
+) -> Result<FieldPredicate, QueryError> {
+    let error = |kind| QueryError { kind, position };
+
+    let (comparison, value) = split_comparison(value);
+    if value.is_empty() {
+        return Err(error(QueryErrorKind::MissingValue(name.to_owned())));
+    }
+
+    match (field, comparison) {
+        (Field::Text(_), Some(_)) => Err(error(QueryErrorKind::ComparisonOnTextField(
+            name.to_owned(),
+        ))),
+        (Field::Text(field), None) => Ok(FieldPredicate::Text {
+            field,
+            text: value.to_owned(),
+        }),
+        (Field::Number(field), comparison) => match value.parse() {
+            Ok(value) => Ok(FieldPredicate::Number {
+                field,
+                comparison: comparison.unwrap_or(Comparison::Equal),
+                value,
+            }),
+            Err(_) => Err(error(QueryErrorKind::InvalidNumber(value.to_owned()))),
+        },
+        (Field::Duration, comparison) => match parse_duration(value) {
+            Some(value) => Ok(FieldPredicate::Duration {
+                comparison: comparison.unwrap_or(Comparison::Equal),
+                value,
+            }),
+            None => Err(error(QueryErrorKind::InvalidDuration(value.to_owned()))),
+        },
+    }
+}
+
+fn split_comparison(value: &str) -> (Option<Comparison>, &str) {
+    // Longest first, so `>=` is not read as `>`.
+    let comparisons = [
+        (">=", Comparison::GreaterOrEqual),
+        ("<=", Comparison::LessOrEqual),
+        (">", Comparison::Greater),
+        ("<", Comparison::Less),
+        ("=", Comparison::Equal),
+    ];
+
+    for (prefix, comparison) in comparisons {
+        if let Some(rest) = value.strip_prefix(prefix) {
+            return (Some(comparison), rest);
+        }
+    }
+    (None, value)
+}
+
+/// Parses durations like `90` (seconds), `5m`, `3m30s`, `1h2m`, `3:30` and `1:02:03`.
+/// Returns `None` for durations too long to count in seconds.
+fn parse_duration(text: &str) -> Option<Duration> {
+    if text.contains(':') {
+        let mut seconds: u64 = 0;
+        for part in text.split(':') {
+            seconds = seconds
+                .checked_mul(60)?
+                .checked_add(part.parse::<u64>().ok()?)?;
+        }
+        return Some(Duration::from_secs(seconds));
+    }
+
+    if let Ok(seconds) = text.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let mut seconds: u64 = 0;
+    let mut number = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+        } else {
+            let unit = match c.to_ascii_lowercase() {
+                'h' => 3600,
+                'm' => 60,
+                's' => 1,
+                _ => return None,
+            };
+            let part = number.parse::<u64>().ok()?.checked_mul(unit)?;
+            seconds = seconds.checked_add(part)?;
+            number.clear();
+        }
+    }
+
+    // A number without a unit at the end, like the "30" in "3m30".
+    if !number.is_empty() {
+        seconds = seconds.checked_add(number.parse::<u64>().ok()?)?;
+    }
+
+    Some(Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::library::query::{
+        parse_duration, Comparison, FieldPredicate, NumberField, Query, QueryErrorKind, TextField,
+    };
+    use crate::library::Song;
+    use std::time::Duration;
+    use test_log::test;
+
+    fn song() -> Song {
+        Song {
+            title: "Karma Police".to_string(),
+            artist: Some("Radiohead".to_string()),
+            album: Some("OK Computer".to_string()),
+            year: Some(1997),
+            track_number: Some(6),
+            duration: Some(Duration::from_secs(264)),
+            ..Default::default()
+        }
+    }
+
+    fn error_kind(query: &str) -> QueryErrorKind {
+        Query::parse(query).unwrap_err().kind
+    }
+
+    #[test]
+    fn query_parses_into_ast() {
+        assert_eq!(Query::parse("  ").unwrap(), Query::All);
+        assert_eq!(
+            Query::parse("artist:radiohead year:>2000 -live dur:<5m").unwrap(),
+            Query::And(vec![
+                Query::Field(FieldPredicate::Text {
+                    field: TextField::Artist,
+                    text: "radiohead".to_string()
+                }),
+                Query::Field(FieldPredicate::Number {
+                    field: NumberField::Year,
+                    comparison: Comparison::Greater,
+                    value: 2000
+                }),
+                Query::Not(Box::new(Query::Word("live".to_string()))),
+                Query::Field(FieldPredicate::Duration {
+                    comparison: Comparison::Less,
+                    value: Duration::from_secs(300)
+                }),
+            ])
+        );
+        assert_eq!(
+            Query::parse("(a OR \"b c\") d").unwrap(),
+            Query::And(vec![
+                Query::Or(vec![
+                    Query::Word("a".to_string()),
+                    Query::Phrase("b c".to_string())
+                ]),
+                Query::Word("d".to_string()),
+            ])
+        );
+        assert_eq!(
+            Query::parse("artist:\"the national\"").unwrap(),
+            Query::Field(FieldPredicate::Text {
+                field: TextField::Artist,
+                text: "the national".to_string()
+            })
+        );
+        // A dash inside a word, or on its own, is part of the text.
+        assert_eq!(
+            Query::parse("jay-z - x").unwrap(),
+            Query::And(vec![
+                Query::Word("jay-z".to_string()),
+                Query::Word("-".to_string()),
+                Query::Word("x".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn query_reports_malformed_queries() {
+        assert_eq!(
+            error_kind("artist:"),
+            QueryErrorKind::MissingValue("artist".to_string())
+        );
+        assert_eq!(
+            error_kind("year:>nineties"),
+            QueryErrorKind::InvalidNumber("nineties".to_string())
+        );
+        assert_eq!(
+            error_kind("dur:long"),
+            QueryErrorKind::InvalidDuration("long".to_string())
+        );
+        assert_eq!(
+            error_kind("artist:>a"),
+            QueryErrorKind::ComparisonOnTextField("artist".to_string())
+        );
+        assert_eq!(error_kind("\"karma"), QueryErrorKind::UnclosedQuote);
+        assert_eq!(error_kind("(karma"), QueryErrorKind::UnclosedParenthesis);
+        assert_eq!(
+            error_kind("karma)"),
+            QueryErrorKind::UnexpectedClosingParenthesis
+        );
+        assert_eq!(error_kind("karma OR"), QueryErrorKind::MissingTerm);
+        assert_eq!(error_kind("OR karma"), QueryErrorKind::MissingTerm);
+        assert_eq!(error_kind("()"), QueryErrorKind::MissingTerm);
+
+        let error = Query::parse("karma year:red").unwrap_err();
+        assert_eq!(error.position, 6);
+    }
+
+    #[test]
+    fn query_keeps_words_with_a_colon_that_are_not_fields() {
+        assert_eq!(
+            Query::parse("Re: Stacks").unwrap(),
+            Query::And(vec![
+                Query::Word("Re:".to_string()),
+                Query::Word("Stacks".to_string()),
+            ])
+        );
+        assert_eq!(
+            Query::parse("colour:red").unwrap(),
+            Query::Word("colour:red".to_string())
+        );
+        assert_eq!(
+            Query::parse("Title:Re:").unwrap(),
+            Query::Field(FieldPredicate::Text {
+                field: TextField::Title,
+                text: "Re:".to_string()
+            })
+        );
+
+        let stacks = Song {
+            title: "Re: Stacks".to_string(),
+            artist: Some("Bon Iver".to_string()),
+            ..Default::default()
+        };
+        assert!(Query::parse("Re: Stacks").unwrap().matches(&stacks));
+        assert!(!Query::parse("Re: Stacks").unwrap().matches(&song()));
+    }
+
+    #[test]
+    fn query_matches_songs() {
+        let matches = |query: &str| Query::parse(query).unwrap().matches(&song());
+
+        assert!(matches(""));
+        assert!(matches("karma"));
+        assert!(matches("radiohed"));
+        assert!(matches("artist:radio year:>=1997 dur:<5m"));
+        assert!(matches("year:1997 track:6 dur:4:24"));
+        assert!(matches("\"ok computer\""));
+        assert!(matches("-live"));
+        assert!(matches("artist:muse OR artist:radiohead"));
+        assert!(matches("-(artist:muse OR year:<1990)"));
+
+        assert!(!matches("artist:muse"));
+        assert!(!matches("year:>1997"));
+        assert!(!matches("dur:>5m"));
+        assert!(!matches("\"computer ok\""));
+        assert!(!matches("-police"));
+        assert!(!matches("genre:rock"));
+    }
+
+    #[test]
+    fn negated_words_are_matched_exactly() {
+        let song = Song {
+            title: "Love".to_string(),
+            ..Default::default()
+        };
+
+        // With the typo allowance "live" would match "love", and exclude it.
+        assert!(Query::parse("-live").unwrap().matches(&song));
+    }
+
+    #[test]
+    fn parse_duration_accepts_several_formats() {
+        let seconds = |text| parse_duration(text).map(|duration| duration.as_secs());
+
+        assert_eq!(seconds("90"), Some(90));
+        assert_eq!(seconds("5m"), Some(300));
+        assert_eq!(seconds("3m30s"), Some(210));
+        assert_eq!(seconds("3m30"), Some(210));
+        assert_eq!(seconds("1h2m"), Some(3720));
+        assert_eq!(seconds("3:30"), Some(210));
+        assert_eq!(seconds("1:02:03"), Some(3723));
+        assert_eq!(seconds("3x"), None);
+        assert_eq!(seconds("3::"), None);
+    }
+
+    #[test]
+    fn parse_duration_rejects_durations_that_are_too_long() {
+        let seconds = |text: &str| parse_duration(text).map(|duration| duration.as_secs());
+        let long_number = "9".repeat(19);
+
+        assert_eq!(seconds(&format!("{}:00", long_number)), None);
+        assert_eq!(seconds(&format!("{}:1:00", long_number)), None);
+        assert_eq!(seconds(&format!("{}h", long_number)), None);
+        assert_eq!(seconds(&format!("{}m{}s", long_number, long_number)), None);
+        assert_eq!(seconds(&"9".repeat(40)), None);
+
+        assert_eq!(
+            error_kind(&format!("dur:{}:00", long_number)),
+            QueryErrorKind::InvalidDuration(format!("{}:00", long_number))
+        );
+    }
+
+    #[test]
+    fn query_reads_times_in_field_values() {
+        let twelve_thirty = Query::Field(FieldPredicate::Duration {
+            comparison: Comparison::Equal,
+            value: Duration::from_secs(750),
+        });
+
+        assert_eq!(Query::parse("length:12:30").unwrap(), twelve_thirty);
+        assert_eq!(Query::parse("dur:12:30").unwrap(), twelve_thirty);
+        assert_eq!(
+            Query::parse("12:30").unwrap(),
+            Query::Word("12:30".to_string())
+        );
+    }
+}
@@ -0,0 +1,416 @@
+use crate::library::{LibraryRoot, ScanOptions};
+use anyhow::Result;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use log::{debug, warn};
+use std::collections::{HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Lists paths, in gitignore syntax, that should be left out of the library.
+/// Applies to the directory it is in, and everything below it.
+pub const IGNORE_FILE_NAME: &str = ".smpignore";
+/// A directory containing a file with this name is skipped completely, including everything below it.
+pub const NO_MEDIA_FILE_NAME: &str = ".nomedia";
+
+/// A [LibraryRoot] with its glob patterns compiled, so they can be matched against many files.
+pub(crate) struct RootFilter<'a> {
+    pub root: &'a LibraryRoot,
+    include: Vec<glob::Pattern>,
+    exclude: Vec<glob::Pattern>,
+}
+
+impl<'a> RootFilter<'a> {
+    pub fn new(root: &'a LibraryRoot) -> Self {
+        Self {
+            root,
+            include: compile_patterns(&root.include),
+            exclude: compile_patterns(&root.exclude),
+        }
+    }
+
+    pub fn accepts(&self, path: &Path) -> bool {
+        let relative_path = path.strip_prefix(&self.root.path).unwrap_or(path);
+
+        let included = self.include.is_empty()
+            || self
+                .include
+                .iter()
+                .any(|pattern| pattern.matches_path(relative_path));
+        let excluded = self
+            .exclude
+            .iter()
+            .any(|pattern| pattern.matches_path(relative_path));
+
+        included && !excluded
+    }
+}
+
+/// Patterns that are not valid are skipped, with a warning.
+fn compile_patterns(patterns: &[String]) -> Vec<glob::Pattern> {
+    patterns
+        .iter()
+        .filter(|pattern| !pattern.trim().is_empty())
+        .filter_map(|pattern| match glob::Pattern::new(pattern.trim()) {
+            Ok(pattern) => Some(pattern),
+            Err(e) => {
+                warn!("Ignoring invalid glob pattern '{}': {}", pattern, e);
+                None
+            }
+        })
+        .collect()
+}
+
+pub(crate) fn has_song_extension(path: &Path, extensions: &[String]) -> bool {
+    match path.extension() {
+        Some(extension) => {
+            let extension = extension.to_string_lossy();
+            extensions.iter().any(|song_extension| {
+                // Be lenient towards extensions that are configured with a leading dot.
+                song_extension
+                    .trim_start_matches('.')
+                    .eq_ignore_ascii_case(&extension)
+            })
+        }
+        None => false,
+    }
+}
+
+/// The result of [find_song_files].
+#[derive(Default)]
+pub(crate) struct FoundFiles {
+    pub files: Vec<PathBuf>,
+    /// Directories that could not be read. Whatever songs they contain are not in `files`.
+    pub unreadable_dirs: Vec<PathBuf>,
+    pub warnings: Vec<String>,
+}
+
+/// Finds all song files in the directory and its subdirectories that are accepted by the filter.
+/// The directory has to be inside the filter's root. Ignore files in the directories between
+/// the root and the directory are taken into account, as if the scan started at the root.
+///
+/// Every file and directory is visited only once, even when it can be reached through
+/// several paths because of symbolic links. Symbolic links are followed last,
+/// so files are found through the path without a link if there is one.
+pub(crate) fn find_song_files(
+    dir: &Path,
+    filter: &RootFilter<'_>,
+    options: &ScanOptions,
+) -> Result<FoundFiles> {
+    if !dir.exists() {
+        anyhow::bail!(
+            "Could not scan directory '{}', it does not exist.",
+            dir.display()
+        );
+    }
+    if !dir.is_dir() {
+        anyhow::bail!(
+            "Could not scan directory '{}', it is not a directory.",
+            dir.display()
+        );
+    }
+
+    let mut walker = Walker {
+        filter,
+        options,
+        visited_dirs: HashSet::new(),
+        visited_files: HashSet::new(),
+        symlinks: VecDeque::new(),
+        found: FoundFiles::default(),
+    };
+
+    let mut ignore_files = match ignore_files_above(dir, &filter.root.path, options) {
+        Some(ignore_files) => ignore_files,
+        None => return Ok(walker.found),
+    };
+    walker.walk_directory(dir, &mut ignore_files);
+
+    while let Some((path, mut ignore_files)) = walker.symlinks.pop_front() {
+        walker.follow_symlink(&path, &mut ignore_files);
+    }
+
+    Ok(walker.found)
+}
+
+/// Whether the path, which is inside the root, is skipped when scanning the root.
+/// This is the case when it is matched by an ignore file, when it is a symbolic link
+/// that should not be followed, or when one of the directories it is in is skipped.
+pub(crate) fn is_ignored(path: &Path, root: &Path, options: &ScanOptions) -> bool {
+    if path == root {
+        return false;
+    }
+
+    let parent = match path.parent() {
+        Some(parent) => parent,
+        None => return false,
+    };
+
+    match ignore_files_above(parent, root, options) {
+        Some(mut ignore_files) => {
+            push_ignore_file(parent, &mut ignore_files);
+            let is_dir = path.is_dir();
+            (!options.follow_symlinks && is_symlink(path))
+                || (is_dir && skips_directory(path, options))
+                || matches_ignore_files(path, is_dir, &ignore_files)
+        }
+        None => true,
+    }
+}
+
+/// Collects the ignore files of the directories from the root down to, but not including, `dir`.
+/// Returns `None` if `dir` itself is skipped, because of one of those directories.
+fn ignore_files_above(dir: &Path, root: &Path, options: &ScanOptions) -> Option<Vec<Gitignore>> {
+    let mut ignore_files = Vec::new();
+
+    let relative_dir = match dir.strip_prefix(root) {
+        Ok(relative_dir) => relative_dir,
+        // Outside of the root, there is nothing to take into account.
+        Err(_) => return Some(ignore_files),
+    };
+
+    let mut current = root.to_path_buf();
+    for component in relative_dir.components() {
+        if current.join(NO_MEDIA_FILE_NAME).exists() {
+            return None;
+        }
+        push_ignore_file(&current, &mut ignore_files);
+
+        current.push(component);
+        if (!options.follow_symlinks && is_symlink(&current))
+            || skips_directory(&current, options)
+            || matches_ignore_files(&current, true, &ignore_files)
+        {
+            return None;
+        }
+    }
+
+    Some(ignore_files)
+}
+
+struct Walker<'a> {
+    filter: &'a RootFilter<'a>,
+    options: &'a ScanOptions,
+    visited_dirs: HashSet<FileId>,
+    visited_files: HashSet<FileId>,
+    /// Symbolic links that are followed once everything else has been walked,
+    /// together with the ignore files that apply to them.
+    symlinks: VecDeque<(PathBuf, Vec<Gitignore>)>,
+    found: FoundFiles,
+}
+
+impl<'a> Walker<'a> {
+    /// `ignore_files` holds the ignore files of all the directories above `dir`.
+    /// When this returns, it is back in the same state.
+    fn walk_directory(&mut self, dir: &Path, ignore_files: &mut Vec<Gitignore>) {
+        if dir.join(NO_MEDIA_FILE_NAME).exists() {
+            return;
+        }
+
+        let id = match FileId::read(dir) {
+            Ok(id) => id,
+            Err(e) => {
+                self.add_unreadable_dir(dir, e);
+                return;
+            }
+        };
+        // Prevents scanning the same directory twice, and endless loops.
+        if !self.visited_dirs.insert(id) {
+            debug!(
+                "Skipping directory '{}', it was already scanned through another path.",
+                dir.display()
+            );
+            return;
+        }
+
+        let mut entries = Vec::new();
+        match fs::read_dir(dir) {
+            Ok(read_dir) => {
+                for entry in read_dir {
+                    match entry {
+                        Ok(entry) => entries.push(entry),
+                        Err(e) => {
+                            self.add_unreadable_dir(dir, e);
+                            return;
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                self.add_unreadable_dir(dir, e);
+                return;
+            }
+        }
+        // The order in which the file system lists entries differs between platforms.
+        entries.sort_by_key(|entry| entry.file_name());
+
+        let pushed = push_ignore_file(dir, ignore_files);
+
+        for entry in entries {
+            let path = entry.path();
+            let file_type = match entry.file_type() {
+                Ok(file_type) => file_type,
+                Err(e) => {
+                    self.found
+                        .warnings
+                        .push(format!("Could not read '{}': {}", path.display(), e));
+                    continue;
+                }
+            };
+
+            if file_type.is_symlink() {
+                if self.options.follow_symlinks {
+                    self.symlinks.push_back((path, ignore_files.clone()));
+                }
+            } else if file_type.is_dir() {
+                if !skips_directory(&path, self.options)
+                    && !matches_ignore_files(&path, true, ignore_files)
+                {
+                    self.walk_directory(&path, ignore_files);
+                }
+            } else if file_type.is_file() {
+                self.add_file(path, ignore_files);
+            }
+        }
+
+        if pushed {
+            ignore_files.pop();
+        }
+    }
+
+    fn follow_symlink(&mut self, path: &Path, ignore_files: &mut Vec<Gitignore>) {
+        match fs::metadata(path) {
+            Ok(metadata) if metadata.is_dir() => {
+                if !skips_directory(path, self.options)
+                    && !matches_ignore_files(path, true, ignore_files)
+                {
+                    self.walk_directory(path, ignore_files);
+                }
+            }
+            Ok(metadata) if metadata.is_file() => self.add_file(path.to_path_buf(), ignore_files),
+            Ok(_) => {}
+            Err(e) => self.found.warnings.push(format!(
+                "Could not follow symbolic link '{}': {}",
+                path.display(),
+                e
+            )),
+        }
+    }
+
+    fn add_file(&mut self, path: PathBuf, ignore_files: &[Gitignore]) {
+        if !has_song_extension(&path, &self.options.extensions)
+            || !self.filter.accepts(&path)
+            || matches_ignore_files(&path, false, ignore_files)
+        {
+            return;
+        }
+
+        // The same file can be reached through several paths, but should only be added once.
+        if let Ok(id) = FileId::read(&path) {
+            if !self.visited_files.insert(id) {
+                debug!(
+                    "Skipping '{}', it was already found through another path.",
+                    path.display()
+                );
+                return;
+            }
+        }
+
+        self.found.files.push(path);
+    }
+
+    fn add_unreadable_dir(&mut self, dir: &Path, error: std::io::Error) {
+        self.found.warnings.push(format!(
+            "Could not read directory '{}': {}",
+            dir.display(),
+            error
+        ));
+        self.found.unreadable_dirs.push(dir.to_path_buf());
+    }
+}
+
+/// Identifies a file or directory, no matter through which path it is reached.
+#[cfg(unix)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+struct FileId {
+    device: u64,
+    inode: u64,
+}
+
+#[cfg(unix)]
+impl FileId {
+    fn read(path: &Path) -> std::io::Result<Self> {
+        use std::os::unix::fs::MetadataExt;
+
+        let metadata = fs::metadata(path)?;
+        Ok(Self {
+            device: metadata.dev(),
+            inode: metadata.ino(),
+        })
+    }
+}
+
+/// Identifies a file or directory, no matter through which path it is reached.
+/// Device and inode numbers are not available here, so the path without any links is used instead.
+#[cfg(not(unix))]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+struct FileId(PathBuf);
+
+#[cfg(not(unix))]
+impl FileId {
+    fn read(path: &Path) -> std::io::Result<Self> {
+        fs::canonicalize(path).map(Self)
+    }
+}
+
+/// Whether the directory is skipped regardless of any ignore files or links.
+fn skips_directory(dir: &Path, options: &ScanOptions) -> bool {
+    !options.scan_hidden_directories && is_hidden(dir)
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
+fn is_symlink(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_symlink())
+}
+
+/// Adds the ignore file of the directory to the list, if it has one.
+/// Returns whether one was added.
+fn push_ignore_file(dir: &Path, ignore_files: &mut Vec<Gitignore>) -> bool {
+    let path = dir.join(IGNORE_FILE_NAME);
+    if !path.is_file() {
+        return false;
+    }
+
+    let mut builder = GitignoreBuilder::new(dir);
+    if let Some(e) = builder.add(&path) {
+        // Invalid lines are reported, but the valid ones are still used.
+        warn!("Problem in ignore file '{}': {}", path.display(), e);
+    }
+
+    match builder.build() {
+        Ok(ignore_file) => {
+            ignore_files.push(ignore_file);
+            true
+        }
+        Err(e) => {
+            warn!("Could not read ignore file '{}': {}", path.display(), e);
+            false
+        }
+    }
+}
+
+/// The ignore file closest to the path has the final say,
+/// so a subdirectory can include files again that were excluded further up.
+fn matches_ignore_files(path: &Path, is_dir: bool, ignore_files: &[Gitignore]) -> bool {
+    for ignore_file in ignore_files.iter().rev() {
+        match ignore_file.matched(path, is_dir) {
+            Match::Ignore(_) => return true,
+            Match::Whitelist(_) => return false,
+            Match::None => {}
+        }
+    }
+    false
+}
@@ -0,0 +1,263 @@
+use crate::library::collation::fold;
+
+/// Score for every query character that is found in the text.
+const SCORE_MATCH: i64 = 16;
+/// Extra score for a character at the start of a word, like the "p" in "Karma Police" for "po".
+const BONUS_WORD_START: i64 = 8;
+/// Extra score for a character that directly follows the previous matched character.
+const BONUS_CONSECUTIVE: i64 = 6;
+/// Penalty for every character of the text that is skipped between two matched characters.
+const PENALTY_GAP: i64 = 1;
+/// Shorter words only match the start of a word. Spread out over the text,
+/// one or two characters would match nearly everything.
+pub(crate) const MIN_SUBSEQUENCE_LENGTH: usize = 3;
+
+/// Scores how well the query matches the text. Higher is better, `None` means it doesn't match.
+///
+/// Every word of the query has to match, but the order of the words does not matter.
+/// A word matches if its characters appear in the text in the same order,
+/// though not necessarily next to each other. Matches at the start of words,
+/// and characters that are next to each other, score higher.
+/// Longer words are also allowed to have a typo, at a lower score.
+/// Words shorter than [MIN_SUBSEQUENCE_LENGTH] have to match the start of a word of the text.
+/// Matching ignores case and accents. An empty query matches everything.
+pub fn fuzzy_match(query: &str, text: &str) -> Option<i64> {
+    let text: Vec<char> = fold(text).chars().collect();
+
+    fold(query)
+        .split_whitespace()
+        .map(|word| {
+            let word: Vec<char> = word.chars().collect();
+            if word.len() < MIN_SUBSEQUENCE_LENGTH {
+                return word_start_score(&word, &text);
+            }
+            subsequence_score(&word, &text).or_else(|| typo_score(&word, &text))
+        })
+        .sum()
+}
+
+/// The best score of the characters of the word appearing, in order, in the text.
+fn subsequence_score(word: &[char], text: &[char]) -> Option<i64> {
+    if word.is_empty() {
+        return Some(0);
+    }
+    if !is_subsequence(word, text) {
+        return None;
+    }
+
+    // `previous[j]` is the best score for matching the word up to the previous character,
+    // with that character at position `j` of the text.
+    let mut previous: Vec<Option<i64>> = vec![None; text.len()];
+    let mut current: Vec<Option<i64>> = vec![None; text.len()];
+
+    for (i, &word_char) in word.iter().enumerate() {
+        // Best of `previous[k] + k * PENALTY_GAP` for all `k` before `j - 1`,
+        // so the gap penalty to `j` can be applied afterwards.
+        let mut best_before: Option<i64> = None;
+
+        for (j, &text_char) in text.iter().enumerate() {
+            if j >= 2 {
+                if let Some(score) = previous[j - 2] {
+                    let score = score + (j as i64 - 2) * PENALTY_GAP;
+                    best_before = Some(best_before.map_or(score, |best| best.max(score)));
+                }
+            }
+
+            current[j] = if word_char != text_char {
+                None
+            } else {
+                let char_score = SCORE_MATCH + word_start_bonus(text, j);
+
+                if i == 0 {
+                    Some(char_score)
+                } else {
+                    let consecutive = if j >= 1 {
+                        previous[j - 1].map(|score| score + BONUS_CONSECUTIVE)
+                    } else {
+                        None
+                    };
+                    let with_gap = best_before.map(|score| score - (j as i64 - 1) * PENALTY_GAP);
+
+                    match (consecutive, with_gap) {
+                        (Some(a), Some(b)) => Some(a.max(b) + char_score),
+                        (Some(a), None) | (None, Some(a)) => Some(a + char_score),
+                        (None, None) => None,
+                    }
+                }
+            };
+        }
+
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous.into_iter().flatten().max()
+}
+
+pub(crate) fn is_subsequence(word: &[char], text: &[char]) -> bool {
+    let mut text = text.iter();
+    word.iter()
+        .all(|word_char| text.any(|text_char| text_char == word_char))
+}
+
+/// Score for the word appearing at the start of a word in the text,
+/// the same as [subsequence_score] gives a match like that.
+fn word_start_score(word: &[char], text: &[char]) -> Option<i64> {
+    let at_word_start = (0..text.len())
+        .filter(|&index| word_start_bonus(text, index) > 0)
+        .any(|index| text[index..].starts_with(word));
+    if !at_word_start {
+        return None;
+    }
+
+    let length = word.len() as i64;
+    Some(length * SCORE_MATCH + BONUS_WORD_START + (length - 1).max(0) * BONUS_CONSECUTIVE)
+}
+
+fn word_start_bonus(text: &[char], index: usize) -> i64 {
+    if index == 0 || !text[index - 1].is_alphanumeric() {
+        BONUS_WORD_START
+    } else {
+        0
+    }
+}
+
+/// Score for the word matching the start of a word in the text with a few typos.
+fn typo_score(word: &[char], text: &[char]) -> Option<i64> {
+    let allowed_typos = allowed_typos(word.len())?;
+
+    let typos = text
+        .split(|c| !c.is_alphanumeric())
+        .filter(|text_word| !text_word.is_empty())
+        .map(|text_word| typo_count(word, text_word))
+        .min()?;
+
+    if typos > allowed_typos {
+        return None;
+    }
+
+    // Half the score of a perfect match, so typos always end up below the real matches.
+    Some((word.len() - typos) as i64 * SCORE_MATCH / 2)
+}
+
+/// How many typos a word of this length may contain.
+/// Short words are not allowed any typos, because then almost everything would match.
+pub(crate) fn allowed_typos(word_length: usize) -> Option<usize> {
+    match word_length {
+        0..=3 => None,
+        4..=7 => Some(1),
+        _ => Some(2),
+    }
+}
+
+/// The amount of typos to get from the word to the start of the text word.
+pub(crate) fn typo_count(word: &[char], text_word: &[char]) -> usize {
+    // Also compare against the start of the text word, so partially typed words match.
+    // One longer or shorter, in case the typo is a missing or extra character.
+    (word.len().saturating_sub(1)..=word.len() + 1)
+        .map(|length| &text_word[..length.min(text_word.len())])
+        .map(|text_prefix| edit_distance(word, text_prefix))
+        .min()
+        .unwrap_or(word.len())
+}
+
+/// The amount of inserted, removed, changed or swapped characters to get from `a` to `b`.
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    // Only the last three rows are needed.
+    let mut before_previous: Vec<usize> = vec![0; b.len() + 1];
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current: Vec<usize> = vec![0; b.len() + 1];
+
+    for i in 1..=a.len() {
+        current[0] = i;
+
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            current[j] = (previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(previous[j - 1] + cost);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before_previous[j - 2] + 1);
+            }
+        }
+
+        std::mem::swap(&mut before_previous, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod test {
+    use crate::library::search::{edit_distance, fuzzy_match};
+    use test_log::test;
+
+    #[test]
+    fn fuzzy_match_matches_subsequences() {
+        assert!(fuzzy_match("", "Anything").is_some());
+        assert!(fuzzy_match("kpol", "Karma Police").is_some());
+        assert!(fuzzy_match("KARMA", "karma police").is_some());
+        assert!(fuzzy_match("bjork", "Björk").is_some());
+        assert!(fuzzy_match("Björk", "BJORK").is_some());
+        assert!(fuzzy_match("xyz", "Karma Police").is_none());
+        assert!(fuzzy_match("ecilop", "Karma Police").is_none());
+    }
+
+    #[test]
+    fn fuzzy_match_matches_short_words_at_word_starts() {
+        assert!(fuzzy_match("k", "Karma Police").is_some());
+        assert!(fuzzy_match("po", "Karma Police").is_some());
+        assert!(fuzzy_match("kp", "Karma Police").is_none());
+        assert!(fuzzy_match("ar", "Karma Police").is_none());
+        assert_eq!(
+            fuzzy_match("po", "Karma Police"),
+            fuzzy_match("po", "Police")
+        );
+    }
+
+    #[test]
+    fn fuzzy_match_ignores_word_order() {
+        assert!(fuzzy_match("police karma", "Karma Police").is_some());
+        assert!(fuzzy_match("police karma xyz", "Karma Police").is_none());
+    }
+
+    #[test]
+    fn fuzzy_match_allows_typos_in_longer_words() {
+        assert!(fuzzy_match("polise", "Karma Police").is_some());
+        assert!(fuzzy_match("polcie", "Karma Police").is_some());
+        assert!(fuzzy_match("karna", "Karma Police").is_some());
+        // Too many typos.
+        assert!(fuzzy_match("kurna", "Karma Police").is_none());
+        // Too short for a typo.
+        assert!(fuzzy_match("kxr", "Karma Police").is_none());
+    }
+
+    #[test]
+    fn fuzzy_match_ranks_better_matches_higher() {
+        let score = |query, text| fuzzy_match(query, text).unwrap();
+
+        // Consecutive characters over scattered ones.
+        assert!(score("pol", "Police") > score("pol", "Paper Tiger Bowl"));
+        // Word starts over the middle of a word.
+        assert!(score("pol", "Karma Police") > score("pol", "Napoleon"));
+        // Exact over typos.
+        assert!(score("police", "Karma Police") > score("polcie", "Karma Police"));
+    }
+
+    #[test]
+    fn edit_distance_counts_swaps_as_one() {
+        let distance = |a: &str, b: &str| {
+            edit_distance(
+                &a.chars().collect::<Vec<_>>(),
+                &b.chars().collect::<Vec<_>>(),
+            )
+        };
+
+        assert_eq!(distance("police", "police"), 0);
+        assert_eq!(distance("polcie", "police"), 1);
+        assert_eq!(distance("plice", "police"), 1);
+        assert_eq!(distance("kitten", "sitting"), 3);
+        assert_eq!(distance("", "abc"), 3);
+    }
+}
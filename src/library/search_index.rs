@@ -0,0 +1,421 @@
+use crate::library::collation::{fold, SortKey};
+use crate::library::query::{FieldPredicate, Query};
+use crate::library::search::{allowed_typos, MIN_SUBSEQUENCE_LENGTH};
+use crate::library::{Song, SongId};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+/// Finds the songs that can match a [Query], without looking at every song in the library.
+///
+/// The words and the characters in the text fields of the songs are indexed.
+/// A fuzzy query word can be spread out over several words of a field, and can contain typos,
+/// so it is looked up by its characters: only songs that have (nearly) all of them can match.
+/// Short words only match the start of a word, so they are looked up in the word index instead,
+/// which keeps the first keystrokes of a search from checking nearly every song.
+/// Phrases have to appear as they are, so the words they contain completely are looked up
+/// in the word index.
+/// The songs that are found this way are then checked against the full query.
+#[derive(Debug, Default)]
+pub(crate) struct SearchIndex {
+    /// Songs by the folded words in their text fields.
+    words: BTreeMap<String, HashSet<SongId>>,
+    /// Songs by the folded characters in their text fields.
+    chars: HashMap<char, HashSet<SongId>>,
+    songs: HashMap<SongId, IndexedSong>,
+    /// All songs, in the order they are shown when nothing is searched for.
+    in_order: BTreeSet<(SortKey, SongId)>,
+}
+
+#[derive(Debug)]
+struct IndexedSong {
+    words: Vec<String>,
+    chars: Vec<char>,
+    sort_key: SortKey,
+}
+
+impl SearchIndex {
+    pub fn insert(&mut self, id: SongId, song: &Song) {
+        self.remove(id);
+
+        let mut words: Vec<String> = text_fields(song).flat_map(split_words).collect();
+        words.sort_unstable();
+        words.dedup();
+
+        let mut chars: Vec<char> = text_fields(song)
+            .flat_map(|text| fold(text).chars().collect::<Vec<_>>())
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        chars.sort_unstable();
+        chars.dedup();
+
+        for word in &words {
+            self.words.entry(word.clone()).or_default().insert(id);
+        }
+        for &c in &chars {
+            self.chars.entry(c).or_default().insert(id);
+        }
+
+        let sort_key = SortKey::new(&song.title);
+        self.in_order.insert((sort_key.clone(), id));
+        self.songs.insert(
+            id,
+            IndexedSong {
+                words,
+                chars,
+                sort_key,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, id: SongId) {
+        if let Some(song) = self.songs.remove(&id) {
+            for word in song.words {
+                if let Some(songs) = self.words.get_mut(&word) {
+                    songs.remove(&id);
+                    if songs.is_empty() {
+                        self.words.remove(&word);
+                    }
+                }
+            }
+            for c in song.chars {
+                if let Some(songs) = self.chars.get_mut(&c) {
+                    songs.remove(&id);
+                    if songs.is_empty() {
+                        self.chars.remove(&c);
+                    }
+                }
+            }
+            self.in_order.remove(&(song.sort_key, id));
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// The songs matching the query, best matches first.
+    /// Equally good matches are in the same order as when nothing is searched for.
+    pub fn search(&self, query: &Query, songs: &HashMap<SongId, Song>) -> Vec<SongId> {
+        if *query == Query::All {
+            return self.in_order.iter().map(|(_, id)| *id).collect();
+        }
+
+        let mut results: Vec<(i64, &SortKey, SongId)> = match self.candidates(query) {
+            Some(candidates) => candidates
+                .into_iter()
+                .filter_map(|id| {
+                    let score = query.score(songs.get(&id)?)?;
+                    Some((score, &self.songs.get(&id)?.sort_key, id))
+                })
+                .collect(),
+            None => self
+                .in_order
+                .iter()
+                .filter_map(|(sort_key, id)| {
+                    let score = query.score(songs.get(id)?)?;
+                    Some((score, sort_key, *id))
+                })
+                .collect(),
+        };
+
+        results.sort_unstable_by(
+            |(first_score, first_key, first_id), (second_score, second_key, second_id)| {
+                (Reverse(first_score), first_key, first_id).cmp(&(
+                    Reverse(second_score),
+                    second_key,
+                    second_id,
+                ))
+            },
+        );
+        results.into_iter().map(|(_, _, id)| id).collect()
+    }
+
+    /// The songs that may match the query. They still have to be checked against it.
+    /// `None` if the index can't tell, and every song has to be checked.
+    fn candidates(&self, query: &Query) -> Option<HashSet<SongId>> {
+        match query {
+            Query::All | Query::Not(_) => None,
+            Query::Word(word) => self.fuzzy_candidates(word),
+            Query::Phrase(text) | Query::Field(FieldPredicate::Text { text, .. }) => {
+                self.phrase_candidates(text)
+            }
+            Query::Field(_) => None,
+            Query::And(queries) => queries
+                .iter()
+                .filter_map(|query| self.candidates(query))
+                .reduce(|first, second| first.intersection(&second).copied().collect()),
+            Query::Or(queries) => {
+                let mut candidates = HashSet::new();
+                for query in queries {
+                    candidates.extend(self.candidates(query)?);
+                }
+                Some(candidates)
+            }
+        }
+    }
+
+    /// The songs that may contain the words, the way [fuzzy_match](crate::library::search::fuzzy_match)
+    /// matches them: as a subsequence of a field, or with a few typos.
+    fn fuzzy_candidates(&self, text: &str) -> Option<HashSet<SongId>> {
+        fold(text)
+            .split_whitespace()
+            .map(|word| {
+                let mut chars: Vec<char> = word.chars().collect();
+                if chars.len() < MIN_SUBSEQUENCE_LENGTH {
+                    // A word with other characters can start in one word of a song
+                    // and continue in the next, so the word index doesn't help.
+                    return chars
+                        .iter()
+                        .all(|c| c.is_alphanumeric())
+                        .then(|| self.songs_with_word_starting_with(word));
+                }
+
+                let allowed_typos = allowed_typos(chars.len()).unwrap_or(0);
+                chars.sort_unstable();
+                chars.dedup();
+
+                // A subsequence has all characters of the word. Every typo can take away at most
+                // one of the characters, by removing or replacing it.
+                let needed = chars.len().saturating_sub(allowed_typos);
+                if needed == 0 {
+                    return None;
+                }
+
+                let mut counts: HashMap<SongId, usize> = HashMap::new();
+                for c in &chars {
+                    for &id in self.chars.get(c).into_iter().flatten() {
+                        *counts.entry(id).or_default() += 1;
+                    }
+                }
+                let candidates: HashSet<SongId> = counts
+                    .into_iter()
+                    .filter(|&(_, count)| count >= needed)
+                    .map(|(id, _)| id)
+                    .collect();
+                Some(candidates)
+            })
+            .reduce(|first, second| match (first, second) {
+                (Some(first), Some(second)) => Some(first.intersection(&second).copied().collect()),
+                (first, None) => first,
+                (None, second) => second,
+            })
+            .flatten()
+    }
+
+    fn songs_with_word_starting_with(&self, start: &str) -> HashSet<SongId> {
+        self.words
+            .range(start.to_string()..)
+            .take_while(|(word, _)| word.starts_with(start))
+            .flat_map(|(_, songs)| songs.iter().copied())
+            .collect()
+    }
+
+    /// The songs that may contain the text as it is.
+    /// Parts of the text between two separators are whole words of the song, and a part after
+    /// a separator starts a word. Other parts can be inside a word, so those are looked up
+    /// by their characters.
+    fn phrase_candidates(&self, text: &str) -> Option<HashSet<SongId>> {
+        let folded = fold(text);
+        let parts: Vec<&str> = folded.split(|c: char| !c.is_alphanumeric()).collect();
+        let last = parts.len() - 1;
+
+        parts
+            .iter()
+            .enumerate()
+            .filter(|(_, part)| !part.is_empty())
+            .map(|(index, part)| match (index > 0, index < last) {
+                (true, true) => self.words.get(*part).cloned().unwrap_or_default(),
+                (true, false) => self.songs_with_word_starting_with(part),
+                _ => part
+                    .chars()
+                    .map(|c| self.chars.get(&c).cloned().unwrap_or_default())
+                    .reduce(|first, second| first.intersection(&second).copied().collect())
+                    .unwrap_or_default(),
+            })
+            .reduce(|first, second| first.intersection(&second).copied().collect())
+    }
+}
+
+fn text_fields(song: &Song) -> impl Iterator<Item = &str> {
+    [
+        Some(song.title.as_str()),
+        song.artist.as_deref(),
+        song.album_artist.as_deref(),
+        song.album.as_deref(),
+        song.genre.as_deref(),
+    ]
+    .into_iter()
+    .flatten()
+}
+
+/// Folded words, the way the search compares them.
+fn split_words(text: &str) -> impl Iterator<Item = String> {
+    fold(text)
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_string())
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::library::collation::SortKey;
+    use crate::library::query::Query;
+    use crate::library::search_index::SearchIndex;
+    use crate::library::{Song, SongId};
+    use std::cmp::Reverse;
+    use std::collections::HashMap;
+    use test_log::test;
+
+    fn songs() -> HashMap<SongId, Song> {
+        let song = |title: &str, artist: &str, album: &str, year| Song {
+            title: title.to_string(),
+            artist: Some(artist.to_string()),
+            album: Some(album.to_string()),
+            year: Some(year),
+            ..Default::default()
+        };
+
+        [
+            song("Karma Police", "Radiohead", "OK Computer", 1997),
+            song("Paranoid Android", "Radiohead", "OK Computer", 1997),
+            song("Everything In Its Right Place", "Radiohead", "Kid A", 2000),
+            song("Police On My Back", "The Clash", "Sandinista!", 1980),
+            song("Napoleon", "Weezer", "Pinkerton", 1996),
+            song("Teardrop", "Massive Attack", "Mezzanine", 1998),
+            song("Angel", "Massive Attack", "Mezzanine", 1998),
+            song("Jóga", "Björk", "Homogenic", 1997),
+            song("Track 10", "Various", "Café del Mar", 1994),
+            song("Track 2", "Various", "Café del Mar", 1994),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, song)| (SongId(i as u64), song))
+        .collect()
+    }
+
+    fn index(songs: &HashMap<SongId, Song>) -> SearchIndex {
+        let mut index = SearchIndex::default();
+        for (&id, song) in songs {
+            index.insert(id, song);
+        }
+        index
+    }
+
+    /// Checks every song against the query, without using the index.
+    fn search_every_song(query: &Query, songs: &HashMap<SongId, Song>) -> Vec<SongId> {
+        let mut results: Vec<(Reverse<i64>, SortKey, SongId)> = songs
+            .iter()
+            .filter_map(|(&id, song)| {
+                Some((Reverse(query.score(song)?), SortKey::new(&song.title), id))
+            })
+            .collect();
+        results.sort();
+        results.into_iter().map(|(_, _, id)| id).collect()
+    }
+
+    #[test]
+    fn search_index_finds_the_same_songs_as_checking_every_song() {
+        let songs = songs();
+        let index = index(&songs);
+
+        for query in [
+            "",
+            "police",
+            "pol",
+            "polcie",
+            "kpol",
+            "kp",
+            "rh",
+            "okc",
+            "cafedm",
+            "mattack",
+            "tdrop",
+            "pa",
+            "police kp",
+            "\"del mar\"",
+            "\"rma pol\"",
+            "album:\"fé del\"",
+            "radiohead police",
+            "\"ok computer\"",
+            "artist:massive",
+            "album:\"ok comp\"",
+            "year:>1997",
+            "-radiohead",
+            "police | angel",
+            "massive -angel",
+            "xyz",
+            "bjork",
+            "BJÖRK joga",
+            "cafe",
+            "album:café",
+            "p",
+            "a",
+            "mé",
+            "10",
+            "k police",
+            "!",
+        ] {
+            let query = Query::parse(query).unwrap();
+            assert_eq!(
+                index.search(&query, &songs),
+                search_every_song(&query, &songs),
+                "{:?}",
+                query
+            );
+        }
+    }
+
+    #[test]
+    fn search_index_looks_up_short_words_by_word_start() {
+        let songs = songs();
+        let index = index(&songs);
+
+        // Nearly every song has an "a", but only a few have a word starting with it.
+        let candidates = index.candidates(&Query::parse("a").unwrap()).unwrap();
+        assert_eq!(candidates.len(), 4);
+        let candidates = index.candidates(&Query::parse("ma").unwrap()).unwrap();
+        assert_eq!(candidates.len(), 4);
+    }
+
+    #[test]
+    fn search_index_ignores_accents_and_sorts_naturally() {
+        let songs = songs();
+        let index = index(&songs);
+
+        assert_eq!(
+            index.search(&Query::parse("bjork").unwrap(), &songs),
+            vec![SongId(7)]
+        );
+        assert_eq!(
+            index.search(&Query::parse("album:cafe").unwrap(), &songs),
+            vec![SongId(9), SongId(8)]
+        );
+    }
+
+    #[test]
+    fn search_index_forgets_removed_and_changed_songs() {
+        let mut songs = songs();
+        let mut index = index(&songs);
+        let police = Query::parse("police").unwrap();
+
+        index.remove(SongId(0));
+        songs.remove(&SongId(0));
+        assert_eq!(index.search(&police, &songs), vec![SongId(3)]);
+
+        let changed = Song {
+            title: "Something Else".to_string(),
+            ..songs[&SongId(3)].clone()
+        };
+        index.insert(SongId(3), &changed);
+        songs.insert(SongId(3), changed);
+        assert!(index.search(&police, &songs).is_empty());
+        assert_eq!(index.search(&Query::All, &songs).len(), songs.len());
+
+        index.clear();
+        assert!(index.search(&Query::All, &songs).is_empty());
+        assert!(index.words.is_empty());
+        assert!(index.chars.is_empty());
+    }
+}
@@ -0,0 +1,391 @@
+use crate::library::{ListEntryId, Playlist, RepeatMode, SongId};
+use rand::seq::SliceRandom;
+use std::collections::HashSet;
+
+/// A shuffled order to play the entries of a [Playlist] in,
+/// without changing the order of the playlist itself.
+///
+/// Every entry is played once before the order is shuffled again.
+/// The order is kept up to date with the entries of the playlist:
+/// entries that were added are shuffled in with the entries that haven't been played yet.
+#[derive(Debug, Default)]
+pub struct ShuffleOrder {
+    order: Vec<ListEntryId>,
+    /// Position of the current entry in the order.
+    /// The entries up to and including it have been played in this cycle.
+    current: Option<usize>,
+}
+
+impl ShuffleOrder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Forgets the order, for example because another playlist is played.
+    pub fn clear(&mut self) {
+        self.order.clear();
+        self.current = None;
+    }
+
+    /// The entries that have been played in this cycle, in the order they were played.
+    /// The current entry is the last one.
+    pub fn played(&self) -> &[ListEntryId] {
+        let played_count = self.current.map_or(0, |current| current + 1);
+        &self.order[..played_count]
+    }
+
+    /// The entry to play after the current entry, like [Playlist::get_next_entry].
+    /// Without a current entry, a new cycle is started.
+    /// When the current entry was removed from the playlist, the order continues where it was.
+    pub fn get_next_entry(
+        &mut self,
+        playlist: &Playlist,
+        current_entry: Option<ListEntryId>,
+        repeat: RepeatMode,
+    ) -> Option<(ListEntryId, SongId, usize)> {
+        self.update(playlist);
+
+        let (current, repeat) = match current_entry.map(|entry_id| self.move_to(entry_id)) {
+            Some(Some(current)) => (current, repeat),
+            // The current entry was removed, continue after the entry that was played before it.
+            Some(None) => match self.current {
+                Some(current) => (current, repeat.when_skipping()),
+                None => return self.go_to(playlist, 0),
+            },
+            None => {
+                self.reshuffle(None);
+                return self.go_to(playlist, 0);
+            }
+        };
+
+        match repeat {
+            RepeatMode::One => self.entry_at(playlist, current),
+            _ if current + 1 < self.order.len() => self.go_to(playlist, current + 1),
+            RepeatMode::All => {
+                self.reshuffle(current_entry);
+                self.go_to(playlist, 0)
+            }
+            RepeatMode::Off => None,
+        }
+    }
+
+    /// The entry that was played before the current entry in the shuffled order,
+    /// like [Playlist::get_previous_entry].
+    pub fn get_previous_entry(
+        &mut self,
+        playlist: &Playlist,
+        current_entry: Option<ListEntryId>,
+        repeat: RepeatMode,
+    ) -> Option<(ListEntryId, SongId, usize)> {
+        self.update(playlist);
+
+        let current = match current_entry.map(|entry_id| self.move_to(entry_id)) {
+            Some(Some(current)) => current,
+            // The current entry was removed, the entry that was played before it is the previous one.
+            Some(None) => match (self.current, repeat) {
+                (Some(current), _) => return self.go_to(playlist, current),
+                (None, RepeatMode::Off) => return None,
+                (None, _) => return self.go_to(playlist, self.order.len().checked_sub(1)?),
+            },
+            None => {
+                self.reshuffle(None);
+                return self.go_to(playlist, self.order.len().checked_sub(1)?);
+            }
+        };
+
+        match repeat {
+            RepeatMode::One => self.entry_at(playlist, current),
+            _ if current > 0 => self.go_to(playlist, current - 1),
+            RepeatMode::All => self.go_to(playlist, self.order.len() - 1),
+            RepeatMode::Off => None,
+        }
+    }
+
+    /// Makes the position in the order point at the entry, and returns the position.
+    /// An entry that hasn't been played yet in this cycle, like one that was double-clicked,
+    /// is moved right after the entries that have been played, so it isn't played again.
+    fn move_to(&mut self, entry_id: ListEntryId) -> Option<usize> {
+        let index = self.order.iter().position(|&id| id == entry_id)?;
+
+        let current = match self.current {
+            Some(current) if index > current + 1 => {
+                self.order.remove(index);
+                self.order.insert(current + 1, entry_id);
+                current + 1
+            }
+            // The next entry, or walking back through the entries that have been played.
+            Some(_) => index,
+            None => {
+                self.order.remove(index);
+                self.order.insert(0, entry_id);
+                0
+            }
+        };
+        self.current = Some(current);
+
+        Some(current)
+    }
+
+    /// Takes over entries that were added to or removed from the playlist.
+    /// The entries that have been played keep their place, the others are shuffled again.
+    fn update(&mut self, playlist: &Playlist) {
+        let entries: HashSet<ListEntryId> =
+            playlist.song_ids().map(|&(entry_id, _)| entry_id).collect();
+        if entries.len() == self.order.len() && self.order.iter().all(|id| entries.contains(id)) {
+            return;
+        }
+
+        let played_count = self.current.map_or(0, |current| current + 1);
+        let played: Vec<ListEntryId> = self.order[..played_count]
+            .iter()
+            .copied()
+            .filter(|id| entries.contains(id))
+            .collect();
+        let played_set: HashSet<ListEntryId> = played.iter().copied().collect();
+
+        let mut not_played: Vec<ListEntryId> = playlist
+            .song_ids()
+            .map(|&(entry_id, _)| entry_id)
+            .filter(|id| !played_set.contains(id))
+            .collect();
+        not_played.shuffle(&mut rand::thread_rng());
+
+        // When the current entry was removed, the one played before it becomes the current one,
+        // so the next entry is still the first one that hasn't been played.
+        self.current = played.len().checked_sub(1);
+        self.order = played;
+        self.order.append(&mut not_played);
+    }
+
+    /// Starts a new cycle through all entries.
+    /// The last played entry is not put first, so it isn't played twice in a row.
+    fn reshuffle(&mut self, last_played: Option<ListEntryId>) {
+        self.order.shuffle(&mut rand::thread_rng());
+        self.current = None;
+
+        if self.order.len() > 1 && last_played == self.order.first().copied() {
+            let last = self.order.len() - 1;
+            self.order.swap(0, last);
+        }
+    }
+
+    /// Makes the entry at the position the current one, since it is about to be played.
+    fn go_to(
+        &mut self,
+        playlist: &Playlist,
+        position: usize,
+    ) -> Option<(ListEntryId, SongId, usize)> {
+        let entry = self.entry_at(playlist, position)?;
+        self.current = Some(position);
+        Some(entry)
+    }
+
+    fn entry_at(
+        &self,
+        playlist: &Playlist,
+        position: usize,
+    ) -> Option<(ListEntryId, SongId, usize)> {
+        let entry_id = *self.order.get(position)?;
+        let index = playlist.get_song_index(entry_id)?;
+        let &(_, song_id) = playlist.get_at_index(index)?;
+
+        Some((entry_id, song_id, index))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::library::shuffle::ShuffleOrder;
+    use crate::library::{ListEntryId, Playlist, RepeatMode, SongId};
+    use std::collections::HashSet;
+    use test_log::test;
+
+    fn playlist_of_songs(count: u64) -> Playlist {
+        let mut playlist = Playlist::new();
+        playlist.add_songs((0..count).map(SongId).collect());
+        playlist
+    }
+
+    /// Follows the order from the start until it stops, or for `limit` entries.
+    fn play_through(
+        shuffle: &mut ShuffleOrder,
+        playlist: &Playlist,
+        repeat: RepeatMode,
+        limit: usize,
+    ) -> Vec<ListEntryId> {
+        let mut played = Vec::new();
+        let mut current = None;
+
+        while played.len() < limit {
+            match shuffle.get_next_entry(playlist, current, repeat) {
+                Some((entry_id, _, _)) => {
+                    played.push(entry_id);
+                    current = Some(entry_id);
+                }
+                None => break,
+            }
+        }
+
+        played
+    }
+
+    #[test]
+    fn shuffle_plays_every_entry_once_per_cycle() {
+        let playlist = playlist_of_songs(20);
+        let mut shuffle = ShuffleOrder::new();
+
+        let played = play_through(&mut shuffle, &playlist, RepeatMode::Off, 100);
+        assert_eq!(played.len(), 20);
+        assert_eq!(played.iter().collect::<HashSet<_>>().len(), 20);
+
+        let played = play_through(&mut shuffle, &playlist, RepeatMode::All, 40);
+        let (first_cycle, second_cycle) = played.split_at(20);
+        assert_eq!(first_cycle.iter().collect::<HashSet<_>>().len(), 20);
+        assert_eq!(second_cycle.iter().collect::<HashSet<_>>().len(), 20);
+        // The next cycle doesn't start with the song that was just played.
+        assert_ne!(first_cycle.last(), second_cycle.first());
+
+        // The playlist itself is not reordered.
+        assert_eq!(
+            playlist.get_song_ids(),
+            (0..20).map(SongId).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn shuffle_previous_walks_back_through_the_played_entries() {
+        let playlist = playlist_of_songs(10);
+        let mut shuffle = ShuffleOrder::new();
+        let played = play_through(&mut shuffle, &playlist, RepeatMode::Off, 5);
+
+        let mut current = played[4];
+        for &expected in played[..4].iter().rev() {
+            let (previous, _, _) = shuffle
+                .get_previous_entry(&playlist, Some(current), RepeatMode::Off)
+                .unwrap();
+            assert_eq!(previous, expected);
+            current = previous;
+        }
+        assert_eq!(
+            shuffle.get_previous_entry(&playlist, Some(current), RepeatMode::Off),
+            None
+        );
+
+        // Going forward again follows the same order.
+        let (next, _, _) = shuffle
+            .get_next_entry(&playlist, Some(current), RepeatMode::Off)
+            .unwrap();
+        assert_eq!(next, played[1]);
+    }
+
+    #[test]
+    fn shuffle_played_has_the_entries_of_this_cycle() {
+        let mut playlist = playlist_of_songs(10);
+        let mut shuffle = ShuffleOrder::new();
+        assert!(shuffle.played().is_empty());
+
+        let played = play_through(&mut shuffle, &playlist, RepeatMode::Off, 4);
+        assert_eq!(shuffle.played(), played.as_slice());
+
+        // Removing the played entries, like the infinite playlist does,
+        // leaves the rest of the cycle as it was.
+        for &entry_id in &played[..3] {
+            playlist.remove_song(entry_id);
+        }
+        let mut rest = Vec::new();
+        let mut current = Some(played[3]);
+        while let Some((entry_id, _, _)) =
+            shuffle.get_next_entry(&playlist, current, RepeatMode::Off)
+        {
+            rest.push(entry_id);
+            current = Some(entry_id);
+        }
+        assert_eq!(shuffle.played().len(), 7);
+        assert_eq!(rest.len(), 6);
+        assert!(!rest.iter().any(|entry_id| played.contains(entry_id)));
+    }
+
+    #[test]
+    fn shuffle_repeats_one() {
+        let playlist = playlist_of_songs(5);
+        let mut shuffle = ShuffleOrder::new();
+        let (first, _, _) = shuffle
+            .get_next_entry(&playlist, None, RepeatMode::One)
+            .unwrap();
+
+        let next = shuffle.get_next_entry(&playlist, Some(first), RepeatMode::One);
+        let previous = shuffle.get_previous_entry(&playlist, Some(first), RepeatMode::One);
+        assert_eq!(next.map(|(entry_id, _, _)| entry_id), Some(first));
+        assert_eq!(previous.map(|(entry_id, _, _)| entry_id), Some(first));
+    }
+
+    #[test]
+    fn shuffle_takes_over_added_and_removed_entries() {
+        let mut playlist = playlist_of_songs(10);
+        let mut shuffle = ShuffleOrder::new();
+        let mut played = play_through(&mut shuffle, &playlist, RepeatMode::Off, 4);
+        let current = *played.last().unwrap();
+
+        // Remove one that was played, and one that wasn't.
+        playlist.remove_song(played[0]);
+        let not_played = playlist
+            .song_ids()
+            .map(|&(entry_id, _)| entry_id)
+            .find(|entry_id| !played.contains(entry_id))
+            .unwrap();
+        playlist.remove_song(not_played);
+        playlist.add_songs(vec![SongId(10), SongId(11)]);
+
+        // The rest of the cycle has the entries that were not played yet, including the new ones.
+        let mut current = Some(current);
+        while let Some((entry_id, _, index)) =
+            shuffle.get_next_entry(&playlist, current, RepeatMode::Off)
+        {
+            assert_eq!(playlist.get_at_index(index).unwrap().0, entry_id);
+            played.push(entry_id);
+            current = Some(entry_id);
+        }
+
+        assert_eq!(played.len(), 11);
+        assert!(!played.contains(&not_played));
+        let expected: HashSet<ListEntryId> = playlist
+            .song_ids()
+            .map(|&(entry_id, _)| entry_id)
+            .chain([played[0]])
+            .collect();
+        assert_eq!(played.into_iter().collect::<HashSet<_>>(), expected);
+    }
+
+    #[test]
+    fn shuffle_continues_after_an_entry_that_was_chosen() {
+        let playlist = playlist_of_songs(6);
+        let mut shuffle = ShuffleOrder::new();
+        let played = play_through(&mut shuffle, &playlist, RepeatMode::Off, 2);
+
+        // Like double-clicking an entry that wasn't played yet.
+        let chosen = playlist
+            .song_ids()
+            .map(|&(entry_id, _)| entry_id)
+            .find(|entry_id| !played.contains(entry_id))
+            .unwrap();
+
+        let (previous, _, _) = shuffle
+            .get_previous_entry(&playlist, Some(chosen), RepeatMode::Off)
+            .unwrap();
+        assert_eq!(previous, played[1]);
+
+        let mut rest = Vec::new();
+        let mut current = Some(chosen);
+        while let Some((entry_id, _, _)) =
+            shuffle.get_next_entry(&playlist, current, RepeatMode::Off)
+        {
+            rest.push(entry_id);
+            current = Some(entry_id);
+        }
+
+        // The chosen entry isn't played again, and neither are the ones before it.
+        assert_eq!(rest.len(), 3);
+        assert!(!rest.contains(&chosen));
+        assert!(!rest.iter().any(|entry_id| played.contains(entry_id)));
+    }
+}
@@ -0,0 +1,118 @@
+use anyhow::Result;
+use std::fs::File;
+use std::path::Path;
+use std::time::Duration;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{MetadataOptions, MetadataRevision, StandardTagKey};
+use symphonia::core::probe::Hint;
+
+/// Metadata read from the tags embedded in a song file.
+/// Any value that is not present in the file is `None`.
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+pub struct SongTags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album_artist: Option<String>,
+    pub album: Option<String>,
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub year: Option<u32>,
+    pub genre: Option<String>,
+    pub duration: Option<Duration>,
+}
+
+/// Reads the embedded tags (Vorbis comments, ID3v2, FLAC and MP4 atoms) from a song file.
+/// Fails if the file cannot be opened, or if the format is not recognized.
+pub fn read_tags(path: &Path) -> Result<SongTags> {
+    let file = File::open(path)?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(extension);
+    }
+
+    let mut probed = symphonia::default::get_probe().format(
+        &hint,
+        stream,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+
+    let mut tags = SongTags::default();
+
+    // Tags in front of the container (like ID3v2 in an mp3 file) are found while probing.
+    if let Some(metadata) = probed.metadata.get() {
+        if let Some(revision) = metadata.current() {
+            tags.apply_revision(revision);
+        }
+    }
+    // Tags inside the container take precedence.
+    if let Some(revision) = probed.format.metadata().current() {
+        tags.apply_revision(revision);
+    }
+
+    if let Some(track) = probed.format.default_track() {
+        let params = &track.codec_params;
+        tags.duration = match (params.time_base, params.n_frames) {
+            (Some(time_base), Some(frames)) => {
+                let time = time_base.calc_time(frames);
+                Some(Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac))
+            }
+            _ => None,
+        };
+    }
+
+    Ok(tags)
+}
+
+impl SongTags {
+    fn apply_revision(&mut self, revision: &MetadataRevision) {
+        for tag in revision.tags() {
+            let key = match tag.std_key {
+                Some(key) => key,
+                None => continue,
+            };
+
+            let value = tag.value.to_string().trim().to_owned();
+            if value.is_empty() {
+                continue;
+            }
+
+            match key {
+                StandardTagKey::TrackTitle => self.title = Some(value),
+                StandardTagKey::Artist => self.artist = Some(value),
+                StandardTagKey::AlbumArtist => self.album_artist = Some(value),
+                StandardTagKey::Album => self.album = Some(value),
+                StandardTagKey::TrackNumber => self.track_number = parse_leading_number(&value),
+                StandardTagKey::DiscNumber => self.disc_number = parse_leading_number(&value),
+                StandardTagKey::Date => self.year = parse_leading_number(&value),
+                StandardTagKey::Genre => self.genre = Some(value),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Track numbers are often stored as "3/12", and dates as "2021-05-01".
+/// We are only interested in the first number.
+fn parse_leading_number(value: &str) -> Option<u32> {
+    let digits: String = value.chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::library::tags::parse_leading_number;
+    use test_log::test;
+
+    #[test]
+    fn parse_leading_number_ignores_suffixes() {
+        assert_eq!(parse_leading_number("3"), Some(3));
+        assert_eq!(parse_leading_number("03/12"), Some(3));
+        assert_eq!(parse_leading_number("2021-05-01"), Some(2021));
+        assert_eq!(parse_leading_number("unknown"), None);
+        assert_eq!(parse_leading_number(""), None);
+    }
+}
@@ -0,0 +1,130 @@
+use crate::library::{Library, LibraryRoot, RescanSummary, ScanOptions};
+use anyhow::Result;
+use log::warn;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How long the directory has to be quiet before the collected changes are applied.
+/// Copying a whole album causes a burst of events, which we want to handle in one go.
+pub const DEBOUNCE_DURATION: Duration = Duration::from_millis(500);
+
+/// Watches the library roots for files that are added, changed, renamed or removed.
+pub struct LibraryWatcher {
+    /// Stops watching when dropped.
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+    roots: Vec<LibraryRoot>,
+    options: ScanOptions,
+    /// Paths that changed since the changes were last applied.
+    changed_paths: HashSet<PathBuf>,
+    last_event: Instant,
+    /// Whether `on_event` was called for changes that haven't been applied yet.
+    /// Until they are, there is no need to call it again.
+    woken: Arc<AtomicBool>,
+}
+
+impl LibraryWatcher {
+    /// Starts watching the enabled roots and everything below them.
+    /// Roots that cannot be watched are skipped with a warning.
+    /// `on_event` is called (from another thread) when something changes after a quiet period,
+    /// which can be used to wake up the UI so that it calls [apply_changes](Self::apply_changes).
+    /// It is not called again until those changes are applied, so while there are
+    /// [pending changes](Self::has_pending_changes), [apply_changes](Self::apply_changes)
+    /// has to be called again after [DEBOUNCE_DURATION].
+    pub fn new<F>(roots: Vec<LibraryRoot>, options: ScanOptions, on_event: F) -> Result<Self>
+    where
+        F: Fn() + Send + 'static,
+    {
+        let (sender, events) = channel();
+        let woken = Arc::new(AtomicBool::new(false));
+        let woken_by_watcher = woken.clone();
+
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                if let Ok(event) = &event {
+                    if !changes_library(event) {
+                        return;
+                    }
+                }
+                // The receiver is gone when the watcher is dropped, nothing left to do then.
+                if sender.send(event).is_ok() && !woken_by_watcher.swap(true, Ordering::AcqRel) {
+                    on_event();
+                }
+            })?;
+
+        for root in roots.iter().filter(|root| root.enabled) {
+            if let Err(e) = watcher.watch(&root.path, RecursiveMode::Recursive) {
+                warn!(
+                    "Could not watch library directory '{}': {}",
+                    root.path.display(),
+                    e
+                );
+            }
+        }
+
+        Ok(Self {
+            _watcher: watcher,
+            events,
+            roots,
+            options,
+            changed_paths: HashSet::new(),
+            last_event: Instant::now(),
+            woken,
+        })
+    }
+
+    /// Whether there are changes that will be applied once the directory has been quiet
+    /// for [DEBOUNCE_DURATION].
+    pub fn has_pending_changes(&self) -> bool {
+        !self.changed_paths.is_empty()
+    }
+
+    /// Collects the events that came in since the last call.
+    /// Once no new events arrived for [DEBOUNCE_DURATION], the changes are applied to the library.
+    /// Returns `None` if nothing was applied.
+    pub fn apply_changes(&mut self, library: &mut Library) -> Option<RescanSummary> {
+        self.receive_events();
+
+        if self.changed_paths.is_empty() || self.last_event.elapsed() < DEBOUNCE_DURATION {
+            return None;
+        }
+
+        let paths: Vec<PathBuf> = self.changed_paths.drain().collect();
+        let summary = library.update_paths(&paths, &self.roots, &self.options);
+
+        // The next change should wake up the UI again. Events that came in just before
+        // that are picked up here, and are then pending changes.
+        self.woken.store(false, Ordering::Release);
+        self.receive_events();
+
+        Some(summary)
+    }
+
+    fn receive_events(&mut self) {
+        loop {
+            match self.events.try_recv() {
+                Ok(Ok(event)) => self.add_event(event),
+                Ok(Err(e)) => warn!("Error while watching the library directory: {}", e),
+                Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => break,
+            }
+        }
+    }
+
+    /// For the events that [change the library](changes_library), it doesn't matter
+    /// what happened exactly. Once things are quiet, we look at what is there and
+    /// update the library to match. A rename is then seen as a removed and an added path.
+    fn add_event(&mut self, event: notify::Event) {
+        self.changed_paths.extend(event.paths);
+        self.last_event = Instant::now();
+    }
+}
+
+/// Reading files does not change anything.
+fn changes_library(event: &notify::Event) -> bool {
+    !matches!(event.kind, EventKind::Access(_))
+}
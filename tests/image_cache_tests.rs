@@ -17,6 +17,7 @@ fn test_load_image_from_song() {
     let id = library.add_song(Song {
         title: "".to_string(),
         path: Default::default(),
+        ..Default::default()
     });
 
     let ctx = Context::default();
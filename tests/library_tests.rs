@@ -1,30 +1,1129 @@
 use anyhow::Result;
+use simple_music_lib::config::Config;
 use simple_music_lib::library;
-use simple_music_lib::library::Song;
-use std::path::PathBuf;
+use simple_music_lib::library::background_scan::BackgroundScan;
+use simple_music_lib::library::cache;
+use simple_music_lib::library::playlist_file::{self, PathStyle};
+use simple_music_lib::library::query::Query;
+use simple_music_lib::library::watcher::LibraryWatcher;
+use simple_music_lib::library::{
+    FileStamp, Library, LibraryRoot, Playlist, ScanOptions, Song, SongId,
+};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
 use test_log::test;
 
 const TEST_LIBRARY_DIRECTORY: &str = "test_assets/test_library";
+const MIXED_FORMATS_DIRECTORY: &str = "test_assets/mixed_formats";
+const IGNORE_RULES_DIRECTORY: &str = "test_assets/ignore_rules";
 
 #[test]
 fn test_scan_directory() -> Result<()> {
-    let files = library::scan_directory_for_songs(TEST_LIBRARY_DIRECTORY)?;
+    let files = library::scan_directory_for_songs(
+        &[LibraryRoot::new(TEST_LIBRARY_DIRECTORY)],
+        &Config::default().scan_options(),
+    )?;
 
     assert_eq!(files.len(), 2);
 
-    let noise_song = Song {
-        title: "noise".to_string(),
-        path: PathBuf::from(TEST_LIBRARY_DIRECTORY).join("noise.ogg"),
+    let noise_path = PathBuf::from(TEST_LIBRARY_DIRECTORY).join("noise.ogg");
+    assert!(files.iter().any(|song| song.path == noise_path));
+
+    let more_noise_path = PathBuf::from(TEST_LIBRARY_DIRECTORY)
+        .join("some_folder")
+        .join("more_noise.ogg");
+    assert!(files.iter().any(|song| song.path == more_noise_path));
+
+    Ok(())
+}
+
+#[test]
+fn test_scan_directory_reads_tags() -> Result<()> {
+    let files = library::scan_directory_for_songs(
+        &[LibraryRoot::new(TEST_LIBRARY_DIRECTORY)],
+        &Config::default().scan_options(),
+    )?;
+
+    let noise_path = PathBuf::from(TEST_LIBRARY_DIRECTORY).join("noise.ogg");
+    let noise_song = files.iter().find(|song| song.path == noise_path).unwrap();
+
+    assert_eq!(noise_song.title, "Noise Test");
+    assert_eq!(noise_song.artist.as_deref(), Some("Noise tester"));
+    assert_eq!(noise_song.album.as_deref(), Some("The Noise"));
+    assert_eq!(noise_song.album_artist, None);
+    assert_eq!(noise_song.track_number, Some(1));
+    assert_eq!(noise_song.disc_number, None);
+    assert_eq!(noise_song.year, Some(2021));
+    assert_eq!(noise_song.genre.as_deref(), Some("Noise"));
+    assert!(noise_song.duration.unwrap() > Duration::ZERO);
+
+    Ok(())
+}
+
+#[test]
+fn test_read_tags_fails_on_files_without_audio() {
+    let empty_file = PathBuf::from(MIXED_FORMATS_DIRECTORY).join("short.mp3");
+
+    assert!(library::tags::read_tags(&empty_file).is_err());
+}
+
+#[test]
+fn test_scan_directory_finds_all_default_formats() -> Result<()> {
+    let files = library::scan_directory_for_songs(
+        &[LibraryRoot::new(MIXED_FORMATS_DIRECTORY)],
+        &Config::default().scan_options(),
+    )?;
+
+    let mut titles: Vec<&str> = files.iter().map(|song| song.title.as_str()).collect();
+    titles.sort_unstable();
+
+    // Extensions are matched case-insensitively, and non-song files are skipped.
+    // These files are empty, so the titles fall back to the file names.
+    assert_eq!(
+        titles,
+        vec!["LOUD", "apple", "lossless", "raw", "short", "voice"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_scan_directory_only_finds_given_extensions() -> Result<()> {
+    let files = library::scan_directory_for_songs(
+        &[LibraryRoot::new(MIXED_FORMATS_DIRECTORY)],
+        &ScanOptions {
+            extensions: vec!["mp3".to_string(), ".FLAC".to_string()],
+            ..Default::default()
+        },
+    )?;
+
+    let mut titles: Vec<&str> = files.iter().map(|song| song.title.as_str()).collect();
+    titles.sort_unstable();
+
+    assert_eq!(titles, vec!["LOUD", "lossless", "short"]);
+
+    Ok(())
+}
+
+/// Copies the test library into a temporary directory, so tests can modify it.
+fn copy_test_library() -> Result<TempDir> {
+    let dir = tempfile::tempdir()?;
+
+    fs::copy(
+        PathBuf::from(TEST_LIBRARY_DIRECTORY).join("noise.ogg"),
+        dir.path().join("noise.ogg"),
+    )?;
+    fs::create_dir(dir.path().join("some_folder"))?;
+    fs::copy(
+        PathBuf::from(TEST_LIBRARY_DIRECTORY)
+            .join("some_folder")
+            .join("more_noise.ogg"),
+        dir.path().join("some_folder").join("more_noise.ogg"),
+    )?;
+
+    Ok(dir)
+}
+
+fn copy_dir_recursively(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir_recursively(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Paths of the songs relative to the root, sorted, with `/` as separator.
+fn relative_song_paths<'a>(songs: impl Iterator<Item = &'a Song>, root: &Path) -> Vec<String> {
+    let mut paths: Vec<String> = songs
+        .map(|song| {
+            let relative_path = song.path.strip_prefix(root).unwrap();
+            relative_path
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/")
+        })
+        .collect();
+    paths.sort_unstable();
+    paths
+}
+
+/// Applies the events of the scan until it is finished.
+fn finish_background_scan(scan: &mut BackgroundScan, library: &mut Library) {
+    let start = Instant::now();
+    while !scan.is_finished() {
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "The background scan did not finish in time."
+        );
+        scan.apply_events(library);
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn find_song_id(library: &Library, path: &Path) -> Option<SongId> {
+    library
+        .songs()
+        .find(|(_, song)| song.path == path)
+        .map(|(&id, _)| id)
+}
+
+#[test]
+fn test_rescan_does_not_read_unchanged_files() -> Result<()> {
+    let dir = copy_test_library()?;
+    let noise_path = dir.path().join("noise.ogg");
+
+    // Pretend this song was read earlier, and its file did not change since.
+    let mut library = Library::new();
+    let id = library.add_song(Song {
+        title: "cached title".to_string(),
+        path: noise_path.clone(),
+        file_stamp: FileStamp::read(&noise_path)?,
+        ..Default::default()
+    });
+
+    let summary = library.rescan(
+        &[LibraryRoot::new(dir.path())],
+        &Config::default().scan_options(),
+    );
+
+    assert_eq!(library.song_count(), 2);
+    assert_eq!(library.get_song(&id).unwrap().title, "cached title");
+    assert_eq!(summary.unchanged, 1);
+    assert_eq!(summary.added.len(), 1);
+    assert!(summary.updated.is_empty());
+    assert!(summary.removed.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_rescan_reads_changed_files() -> Result<()> {
+    let dir = copy_test_library()?;
+    let noise_path = dir.path().join("noise.ogg");
+
+    let mut library = Library::new();
+    let id = library.add_song(Song {
+        title: "cached title".to_string(),
+        path: noise_path.clone(),
+        file_stamp: FileStamp {
+            size: 1,
+            modified: None,
+        },
+        ..Default::default()
+    });
+    library.record_play(&id);
+    let stats = library.song_stats(&id);
+
+    let summary = library.rescan(
+        &[LibraryRoot::new(dir.path())],
+        &Config::default().scan_options(),
+    );
+
+    // Changed files keep their id, and what the library knows about them.
+    assert_eq!(library.get_song(&id).unwrap().title, "Noise Test");
+    assert_eq!(summary.updated, vec![id]);
+    assert_eq!(library.song_stats(&id), stats);
+
+    Ok(())
+}
+
+#[test]
+fn test_rescan_removes_missing_files() -> Result<()> {
+    let dir = copy_test_library()?;
+    let options = Config::default().scan_options();
+
+    let mut library = Library::new();
+    library.rescan(&[LibraryRoot::new(dir.path())], &options);
+    assert_eq!(library.song_count(), 2);
+
+    let noise_path = dir.path().join("noise.ogg");
+    let more_noise_path = dir.path().join("some_folder").join("more_noise.ogg");
+    let noise_id = find_song_id(&library, &noise_path).unwrap();
+    let more_noise_id = find_song_id(&library, &more_noise_path).unwrap();
+
+    fs::remove_file(&noise_path)?;
+    let summary = library.rescan(&[LibraryRoot::new(dir.path())], &options);
+
+    assert_eq!(summary.removed, vec![noise_id]);
+    assert_eq!(summary.unchanged, 1);
+    assert_eq!(library.song_count(), 1);
+    assert_eq!(find_song_id(&library, &noise_path), None);
+    assert_eq!(
+        find_song_id(&library, &more_noise_path),
+        Some(more_noise_id)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_rescan_adds_new_files_and_keeps_existing_ids() -> Result<()> {
+    let dir = copy_test_library()?;
+    let options = Config::default().scan_options();
+
+    let mut library = Library::new();
+    let summary = library.rescan(&[LibraryRoot::new(dir.path())], &options);
+    assert_eq!(summary.added.len(), 2);
+
+    let noise_path = dir.path().join("noise.ogg");
+    let noise_id = find_song_id(&library, &noise_path).unwrap();
+
+    let copy_path = dir.path().join("some_folder").join("noise_copy.ogg");
+    fs::copy(&noise_path, &copy_path)?;
+    let summary = library.rescan(&[LibraryRoot::new(dir.path())], &options);
+
+    assert!(summary.has_changes());
+    assert_eq!(
+        summary.added,
+        vec![find_song_id(&library, &copy_path).unwrap()]
+    );
+    assert_eq!(summary.unchanged, 2);
+    assert_eq!(find_song_id(&library, &noise_path), Some(noise_id));
+
+    // Nothing changed, so nothing is reported.
+    let summary = library.rescan(&[LibraryRoot::new(dir.path())], &options);
+    assert!(!summary.has_changes());
+    assert_eq!(summary.unchanged, 3);
+
+    Ok(())
+}
+
+#[test]
+fn test_library_cache_round_trip() -> Result<()> {
+    let cache_dir = tempfile::tempdir()?;
+    let cache_path = cache_dir.path().join("nested").join("library_cache.bin");
+
+    let mut library = Library::new();
+    library.rescan(
+        &[LibraryRoot::new(TEST_LIBRARY_DIRECTORY)],
+        &Config::default().scan_options(),
+    );
+
+    let played_id = *library.songs().next().unwrap().0;
+    library.record_play(&played_id);
+
+    cache::save_library_cache(&library, &cache_path)?;
+    let loaded = cache::load_library_cache(&cache_path)?.unwrap();
+
+    assert_eq!(loaded.song_count(), library.song_count());
+    for (id, song) in library.songs() {
+        assert_eq!(loaded.get_song(id), Some(song));
+        assert_eq!(loaded.song_stats(id), library.song_stats(id));
+    }
+
+    // The search index is not stored, so it has to be rebuilt when loading.
+    let query = Query::parse("noise").unwrap();
+    assert_eq!(library.search(&query).len(), 2);
+    assert_eq!(loaded.search(&query), library.search(&query));
+
+    Ok(())
+}
+
+#[test]
+fn test_library_cache_missing_file_is_none() -> Result<()> {
+    let cache_dir = tempfile::tempdir()?;
+
+    assert!(cache::load_library_cache(&cache_dir.path().join("nothing.bin"))?.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_library_cache_discards_other_versions() -> Result<()> {
+    let cache_dir = tempfile::tempdir()?;
+    let cache_path = cache_dir.path().join("library_cache.bin");
+
+    // Correct magic bytes, followed by a version that does not exist.
+    let mut contents = b"SMPL".to_vec();
+    contents.extend_from_slice(&u32::MAX.to_le_bytes());
+    contents.extend_from_slice(b"some data in an unknown layout");
+    fs::write(&cache_path, contents)?;
+
+    assert!(cache::load_library_cache(&cache_path)?.is_none());
+
+    // Files that are not a cache at all are discarded as well.
+    fs::write(&cache_path, b"not a cache")?;
+    assert!(cache::load_library_cache(&cache_path)?.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_update_paths_handles_added_renamed_and_removed_files() -> Result<()> {
+    let dir = copy_test_library()?;
+    let options = Config::default().scan_options();
+
+    let mut library = Library::new();
+    library.rescan(&[LibraryRoot::new(dir.path())], &options);
+
+    let noise_path = dir.path().join("noise.ogg");
+    let renamed_path = dir.path().join("renamed.ogg");
+    let noise_id = find_song_id(&library, &noise_path).unwrap();
+
+    fs::rename(&noise_path, &renamed_path)?;
+    let summary = library.update_paths(
+        &[noise_path.clone(), renamed_path.clone()],
+        &[LibraryRoot::new(dir.path())],
+        &options,
+    );
+
+    assert_eq!(summary.removed, vec![noise_id]);
+    assert_eq!(summary.added.len(), 1);
+    assert_eq!(find_song_id(&library, &noise_path), None);
+    assert!(find_song_id(&library, &renamed_path).is_some());
+    assert_eq!(library.song_count(), 2);
+
+    let all_songs = library.search(&Query::All);
+    assert_eq!(all_songs.len(), 2);
+    assert!(!all_songs.contains(&noise_id));
+
+    Ok(())
+}
+
+#[test]
+fn test_update_paths_handles_directories() -> Result<()> {
+    let dir = copy_test_library()?;
+    let options = Config::default().scan_options();
+
+    let mut library = Library::new();
+    library.rescan(&[LibraryRoot::new(dir.path())], &options);
+
+    // A new directory with songs in it, like when copying an album.
+    let album_dir = dir.path().join("album");
+    fs::create_dir(&album_dir)?;
+    fs::copy(dir.path().join("noise.ogg"), album_dir.join("first.ogg"))?;
+    fs::copy(dir.path().join("noise.ogg"), album_dir.join("second.ogg"))?;
+
+    let summary = library.update_paths(
+        std::slice::from_ref(&album_dir),
+        &[LibraryRoot::new(dir.path())],
+        &options,
+    );
+    assert_eq!(summary.added.len(), 2);
+    assert_eq!(library.song_count(), 4);
+
+    // Removing the directory removes all songs in it.
+    fs::remove_dir_all(&album_dir)?;
+    let summary = library.update_paths(&[album_dir], &[LibraryRoot::new(dir.path())], &options);
+    assert_eq!(summary.removed.len(), 2);
+    assert_eq!(library.song_count(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_update_paths_ignores_other_files() -> Result<()> {
+    let dir = copy_test_library()?;
+    let options = Config::default().scan_options();
+
+    let mut library = Library::new();
+    library.rescan(&[LibraryRoot::new(dir.path())], &options);
+
+    let text_path = dir.path().join("notes.txt");
+    fs::write(&text_path, "not a song")?;
+
+    let summary = library.update_paths(&[text_path], &[LibraryRoot::new(dir.path())], &options);
+    assert!(!summary.has_changes());
+    assert_eq!(library.song_count(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_library_watcher_applies_changes() -> Result<()> {
+    let dir = copy_test_library()?;
+    let options = Config::default().scan_options();
+
+    let mut library = Library::new();
+    library.rescan(&[LibraryRoot::new(dir.path())], &options);
+
+    let wake_ups = Arc::new(AtomicUsize::new(0));
+    let counter = wake_ups.clone();
+    let mut watcher =
+        LibraryWatcher::new(vec![LibraryRoot::new(dir.path())], options, move || {
+            counter.fetch_add(1, Ordering::Relaxed);
+        })?;
+
+    let new_path = dir.path().join("some_folder").join("new.ogg");
+    fs::copy(dir.path().join("noise.ogg"), &new_path)?;
+    fs::remove_file(dir.path().join("noise.ogg"))?;
+
+    // Events arrive asynchronously, and are only applied once things are quiet.
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let mut added = Vec::new();
+    let mut removed = Vec::new();
+    while Instant::now() < deadline && (added.is_empty() || removed.is_empty()) {
+        if let Some(summary) = watcher.apply_changes(&mut library) {
+            added.extend(summary.added);
+            removed.extend(summary.removed);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    assert_eq!(added.len(), 1);
+    assert_eq!(removed.len(), 1);
+    assert!(find_song_id(&library, &new_path).is_some());
+    assert_eq!(library.song_count(), 2);
+    // The burst of events only wakes up the UI once.
+    assert_eq!(wake_ups.load(Ordering::Relaxed), 1);
+
+    // Reading a song doesn't change the library.
+    fs::read(&new_path)?;
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(wake_ups.load(Ordering::Relaxed), 1);
+    assert!(watcher.apply_changes(&mut library).is_none());
+    assert!(!watcher.has_pending_changes());
+
+    Ok(())
+}
+
+#[test]
+fn test_song_ids_are_stable_between_libraries() -> Result<()> {
+    let options = Config::default().scan_options();
+
+    // The same files in two different locations, scanned by two different libraries.
+    let first_dir = copy_test_library()?;
+    let second_dir = copy_test_library()?;
+
+    let mut first_library = Library::new();
+    first_library.rescan(&[LibraryRoot::new(first_dir.path())], &options);
+    let mut second_library = Library::new();
+    second_library.rescan(&[LibraryRoot::new(second_dir.path())], &options);
+
+    let relative_path = Path::new("some_folder").join("more_noise.ogg");
+    let first_id = find_song_id(&first_library, &first_dir.path().join(&relative_path));
+    let second_id = find_song_id(&second_library, &second_dir.path().join(&relative_path));
+
+    assert!(first_id.is_some());
+    assert_eq!(first_id, second_id);
+    assert_eq!(first_id, Some(SongId::from_path(&relative_path)));
+
+    Ok(())
+}
+
+#[test]
+fn test_rescan_recognizes_moved_songs_by_content() -> Result<()> {
+    let dir = copy_test_library()?;
+    let options = ScanOptions {
+        hash_contents: true,
+        ..Config::default().scan_options()
+    };
+
+    let mut library = Library::new();
+    library.rescan(&[LibraryRoot::new(dir.path())], &options);
+
+    let noise_path = dir.path().join("noise.ogg");
+    let moved_path = dir.path().join("some_folder").join("moved_noise.ogg");
+    let noise_id = find_song_id(&library, &noise_path).unwrap();
+    library.record_play(&noise_id);
+    let stats = library.song_stats(&noise_id);
+
+    fs::rename(&noise_path, &moved_path)?;
+    let summary = library.rescan(&[LibraryRoot::new(dir.path())], &options);
+
+    // The song gets the id of its new path, and keeps its stats.
+    let moved_id = SongId::from_path(Path::new("some_folder/moved_noise.ogg"));
+    assert_eq!(summary.moved, vec![(noise_id, moved_id)]);
+    assert_eq!(summary.current_id(noise_id), moved_id);
+    assert_eq!(library.song_stats(&moved_id), stats);
+    assert!(summary.added.is_empty());
+    assert!(summary.removed.is_empty());
+    assert_eq!(find_song_id(&library, &moved_path), Some(moved_id));
+    assert!(library.get_song(&noise_id).is_none());
+    assert_eq!(library.song_count(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_rescan_gives_a_file_at_the_path_of_a_moved_song_the_path_id() -> Result<()> {
+    let dir = copy_test_library()?;
+    let options = ScanOptions {
+        hash_contents: true,
+        ..Config::default().scan_options()
+    };
+
+    let mut library = Library::new();
+    library.rescan(&[LibraryRoot::new(dir.path())], &options);
+
+    let noise_path = dir.path().join("noise.ogg");
+    let moved_path = dir.path().join("moved_noise.ogg");
+    let more_noise_path = dir.path().join("some_folder").join("more_noise.ogg");
+    let noise_id = find_song_id(&library, &noise_path).unwrap();
+
+    let mut playlist = Playlist::new();
+    playlist.add_song(noise_id);
+
+    fs::rename(&noise_path, &moved_path)?;
+    let summary = library.rescan(&[LibraryRoot::new(dir.path())], &options);
+    playlist.replace_song_ids(|id| summary.current_id(id));
+
+    // Another file takes the place of the moved one.
+    fs::copy(&more_noise_path, &noise_path)?;
+    let summary = library.rescan(&[LibraryRoot::new(dir.path())], &options);
+    playlist.replace_song_ids(|id| summary.current_id(id));
+
+    assert_eq!(summary.added, vec![noise_id]);
+    assert_eq!(find_song_id(&library, &noise_path), Some(noise_id));
+    let moved_id = find_song_id(&library, &moved_path).unwrap();
+    assert_eq!(moved_id, SongId::from_path(Path::new("moved_noise.ogg")));
+
+    // The playlist still refers to the song that was moved, not to the new file.
+    assert_eq!(playlist.get_song_ids(), vec![moved_id]);
+
+    Ok(())
+}
+
+#[test]
+fn test_rescan_without_content_hash_sees_moves_as_new_songs() -> Result<()> {
+    let dir = copy_test_library()?;
+    let options = Config::default().scan_options();
+
+    let mut library = Library::new();
+    library.rescan(&[LibraryRoot::new(dir.path())], &options);
+
+    let noise_path = dir.path().join("noise.ogg");
+    let moved_path = dir.path().join("some_folder").join("moved_noise.ogg");
+    let noise_id = find_song_id(&library, &noise_path).unwrap();
+
+    fs::rename(&noise_path, &moved_path)?;
+    let summary = library.rescan(&[LibraryRoot::new(dir.path())], &options);
+
+    assert!(summary.moved.is_empty());
+    assert_eq!(summary.removed, vec![noise_id]);
+    assert_eq!(summary.added.len(), 1);
+    assert_ne!(find_song_id(&library, &moved_path), Some(noise_id));
+
+    Ok(())
+}
+
+#[test]
+fn test_scan_directory_merges_roots() -> Result<()> {
+    let roots = [
+        LibraryRoot::new(TEST_LIBRARY_DIRECTORY),
+        LibraryRoot::new(MIXED_FORMATS_DIRECTORY),
+    ];
+    let files = library::scan_directory_for_songs(&roots, &Config::default().scan_options())?;
+
+    assert_eq!(files.len(), 8);
+
+    let noise_path = PathBuf::from(TEST_LIBRARY_DIRECTORY).join("noise.ogg");
+    let noise_song = files.iter().find(|song| song.path == noise_path).unwrap();
+    assert_eq!(noise_song.root, PathBuf::from(TEST_LIBRARY_DIRECTORY));
+
+    let wav_path = PathBuf::from(MIXED_FORMATS_DIRECTORY)
+        .join("nested")
+        .join("raw.Wav");
+    let wav_song = files.iter().find(|song| song.path == wav_path).unwrap();
+    assert_eq!(wav_song.root, PathBuf::from(MIXED_FORMATS_DIRECTORY));
+
+    Ok(())
+}
+
+#[test]
+fn test_scan_directory_skips_disabled_roots() -> Result<()> {
+    let roots = [
+        LibraryRoot::new(TEST_LIBRARY_DIRECTORY),
+        LibraryRoot {
+            enabled: false,
+            ..LibraryRoot::new(MIXED_FORMATS_DIRECTORY)
+        },
+    ];
+    let files = library::scan_directory_for_songs(&roots, &Config::default().scan_options())?;
+
+    assert_eq!(files.len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_scan_directory_applies_include_and_exclude_patterns() -> Result<()> {
+    let roots = [LibraryRoot {
+        include: vec![
+            "*.mp3".to_string(),
+            "*.MP3".to_string(),
+            "nested/**".to_string(),
+        ],
+        exclude: vec!["LOUD.*".to_string()],
+        ..LibraryRoot::new(MIXED_FORMATS_DIRECTORY)
+    }];
+    let files = library::scan_directory_for_songs(&roots, &Config::default().scan_options())?;
+
+    let mut titles: Vec<&str> = files.iter().map(|song| song.title.as_str()).collect();
+    titles.sort_unstable();
+
+    assert_eq!(titles, vec!["raw", "short"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_rescan_keeps_songs_of_unavailable_roots() -> Result<()> {
+    let dir = copy_test_library()?;
+    let options = Config::default().scan_options();
+
+    // Like an external drive that is not connected.
+    let missing_root = dir.path().join("not_here");
+    let roots = [
+        LibraryRoot::new(dir.path().join("some_folder")),
+        LibraryRoot::new(&missing_root),
+    ];
+    let mut library = Library::new();
+    library.add_song(Song {
+        path: missing_root.join("song.ogg"),
+        root: missing_root.clone(),
+        ..Default::default()
+    });
+
+    let summary = library.rescan(&roots, &options);
+
+    assert_eq!(summary.warnings.len(), 1);
+    assert!(summary.removed.is_empty());
+    assert_eq!(summary.added.len(), 1);
+    assert_eq!(library.song_count(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_rescan_removes_songs_of_disabled_roots() -> Result<()> {
+    let dir = copy_test_library()?;
+    let options = Config::default().scan_options();
+
+    let mut roots = vec![LibraryRoot::new(dir.path())];
+
+    let mut library = Library::new();
+    library.rescan(&roots, &options);
+    assert_eq!(library.song_count(), 2);
+
+    roots[0].enabled = false;
+    let summary = library.rescan(&roots, &options);
+
+    assert_eq!(summary.removed.len(), 2);
+    assert_eq!(library.song_count(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_config_migrates_library_directory_to_root() -> Result<()> {
+    let mut config: Config = toml::from_str("library_directory = 'some/music'")?;
+    config.migrate();
+
+    assert_eq!(config.library_roots, vec![LibraryRoot::new("some/music")]);
+
+    // Migrating again does not add another root.
+    config.migrate();
+    assert_eq!(config.library_roots.len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_scan_directory_skips_ignored_subtrees() -> Result<()> {
+    let files = library::scan_directory_for_songs(
+        &[LibraryRoot::new(IGNORE_RULES_DIRECTORY)],
+        &Config::default().scan_options(),
+    )?;
+
+    // Not returned:
+    // - demos/ and *.wav are listed in the .smpignore of the root.
+    // - live/bootlegs/ is listed in the .smpignore of live/, which includes *.wav again.
+    // - podcasts/ contains a .nomedia file.
+    // - .hidden/ is a hidden directory.
+    assert_eq!(
+        relative_song_paths(files.iter(), Path::new(IGNORE_RULES_DIRECTORY)),
+        vec!["keep.mp3", "live/concert.wav"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_scan_directory_can_include_hidden_directories() -> Result<()> {
+    let options = ScanOptions {
+        scan_hidden_directories: true,
+        ..Config::default().scan_options()
     };
-    assert!(files.contains(&noise_song));
+    let files =
+        library::scan_directory_for_songs(&[LibraryRoot::new(IGNORE_RULES_DIRECTORY)], &options)?;
+
+    assert_eq!(
+        relative_song_paths(files.iter(), Path::new(IGNORE_RULES_DIRECTORY)),
+        vec![".hidden/secret.mp3", "keep.mp3", "live/concert.wav"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_update_paths_skips_ignored_subtrees() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    copy_dir_recursively(Path::new(IGNORE_RULES_DIRECTORY), dir.path())?;
+    let roots = [LibraryRoot::new(dir.path())];
+    let options = Config::default().scan_options();
 
-    let more_noise_song = Song {
-        title: "more_noise".to_string(),
-        path: PathBuf::from(TEST_LIBRARY_DIRECTORY)
+    let mut library = Library::new();
+    library.rescan(&roots, &options);
+
+    // Reported changes inside ignored directories do not add their songs.
+    let summary = library.update_paths(
+        &[
+            dir.path().join("demos").join("demo.mp3"),
+            dir.path().join("podcasts"),
+            dir.path().join("live").join("bootlegs").join("rare.mp3"),
+            dir.path().join(".hidden").join("secret.mp3"),
+        ],
+        &roots,
+        &options,
+    );
+    assert!(!summary.has_changes());
+
+    // Changing an ignore file applies to the songs that are already in the library.
+    fs::write(dir.path().join("live").join(".smpignore"), "*")?;
+    let summary = library.update_paths(
+        &[dir.path().join("live").join(".smpignore")],
+        &roots,
+        &options,
+    );
+    assert_eq!(summary.removed.len(), 1);
+    assert_eq!(
+        relative_song_paths(library.songs().map(|(_, song)| song), dir.path()),
+        vec!["keep.mp3"]
+    );
+
+    Ok(())
+}
+
+/// A root containing `album/noise.ogg`, and symbolic links to it in several ways:
+/// `album_link` -> `album`, `album/loop` -> the root, and `song_link.ogg` -> `album/noise.ogg`.
+/// `external` links to `more_noise.ogg` in a directory outside of the root.
+#[cfg(unix)]
+fn create_symlinked_library() -> Result<(TempDir, TempDir)> {
+    use std::os::unix::fs::symlink;
+
+    let dir = tempfile::tempdir()?;
+    let album = dir.path().join("album");
+    fs::create_dir(&album)?;
+    fs::copy(
+        PathBuf::from(TEST_LIBRARY_DIRECTORY).join("noise.ogg"),
+        album.join("noise.ogg"),
+    )?;
+    symlink(&album, dir.path().join("album_link"))?;
+    symlink(dir.path(), album.join("loop"))?;
+    symlink(album.join("noise.ogg"), dir.path().join("song_link.ogg"))?;
+
+    let external_dir = tempfile::tempdir()?;
+    fs::copy(
+        PathBuf::from(TEST_LIBRARY_DIRECTORY)
             .join("some_folder")
             .join("more_noise.ogg"),
+        external_dir.path().join("more_noise.ogg"),
+    )?;
+    symlink(external_dir.path(), dir.path().join("external"))?;
+
+    Ok((dir, external_dir))
+}
+
+#[cfg(unix)]
+#[test]
+fn test_scan_directory_skips_symlinks_by_default() -> Result<()> {
+    let (dir, _external_dir) = create_symlinked_library()?;
+
+    let files = library::scan_directory_for_songs(
+        &[LibraryRoot::new(dir.path())],
+        &Config::default().scan_options(),
+    )?;
+
+    assert_eq!(
+        relative_song_paths(files.iter(), dir.path()),
+        vec!["album/noise.ogg"]
+    );
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_scan_directory_follows_symlinks_without_loops_or_duplicates() -> Result<()> {
+    let (dir, _external_dir) = create_symlinked_library()?;
+    let options = ScanOptions {
+        follow_symlinks: true,
+        ..Config::default().scan_options()
     };
-    assert!(files.contains(&more_noise_song));
+
+    let files = library::scan_directory_for_songs(&[LibraryRoot::new(dir.path())], &options)?;
+
+    // The song inside the root is found once, through the path without links.
+    assert_eq!(
+        relative_song_paths(files.iter(), dir.path()),
+        vec!["album/noise.ogg", "external/more_noise.ogg"]
+    );
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_rescan_keeps_songs_of_unreadable_directories() -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = copy_test_library()?;
+    let roots = [LibraryRoot::new(dir.path())];
+    let options = Config::default().scan_options();
+
+    let mut library = Library::new();
+    library.rescan(&roots, &options);
+    assert_eq!(library.song_count(), 2);
+
+    let folder = dir.path().join("some_folder");
+    fs::set_permissions(&folder, fs::Permissions::from_mode(0o000))?;
+    if fs::read_dir(&folder).is_ok() {
+        fs::set_permissions(&folder, fs::Permissions::from_mode(0o755))?;
+        eprintln!(
+            "Skipping test_rescan_keeps_songs_of_unreadable_directories: \
+             the directory is still readable, permissions don't apply to this user (like root)."
+        );
+        return Ok(());
+    }
+
+    let summary = library.rescan(&roots, &options);
+    fs::set_permissions(&folder, fs::Permissions::from_mode(0o755))?;
+
+    assert_eq!(summary.warnings.len(), 1);
+    assert!(summary.warnings[0].contains("some_folder"));
+    assert!(summary.removed.is_empty());
+    assert_eq!(library.song_count(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_background_scan_adds_songs() -> Result<()> {
+    let dir = copy_test_library()?;
+    let mut library = Library::new();
+
+    let mut scan = BackgroundScan::start(
+        &library,
+        vec![LibraryRoot::new(dir.path())],
+        Config::default().scan_options(),
+        || {},
+    );
+    finish_background_scan(&mut scan, &mut library);
+
+    assert_eq!(library.song_count(), 2);
+    assert_eq!(scan.summary().added.len(), 2);
+
+    let progress = scan.progress();
+    assert!(progress.all_files_found);
+    assert_eq!(progress.files_found, 2);
+    assert_eq!(progress.files_processed, 2);
+    assert_eq!(progress.errors, 0);
+
+    // The same ids as a normal rescan would give.
+    let mut rescanned_library = Library::new();
+    rescanned_library.rescan(
+        &[LibraryRoot::new(dir.path())],
+        &Config::default().scan_options(),
+    );
+    let ids: HashSet<SongId> = library.songs().map(|(&id, _)| id).collect();
+    let rescanned_ids: HashSet<SongId> = rescanned_library.songs().map(|(&id, _)| id).collect();
+    assert_eq!(ids, rescanned_ids);
+
+    Ok(())
+}
+
+#[test]
+fn test_background_scan_removes_songs_and_skips_unchanged_files() -> Result<()> {
+    let dir = copy_test_library()?;
+    let roots = vec![LibraryRoot::new(dir.path())];
+    let options = Config::default().scan_options();
+
+    let mut library = Library::new();
+    library.rescan(&roots, &options);
+    let noise_path = dir.path().join("noise.ogg");
+    let noise_id = find_song_id(&library, &noise_path).unwrap();
+
+    fs::remove_file(dir.path().join("some_folder").join("more_noise.ogg"))?;
+
+    let mut scan = BackgroundScan::start(&library, roots, options, || {});
+    finish_background_scan(&mut scan, &mut library);
+
+    let summary = scan.summary();
+    assert_eq!(summary.removed.len(), 1);
+    assert_eq!(summary.unchanged, 1);
+    assert!(summary.added.is_empty());
+    assert_eq!(library.song_count(), 1);
+    assert_eq!(find_song_id(&library, &noise_path), Some(noise_id));
+
+    Ok(())
+}
+
+#[test]
+fn test_cancelled_background_scan_keeps_songs() -> Result<()> {
+    let dir = copy_test_library()?;
+    let roots = vec![LibraryRoot::new(dir.path())];
+    let options = Config::default().scan_options();
+
+    let mut library = Library::new();
+    library.rescan(&roots, &options);
+
+    fs::remove_file(dir.path().join("noise.ogg"))?;
+
+    let mut scan = BackgroundScan::start(&library, roots, options, || {});
+    scan.cancel();
+    finish_background_scan(&mut scan, &mut library);
+
+    assert!(scan.is_cancelled());
+    assert!(scan.summary().removed.is_empty());
+    assert_eq!(library.song_count(), 2);
+
+    Ok(())
+}
+
+/// A playlist with every song of the library twice, in a fixed order.
+fn playlist_of_library(library: &Library) -> Playlist {
+    let mut song_ids: Vec<SongId> = library.songs().map(|(&id, _)| id).collect();
+    song_ids.sort_unstable();
+
+    let mut playlist = Playlist::new();
+    playlist.add_songs(song_ids.clone());
+    playlist.add_songs(song_ids);
+    playlist
+}
+
+#[test]
+fn test_playlist_file_round_trip() -> Result<()> {
+    let dir = copy_test_library()?;
+    let mut library = Library::new();
+    library.rescan(
+        &[LibraryRoot::new(dir.path())],
+        &Config::default().scan_options(),
+    );
+    let playlist = playlist_of_library(&library);
+
+    for (file_name, path_style) in [
+        ("relative.m3u8", PathStyle::Relative),
+        ("absolute.m3u8", PathStyle::Absolute),
+        ("playlists/nested.m3u", PathStyle::Relative),
+        ("relative.xspf", PathStyle::Relative),
+        ("absolute.xspf", PathStyle::Absolute),
+        ("playlists/nested.pls", PathStyle::Relative),
+        ("absolute.pls", PathStyle::Absolute),
+    ] {
+        let path = dir.path().join(file_name);
+        fs::create_dir_all(path.parent().unwrap())?;
+
+        playlist_file::save_playlist(&playlist, &library, &path, path_style)?;
+        let loaded = playlist_file::load_playlist(&path, &library)?;
+
+        assert_eq!(loaded.songs, playlist.get_song_ids(), "{}", file_name);
+        assert!(loaded.missing.is_empty());
+    }
+
+    let relative = fs::read_to_string(dir.path().join("relative.m3u8"))?;
+    assert!(relative.starts_with("#EXTM3U\n#EXTINF:"));
+    assert!(relative.contains("\nnoise.ogg\n"));
+    let nested = fs::read_to_string(dir.path().join("playlists").join("nested.m3u"))?;
+    assert!(nested.contains(&format!(
+        "\n{}\n",
+        Path::new("..").join("noise.ogg").display()
+    )));
+
+    let xspf = fs::read_to_string(dir.path().join("relative.xspf"))?;
+    assert!(xspf.contains("<location>noise.ogg</location>"));
+    assert!(xspf.contains("<title>Noise Test</title>"));
+    assert!(xspf.contains("<creator>Noise tester</creator>"));
+
+    Ok(())
+}
+
+#[test]
+fn test_m3u_playlist_from_another_directory_finds_songs() -> Result<()> {
+    let mut library = Library::new();
+    library.rescan(
+        &[LibraryRoot::new(TEST_LIBRARY_DIRECTORY)],
+        &Config::default().scan_options(),
+    );
+    let playlist = playlist_of_library(&library);
+
+    // The library root is relative to the working directory, the playlist is somewhere else.
+    let playlist_dir = tempfile::tempdir()?;
+    let path = playlist_dir.path().join("playlist.m3u8");
+
+    playlist_file::save_playlist(&playlist, &library, &path, PathStyle::Relative)?;
+    let loaded = playlist_file::load_playlist(&path, &library)?;
+
+    assert_eq!(loaded.songs, playlist.get_song_ids());
+
+    Ok(())
+}
+
+#[test]
+fn test_playlist_file_reports_songs_not_in_library() -> Result<()> {
+    let dir = copy_test_library()?;
+    let mut library = Library::new();
+    library.rescan(
+        &[LibraryRoot::new(dir.path())],
+        &Config::default().scan_options(),
+    );
+    let noise_id = find_song_id(&library, &dir.path().join("noise.ogg")).unwrap();
+
+    let path = dir.path().join("playlist.m3u");
+    fs::write(
+        &path,
+        "#EXTM3U\n\
+         #EXTINF:3,Unknown - Gone\n\
+         gone.ogg\n\
+         ./some_folder/../noise.ogg\n",
+    )?;
+
+    let loaded = playlist_file::load_playlist(&path, &library)?;
+
+    assert_eq!(loaded.songs, vec![noise_id]);
+    assert_eq!(loaded.missing.len(), 1);
+    assert_eq!(loaded.missing[0].path, PathBuf::from("gone.ogg"));
+    assert_eq!(loaded.missing[0].title.as_deref(), Some("Unknown - Gone"));
+
+    Ok(())
+}
+
+#[test]
+fn test_malformed_playlist_files_fail() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let library = Library::new();
+
+    let xspf_path = dir.path().join("broken.xspf");
+    fs::write(
+        &xspf_path,
+        "<playlist>\n<trackList>\n<track>\n</playlist>\n",
+    )?;
+    let error = playlist_file::load_playlist(&xspf_path, &library).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Invalid XML: expected </track>, but found </playlist> (on line 4)"
+    );
+
+    let pls_path = dir.path().join("broken.pls");
+    fs::write(&pls_path, "File1=song.mp3\n")?;
+    let error = playlist_file::load_playlist(&pls_path, &library).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "The file is not a PLS playlist (on line 1)"
+    );
 
     Ok(())
 }